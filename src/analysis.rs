@@ -0,0 +1,444 @@
+//! Chord recognition for whole recordings.
+//!
+//! The recording is reduced to a chromagram (energy per pitch class over
+//! time), which is then cut into beat or bar sized segments and each segment
+//! is matched against a small set of chord templates.
+//!
+//! A long recording takes a while to decode and analyse, so it's done a
+//! step at a time by an [`Analyser`], on another thread or between frames.
+
+use crate::locale::tr;
+use crate::theory::PITCH_NAMES;
+use std::io::Cursor;
+
+/// Chord templates as (suffix, intervals in semitones from the root, weight).
+/// Four note templates are weighted down a little so that plain triads don't
+/// get labelled as sevenths just because a passing note is present.
+const TEMPLATES: [(&str, &[usize], f32); 5] = [
+    ("", &[0, 4, 7], 1.0),
+    ("m", &[0, 3, 7], 1.0),
+    ("7", &[0, 4, 7, 10], 0.95),
+    ("m7", &[0, 3, 7, 10], 0.95),
+    ("maj7", &[0, 4, 7, 11], 0.95),
+];
+
+/// Sample rate we downsample to before analysis; plenty for the note range
+/// below and it keeps the analysis fast.
+const ANALYSIS_RATE: u32 = 11025;
+/// Samples decoded in one step, across all channels.
+const DECODE_STEP: usize = 1 << 18;
+/// Chroma frames worked out in one step, a few milliseconds' work.
+const CHROMA_STEP: usize = 8;
+/// Chroma frame length in samples at the analysis rate (~0.37s).
+const FRAME_LEN: usize = 4096;
+/// Onset detection hop in samples at the analysis rate (~46ms).
+const ONSET_HOP: usize = 512;
+/// MIDI note range used for the chromagram (C3 to B6).
+const LOWEST_NOTE: u8 = 48;
+const HIGHEST_NOTE: u8 = 95;
+/// Segments quieter than this (relative to the loudest one) are left unlabelled.
+const SILENCE_THRESHOLD: f32 = 0.05;
+
+/// A mono recording.
+pub(crate) struct Recording {
+    pub(crate) sample_rate: u32,
+    pub(crate) samples: Vec<f32>,
+}
+
+impl Recording {
+    pub(crate) fn duration(&self) -> f32 {
+        self.samples.len() as f32 / self.sample_rate as f32
+    }
+
+    /// Average blocks of samples together to get close to `ANALYSIS_RATE`.
+    fn downsampled(&self) -> Recording {
+        let factor = (self.sample_rate / ANALYSIS_RATE).max(1) as usize;
+        Recording {
+            sample_rate: self.sample_rate / factor as u32,
+            samples: self
+                .samples
+                .chunks(factor)
+                .map(|c| c.iter().sum::<f32>() / c.len() as f32)
+                .collect(),
+        }
+    }
+}
+
+/// How far an [`Analyser`] has got.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Progress {
+    Decoding,
+    /// With the fraction of the chromagram done
+    Analysing(f32),
+}
+
+enum Stage {
+    Decoding {
+        decoder: rodio::Decoder<Cursor<Vec<u8>>>,
+        channels: usize,
+        sample_rate: u32,
+        samples: Vec<f32>,
+    },
+    Analysing {
+        recording: Recording,
+        downsampled: Recording,
+        window: Vec<f32>,
+        // The pitch class and Goertzel coefficient of each note
+        coefficients: Vec<(usize, f32)>,
+        chroma: Vec<[f32; 12]>,
+    },
+}
+
+/// Decodes and analyses a recording a step at a time.
+pub(crate) struct Analyser {
+    stage: Option<Stage>,
+}
+
+impl Analyser {
+    /// Start on any format rodio understands (WAV, FLAC, Ogg Vorbis), which
+    /// is mixed down to mono.
+    pub(crate) fn new(bytes: Vec<u8>) -> Result<Self, String> {
+        use rodio::Source;
+
+        let decoder = rodio::Decoder::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+        let channels = decoder.channels().max(1) as usize;
+        let sample_rate = decoder.sample_rate();
+        Ok(Self {
+            stage: Some(Stage::Decoding {
+                decoder,
+                channels,
+                sample_rate,
+                samples: Vec::new(),
+            }),
+        })
+    }
+
+    /// Start on a recording that's already decoded.
+    fn from_recording(recording: Recording) -> Self {
+        let downsampled = recording.downsampled();
+        let rate = downsampled.sample_rate as f32;
+        let window = (0..FRAME_LEN)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FRAME_LEN as f32).cos())
+            .collect();
+        let coefficients = (LOWEST_NOTE..=HIGHEST_NOTE)
+            .map(|midi| {
+                let freq = 440.0 * 2f32.powf((midi as f32 - 69.0) / 12.0);
+                let omega = 2.0 * std::f32::consts::PI * freq / rate;
+                (midi as usize % 12, 2.0 * omega.cos())
+            })
+            .collect();
+        Self {
+            stage: Some(Stage::Analysing {
+                recording,
+                downsampled,
+                window,
+                coefficients,
+                chroma: Vec::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn progress(&self) -> Progress {
+        match &self.stage {
+            Some(Stage::Analysing {
+                downsampled,
+                chroma,
+                ..
+            }) => {
+                let frames = (downsampled.samples.len() + FRAME_LEN - 1) / FRAME_LEN;
+                Progress::Analysing(chroma.len() as f32 / frames.max(1) as f32)
+            }
+            _ => Progress::Decoding,
+        }
+    }
+
+    /// Do the next step of the work, returning the recording and its
+    /// analysis once it's all done.
+    pub(crate) fn step(&mut self) -> Result<Option<(Recording, Analysis)>, String> {
+        match self.stage.take() {
+            Some(Stage::Decoding {
+                mut decoder,
+                channels,
+                sample_rate,
+                mut samples,
+            }) => {
+                let interleaved: Vec<i16> = decoder.by_ref().take(DECODE_STEP).collect();
+                samples.extend(interleaved.chunks(channels).map(|frame| {
+                    frame.iter().map(|s| *s as f32 / 32768.0).sum::<f32>() / channels as f32
+                }));
+                if interleaved.len() == DECODE_STEP {
+                    self.stage = Some(Stage::Decoding {
+                        decoder,
+                        channels,
+                        sample_rate,
+                        samples,
+                    });
+                } else if samples.is_empty() {
+                    return Err(tr("recording is empty").to_owned());
+                } else {
+                    *self = Self::from_recording(Recording {
+                        sample_rate,
+                        samples,
+                    });
+                }
+                Ok(None)
+            }
+            Some(Stage::Analysing {
+                recording,
+                downsampled,
+                window,
+                coefficients,
+                mut chroma,
+            }) => {
+                let mut windowed = vec![0.0; FRAME_LEN];
+                let frames = downsampled.samples.chunks(FRAME_LEN).skip(chroma.len());
+                for frame in frames.take(CHROMA_STEP) {
+                    for (i, w) in windowed.iter_mut().enumerate() {
+                        *w = frame.get(i).copied().unwrap_or(0.0) * window[i];
+                    }
+                    let mut bins = [0.0; 12];
+                    for (pitch_class, coeff) in &coefficients {
+                        bins[*pitch_class] += goertzel_power(&windowed, *coeff).sqrt();
+                    }
+                    chroma.push(bins);
+                }
+                if chroma.len() * FRAME_LEN < downsampled.samples.len() {
+                    self.stage = Some(Stage::Analysing {
+                        recording,
+                        downsampled,
+                        window,
+                        coefficients,
+                        chroma,
+                    });
+                    return Ok(None);
+                }
+                let analysis = Analysis::new(&downsampled, chroma);
+                Ok(Some((recording, analysis)))
+            }
+            None => Err("the recording was already analysed".to_owned()),
+        }
+    }
+}
+
+/// Everything about a recording that doesn't depend on how it's segmented,
+/// so that re-segmenting (e.g. switching between beats and bars) is cheap
+/// enough to do straight away.
+pub(crate) struct Analysis {
+    frame_duration: f32,
+    chroma: Vec<[f32; 12]>,
+    loudest_frame: f32,
+    tempo: Option<(f32, f32)>,
+}
+
+impl Analysis {
+    fn new(downsampled: &Recording, chroma: Vec<[f32; 12]>) -> Self {
+        let rate = downsampled.sample_rate as f32;
+
+        // Onset strength: positive changes in log energy between hops.
+        let energies: Vec<f32> = downsampled
+            .samples
+            .chunks(ONSET_HOP)
+            .map(|c| (c.iter().map(|s| s * s).sum::<f32>() / c.len() as f32 + 1e-9).ln())
+            .collect();
+        let onsets: Vec<f32> = std::iter::once(0.0)
+            .chain(energies.windows(2).map(|w| (w[1] - w[0]).max(0.0)))
+            .collect();
+
+        let loudest_frame = chroma
+            .iter()
+            .map(|f| f.iter().sum::<f32>())
+            .fold(0.0, f32::max);
+        Self {
+            frame_duration: FRAME_LEN as f32 / rate,
+            chroma,
+            loudest_frame,
+            tempo: estimate_tempo(&onsets, ONSET_HOP as f32 / rate),
+        }
+    }
+
+    pub(crate) fn duration(&self) -> f32 {
+        self.chroma.len() as f32 * self.frame_duration
+    }
+
+    /// The tempo in BPM and the time of the first beat, if they could be
+    /// estimated.
+    pub(crate) fn tempo(&self) -> Option<(f32, f32)> {
+        self.tempo
+    }
+
+    /// Cut the recording into segments of `beats` beats each, starting at
+    /// `offset` seconds. Anything before the offset becomes a pickup segment.
+    pub(crate) fn segments(&self, bpm: f32, offset: f32, beats: u32) -> Vec<(f32, f32)> {
+        let duration = self.duration();
+        let length = 60.0 / bpm.max(1.0) * beats.max(1) as f32;
+        let offset = offset.rem_euclid(length);
+
+        let mut segments = Vec::new();
+        let mut start = 0.0;
+        let mut end = if offset > self.frame_duration {
+            offset
+        } else {
+            length
+        };
+        while start < duration {
+            segments.push((start, end.min(duration)));
+            start = end;
+            end += length;
+        }
+        segments
+    }
+
+    /// Label the section of the recording between `start` and `end` (in
+    /// seconds), returning a chord name `Chord::parse` understands.
+    pub(crate) fn label(&self, start: f32, end: f32) -> Option<String> {
+        let first = (start / self.frame_duration).floor() as usize;
+        let last = ((end / self.frame_duration).ceil() as usize).min(self.chroma.len());
+
+        let mut chroma = [0.0; 12];
+        for frame in self.chroma.get(first..last)? {
+            for (total, bin) in chroma.iter_mut().zip(frame) {
+                *total += bin;
+            }
+        }
+
+        let energy = chroma.iter().sum::<f32>() / (last - first).max(1) as f32;
+        if energy < self.loudest_frame * SILENCE_THRESHOLD {
+            return None;
+        }
+
+        best_chord(&chroma)
+    }
+}
+
+/// Estimate the tempo in BPM and the time of the first beat, by
+/// autocorrelating the onset strength over the 60-180 BPM range.
+fn estimate_tempo(onsets: &[f32], hop: f32) -> Option<(f32, f32)> {
+    let min_lag = (60.0 / 180.0 / hop).round() as usize;
+    let max_lag = (1.0 / hop).round() as usize;
+    if onsets.len() < max_lag * 2 {
+        return None;
+    }
+
+    let mean = onsets.iter().sum::<f32>() / onsets.len() as f32;
+    let centered: Vec<f32> = onsets.iter().map(|o| o - mean).collect();
+    let (lag, score) = (min_lag..=max_lag)
+        .map(|lag| {
+            let score = centered
+                .iter()
+                .zip(&centered[lag..])
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+                / (centered.len() - lag) as f32;
+            (lag, score)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+    if score <= 0.0 {
+        return None;
+    }
+
+    // Pick the phase whose beat grid lines up with the most onset energy.
+    let phase = (0..lag)
+        .max_by(|a, b| {
+            let sum = |offset: usize| onsets.iter().skip(offset).step_by(lag).sum::<f32>();
+            sum(*a).total_cmp(&sum(*b))
+        })
+        .unwrap_or(0);
+
+    Some((60.0 / (lag as f32 * hop), phase as f32 * hop))
+}
+
+/// Signal power at the frequency the coefficient (2cos(ω)) was made for.
+fn goertzel_power(samples: &[f32], coeff: f32) -> f32 {
+    let (mut s1, mut s2) = (0.0, 0.0);
+    for sample in samples {
+        let s0 = sample + coeff * s1 - s2;
+        s2 = s1;
+        s1 = s0;
+    }
+    s1 * s1 + s2 * s2 - coeff * s1 * s2
+}
+
+/// Match a chroma vector against every template in every key, using cosine
+/// similarity.
+fn best_chord(chroma: &[f32; 12]) -> Option<String> {
+    let norm = chroma.iter().map(|c| c * c).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return None;
+    }
+
+    let mut best = (0.0, None);
+    for root in 0..12 {
+        for (suffix, intervals, weight) in TEMPLATES {
+            let dot: f32 = intervals.iter().map(|i| chroma[(root + i) % 12]).sum();
+            let score = weight * dot / (norm * (intervals.len() as f32).sqrt());
+            if score > best.0 {
+                best = (score, Some(format!("{}{}", PITCH_NAMES[root], suffix)));
+            }
+        }
+    }
+    best.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 44100;
+
+    fn tone(freqs: &[f32], seconds: f32) -> Vec<f32> {
+        (0..(RATE as f32 * seconds) as usize)
+            .map(|i| {
+                let t = i as f32 / RATE as f32;
+                freqs
+                    .iter()
+                    .map(|f| (2.0 * std::f32::consts::PI * f * t).sin())
+                    .sum::<f32>()
+                    / freqs.len() as f32
+            })
+            .collect()
+    }
+
+    fn analyse(samples: Vec<f32>) -> Analysis {
+        let mut analyser = Analyser::from_recording(Recording {
+            sample_rate: RATE,
+            samples,
+        });
+        let mut progress = 0.0;
+        loop {
+            if let Some((_, analysis)) = analyser.step().unwrap() {
+                return analysis;
+            }
+            let Progress::Analysing(now) = analyser.progress() else {
+                panic!("went back to decoding");
+            };
+            assert!(now >= progress && now <= 1.0);
+            progress = now;
+        }
+    }
+
+    #[test]
+    fn test_labels_triads() {
+        // C4 E4 G4, then A3 C4 E4
+        let mut samples = tone(&[261.63, 329.63, 392.0], 2.0);
+        samples.extend(tone(&[220.0, 261.63, 329.63], 2.0));
+        let analysis = analyse(samples);
+
+        assert_eq!(analysis.label(0.0, 2.0).as_deref(), Some("C"));
+        assert_eq!(analysis.label(2.0, 4.0).as_deref(), Some("Am"));
+    }
+
+    #[test]
+    fn test_estimates_tempo_of_click_track() {
+        // A short click every half second is 120 BPM
+        let click = tone(&[880.0], 0.03);
+        let mut samples = vec![0.0; RATE as usize * 8];
+        for beat in 0..16 {
+            let start = beat * RATE as usize / 2;
+            samples[start..start + click.len()].copy_from_slice(&click);
+        }
+        let analysis = analyse(samples);
+
+        let (bpm, _) = analysis.tempo().unwrap();
+        assert!((bpm - 120.0).abs() < 4.0, "got {bpm} BPM");
+    }
+}
@@ -1,11 +1,30 @@
+use crate::complete::Autocomplete;
+use crate::diagram::Diagrams;
+use crate::ear::EarTraining;
+use crate::history::History;
+use crate::library::{Chosen, Library};
+use crate::locale::{self, tr, tr_with, Language};
+use crate::metronome::Metronome;
+use crate::naming::{Naming, System, KEYS};
+use crate::performance::Performance;
+use crate::piano::Piano;
+use crate::quiz::{Mark, Quiz, QuizCell};
+use crate::review::Reviews;
+use crate::shapes::ShapeQuiz;
+use crate::snapshot::{self, ImageExport};
+use crate::song::SongView;
+use crate::spelling::{sharp_name, Accidentals, Speller};
+use crate::staff::StaffView;
+use crate::symbol::{fix_chord_name, normalize, SymbolError};
+use crate::synth::{PlaybackSettings, Player};
+use crate::tab::TabExport;
+use crate::theory;
+use crate::timeline::Timeline;
+use crate::voicing::Voicing;
 use egui::RichText;
 use egui::WidgetText;
-use klib::core::base::{HasName, HasStaticName};
-use klib::core::base::{Playable, PlaybackHandle};
 use klib::core::chord::{Chord, HasChord, HasScale};
-use klib::core::named_pitch::HasNamedPitch;
 use klib::core::pitch::{HasPitch, Pitch};
-use std::time::Duration;
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -14,37 +33,86 @@ pub struct TemplateApp {
     chord: String,
     chord_normalized: String,
 
-    // Used as an LRU cache for the last played note -- the handles need to
-    // exist for the sound to continue playing.
+    // Holds the audio output stream, which has to stay open for notes to play
     #[serde(skip)]
-    playback_handles: Vec<PlaybackHandle>,
+    player: Player,
 
+    // The notes picked on the fretboard, one per string
     #[serde(skip)]
-    selection: Vec<Note>,
+    selection: Voicing,
 
     // Just to prevent recalculation each loop
     #[serde(skip)]
     c_scale: Vec<klib::core::pitch::Pitch>,
 
     settings: Settings,
+
+    // Chords looked up recently, and starred ones
+    history: History,
+
+    #[serde(skip)]
+    autocomplete: Autocomplete,
+
+    // Spaced repetition state shared by the practice modes
+    reviews: Reviews,
+
+    timeline: Timeline,
+
+    metronome: Metronome,
+
+    quiz: Quiz,
+
+    shapes: ShapeQuiz,
+
+    ear: EarTraining,
+
+    diagrams: Diagrams,
+
+    piano: Piano,
+
+    staff: StaffView,
+
+    image_export: ImageExport,
+
+    tab: TabExport,
+
+    song: SongView,
+
+    library: Library,
+
+    // The setlist being played on stage, shown instead of everything else
+    #[serde(skip)]
+    performance: Option<Performance>,
+
+    // What the system or browser is set to, for when no language is picked
+    #[serde(skip)]
+    system_language: Language,
 }
 
-#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(serde::Deserialize, serde::Serialize, Default)]
 #[serde(default)]
 pub struct Settings {
     show_disabled_notes: bool,
     default_disable_sharps: bool,
     hide_octaves: bool,
+    spelling: Spelling,
+    accidentals: Accidentals,
+    naming: Naming,
+    /// None to follow the system
+    language: Option<Language>,
+    playback: PlaybackSettings,
 }
 
-impl Default for Settings {
-    fn default() -> Self {
-        Self {
-            show_disabled_notes: false,
-            default_disable_sharps: false,
-            hide_octaves: false,
-        }
-    }
+/// When the chord text box is rewritten with the chord's tidied spelling.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Default, PartialEq)]
+enum Spelling {
+    /// Leave what was typed alone, and show how it was read beside it
+    #[default]
+    Keep,
+    /// Rewrite it when the text box loses focus
+    WhenDone,
+    /// Rewrite it on every change, which fights phone keyboards
+    AsYouType,
 }
 
 const DEFAULT_CHORD: &str = "Cmaj7";
@@ -53,9 +121,26 @@ impl Default for TemplateApp {
         Self {
             chord: DEFAULT_CHORD.to_owned(),
             chord_normalized: fix_chord_name(DEFAULT_CHORD),
-            playback_handles: Vec::new(),
-            selection: Vec::new(),
+            player: Default::default(),
+            selection: Default::default(),
             settings: Default::default(),
+            history: Default::default(),
+            autocomplete: Default::default(),
+            reviews: Default::default(),
+            timeline: Default::default(),
+            metronome: Default::default(),
+            quiz: Default::default(),
+            shapes: Default::default(),
+            ear: Default::default(),
+            diagrams: Default::default(),
+            piano: Default::default(),
+            staff: Default::default(),
+            image_export: Default::default(),
+            tab: Default::default(),
+            song: Default::default(),
+            library: Default::default(),
+            performance: None,
+            system_language: Language::English,
             c_scale: Chord::new(klib::core::note::C)
                 .scale()
                 .iter()
@@ -69,98 +154,384 @@ impl Default for TemplateApp {
 impl TemplateApp {
     /// Called once before the first frame.
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        let ctx = &cc.egui_ctx;
+        // Load previous app state (if any).
+        // Note that you must enable the `persistence` feature for this to work.
+        let mut app: Self = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
+
+        app.system_language = locale::detect();
+        app.set_language(&cc.egui_ctx);
+        app
+    }
 
-        let mut fonts = egui::FontDefinitions::default();
-        fonts.font_data.insert(
-            "noto_sans_music".to_owned(),
-            egui::FontData::from_static(include_bytes!("../assets/NotoSans-RegularWithMusic.otf")),
-        );
+    /// Show the UI in the language picked, or the system's, as long as
+    /// there's a font with its characters.
+    fn set_language(&self, ctx: &egui::Context) {
+        let language = self
+            .settings
+            .language
+            .filter(|language| language.displayable())
+            .unwrap_or(self.system_language);
+        locale::set_language(language);
+        ctx.set_fonts(fonts());
+    }
 
-        fonts
-            .families
-            .entry(egui::FontFamily::Proportional)
-            .or_default()
-            .insert(0, "noto_sans_music".to_owned());
+    /// Show a chord, in English letters, as if the user typed it in.
+    fn set_chord(&mut self, chord: &str) {
+        self.chord = self.settings.naming.chord(chord);
+        self.chord_normalized = fix_chord_name(chord);
+    }
 
-        ctx.set_fonts(fonts);
+    /// The pitches to enable on the fretboard: what the ear training just
+    /// played, or else the notes of the chord.
+    fn fretboard_pitches(&self) -> Vec<Pitch> {
+        use klib::core::base::Parsable;
 
-        // Load previous app state (if any).
-        // Note that you must enable the `persistence` feature for this to work.
-        if let Some(storage) = cc.storage {
-            return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+        if let Some(revealed) = self.ear.revealed_pitches() {
+            return revealed;
         }
+        Chord::parse(&self.chord_normalized)
+            .map(|chord| chord.chord().iter().map(|n| n.pitch()).collect())
+            .unwrap_or_default()
+    }
+
+    fn fretboard_ui(&mut self, ui: &mut egui::Ui, horizontal: bool, chord_pitches: &[Pitch]) {
+        let tuning = standard_tuning();
+        let speller = Speller::new(
+            self.settings.accidentals,
+            &self.settings.naming,
+            &self.chord_normalized,
+        );
+        // The quizzes ask about notes, not the chord's
+        let quiz_speller = Speller::new(self.settings.accidentals, &self.settings.naming, "");
+
+        let fret_label_widget = |ui: &mut egui::Ui, fret: usize| {
+            ui.add_sized(
+                [BUTTON_SIZE[0], BUTTON_SIZE[1] / 2.0],
+                egui::Label::new(egui::RichText::new(fret_label(fret)).strong().size(12.0)),
+            );
+        };
+
+        let mut fret_note_widget = |ui: &mut egui::Ui, string: usize, fret: usize| {
+            let note = note_for_fret(tuning[string], fret);
+
+            // The quizzes take over the fretboard while they're running
+            if let Some(cell) = self.quiz.cell(string, fret) {
+                let response = quiz_cell_button(
+                    ui,
+                    note,
+                    &cell,
+                    horizontal,
+                    &self.settings,
+                    &quiz_speller,
+                    &mut self.player,
+                );
+                if response.clicked() {
+                    let now = ui.input(|i| i.time);
+                    self.quiz.answer_position(
+                        &tuning,
+                        &mut self.reviews,
+                        &quiz_speller,
+                        string,
+                        fret,
+                        now,
+                    );
+                }
+                return;
+            }
+            if let Some(cell) = self.shapes.cell(&self.selection, &tuning, string, fret) {
+                let response = quiz_cell_button(
+                    ui,
+                    note,
+                    &cell,
+                    horizontal,
+                    &self.settings,
+                    &quiz_speller,
+                    &mut self.player,
+                );
+                if response.clicked() {
+                    self.shapes.select(&mut self.selection, string, fret);
+                }
+                return;
+            }
+
+            // enable only if chord pitches are empty or note is in the chord
 
-        Default::default()
+            let enabled = if !chord_pitches.is_empty() {
+                chord_pitches.contains(&note.pitch())
+            } else {
+                !self.settings.default_disable_sharps || self.c_scale.contains(&note.pitch())
+            };
+
+            // Clicking also picks the note for the voicing, one per string
+            let selected = self.selection.fret(string) == Some(fret);
+            let response = ui.add_enabled(
+                enabled,
+                note_button(
+                    note,
+                    selected,
+                    true,
+                    horizontal,
+                    &self.settings,
+                    &speller,
+                    &mut self.player,
+                ),
+            );
+            if response.clicked() {
+                self.selection.toggle(string, fret);
+            }
+            // The key clicked on the piano, wherever it's played
+            if self.piano.highlighted() == Some(theory::midi_number(note)) {
+                let stroke = egui::Stroke::new(2.0, ui.visuals().warn_fg_color);
+                ui.painter().rect_stroke(response.rect, 4.0, stroke);
+            }
+        };
+
+        egui::Grid::new("fretboard").show(ui, |ui| {
+            // I forget what this does
+            // ui.style_mut().visuals.widgets.hovered.bg_fill = egui::Color32::DARK_GRAY;
+
+            // Add fretboard labels as the first row if horizontal
+            if horizontal {
+                for fret in 0..MAX_FRET {
+                    fret_label_widget(ui, fret);
+                }
+                ui.end_row();
+
+                // add a row of buttons for each of the 6 strings
+                for string in 0..tuning.len() {
+                    for fret in 0..MAX_FRET {
+                        fret_note_widget(ui, string, fret);
+                    }
+                    ui.end_row();
+                }
+            } else {
+                for fret in 0..MAX_FRET {
+                    // Reverse string tuning
+                    fret_label_widget(ui, fret);
+                    for string in (0..tuning.len()).rev() {
+                        fret_note_widget(ui, string, fret);
+                    }
+                    ui.end_row();
+                }
+            }
+        });
     }
-}
 
-// Format a note for printing
-fn format_note_name(note: Note, hide_octaves: bool) -> String {
-    use klib::core::interval::Interval;
-    use klib::core::note::ToUniversal;
+    /// Render the fretboard as it's drawn now to a PNG, with the export
+    /// settings.
+    fn export_image(&mut self, ctx: &egui::Context) {
+        let visuals = self.image_export.theme.visuals(ctx);
+        let horizontal = self.image_export.horizontal;
+        let title = self
+            .image_export
+            .show_chord
+            .then(|| self.chord_normalized.clone())
+            .filter(|chord| !chord.is_empty());
+        let chord_pitches = self.fretboard_pitches();
+
+        let image = snapshot::render(fonts(), visuals, self.image_export.width, |ui| {
+            set_main_style(ui.style_mut());
+            if let Some(title) = &title {
+                ui.label(RichText::new(title).size(32.0).strong());
+                ui.add_space(12.0);
+            }
+            self.fretboard_ui(ui, horizontal, &chord_pitches);
+        });
+        self.image_export.save(&self.chord_normalized, &image);
+    }
+
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let dropped_files = ctx.input(|i| i.raw.dropped_files.clone());
+        for file in dropped_files {
+            let name = file
+                .path
+                .as_ref()
+                .and_then(|p| p.file_name())
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| file.name.clone());
+
+            // Native builds only give us the path, web builds give us the bytes
+            let bytes = match (&file.bytes, &file.path) {
+                (Some(bytes), _) => bytes.to_vec(),
+                (None, Some(path)) => match std::fs::read(path) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        log::error!("error reading {}: {}", path.display(), e);
+                        continue;
+                    }
+                },
+                (None, None) => continue,
+            };
 
-    // Get universal note, which may be flat, but never sharp (or double flat/sharp)
-    let mut unote = note.to_universal();
-    let note_name = unote.name();
+            // Progress exported from another device, song sheets and scores,
+            // anything else should be a recording
+            if name.ends_with(".ron") {
+                self.reviews.import_file(&name, &bytes);
+                continue;
+            }
+            let extension = name.rsplit_once('.').map(|(_, e)| e.to_lowercase());
+            if let Some(
+                "cho" | "chopro" | "chordpro" | "crd" | "pro" | "txt" | "musicxml" | "xml" | "mxl",
+            ) = extension.as_deref()
+            {
+                self.song.load_file(&name, &bytes);
+                continue;
+            }
 
-    // convert flats to sharps
-    if note_name.contains('♭') {
-        unote = unote + Interval::AugmentedSeventh - Interval::PerfectOctave;
+            self.timeline.open = true;
+            self.timeline.load(&name, bytes);
+        }
     }
+}
 
-    if hide_octaves {
-        return unote.named_pitch().static_name().to_owned();
+/// The app's fonts, with a font that has the music symbols first.
+fn fonts() -> egui::FontDefinitions {
+    let mut fonts = egui::FontDefinitions::default();
+    fonts.font_data.insert(
+        "noto_sans_music".to_owned(),
+        egui::FontData::from_static(include_bytes!("../assets/NotoSans-RegularWithMusic.otf")),
+    );
+
+    let proportional = fonts
+        .families
+        .entry(egui::FontFamily::Proportional)
+        .or_default();
+    proportional.insert(0, "noto_sans_music".to_owned());
+
+    // Last, for the characters the others don't have, so Japanese can be
+    // picked from the language menu and shown
+    if let Some(font) = locale::cjk_font() {
+        fonts.font_data.insert("cjk".to_owned(), font);
+        for family in fonts.families.values_mut() {
+            family.push("cjk".to_owned());
+        }
     }
+    fonts
+}
 
-    // Turn octave (well all numbers) into subscripts
-    let mut s = String::new();
-    for c in unote.name().chars() {
-        s.push(match c {
-            '0' => '₀',
-            '1' => '₁',
-            '2' => '₂',
-            '3' => '₃',
-            '4' => '₄',
-            '5' => '₅',
-            '6' => '₆',
-            '7' => '₇',
-            '8' => '₈',
-            '9' => '₉',
-            _ => c,
-        });
+// Estimate the space needed to not overlap the bottom panel
+const BOTTOM_PANEL_SLOP: f32 = 24.0;
+
+/// Where the fretboard, or the stage view when performing, is drawn.
+fn main_area(ctx: &egui::Context) -> egui::Area {
+    egui::Area::new("main")
+        .anchor(egui::Align2::CENTER_TOP, [0.0, 5.0])
+        // The goal of this constrain is to stop it from overlapping
+        // the bottom panel.
+        .constrain_to(
+            ctx.available_rect()
+                .translate([0.0, -BOTTOM_PANEL_SLOP].into())
+                .shrink2([0.0, BOTTOM_PANEL_SLOP].into()),
+        )
+}
+
+/// Whether to lay things out side by side. If screen is narrow, (e.g.
+/// phones in portrait mode), things are stacked to be more compact
+/// vertically.
+pub(crate) fn horizontal_layout(screen_rect: egui::Rect) -> bool {
+    let wide_enough = screen_rect.width() > BUTTON_SIZE[0] * (MAX_FRET as f32);
+    let tall_enough = screen_rect.height() > BUTTON_SIZE[1] * (MAX_FRET as f32 + 3.0);
+    let aspect_ratio = screen_rect.width() / screen_rect.height();
+    let max_aspect_ratio = MAX_FRET as f32 / 10.0;
+
+    // Needs to be wide enough *or* if its narrow enough up to a certain point
+    wide_enough && !tall_enough || aspect_ratio > max_aspect_ratio
+}
+
+/// Bigger text and no gaps between the note buttons, for the main view.
+fn set_main_style(style: &mut egui::Style) {
+    style
+        .text_styles
+        .get_mut(&egui::TextStyle::Button)
+        .unwrap()
+        .size = MAIN_FONT_SIZE;
+
+    style
+        .text_styles
+        .get_mut(&egui::TextStyle::Body)
+        .unwrap()
+        .size = MAIN_FONT_SIZE;
+
+    style
+        .text_styles
+        .get_mut(&egui::TextStyle::Heading)
+        .unwrap()
+        .size = 12.0;
+
+    style.spacing.item_spacing = egui::Vec2::new(0.0, 0.0);
+}
+
+/// The color for things that went right, like an answer or a save.
+pub(crate) const SUCCESS_COLOR: egui::Color32 = egui::Color32::from_rgb(0x40, 0xa0, 0x40);
+
+/// Show feedback on the last thing done, in green if it went right and
+/// the error color if not.
+pub(crate) fn feedback_label(ui: &mut egui::Ui, message: &Option<(bool, String)>) {
+    if let Some((ok, message)) = message {
+        let color = if *ok {
+            SUCCESS_COLOR
+        } else {
+            ui.visuals().error_fg_color
+        };
+        ui.colored_label(color, message);
     }
-    s
 }
 
 const MAIN_FONT_SIZE: f32 = 18.0;
 const BUTTON_HEIGHT: f32 = 60.0;
 const BUTTON_SIZE: [f32; 2] = [BUTTON_HEIGHT, BUTTON_HEIGHT];
-const MAX_FRET: usize = 16;
+pub(crate) const MAX_FRET: usize = 16;
 
 use klib::core::note::HasNoteId;
 use klib::core::note::Note;
 
-fn note_for_fret(string: Note, fret: usize) -> Note {
+pub(crate) fn note_for_fret(string: Note, fret: usize) -> Note {
     let note_id = string.id() << fret;
     Note::from_id(note_id).unwrap()
 }
 
-fn playback_handle_add(handle: PlaybackHandle, handles: &mut Vec<PlaybackHandle>) {
-    // LRU
-    const MAX_HANDLES: usize = 50;
-    if handles.len() >= MAX_HANDLES {
-        handles.remove(0);
+/// Standard guitar tuning, from the highest string to the lowest.
+// TODO: make this configurable
+pub(crate) fn standard_tuning() -> [Note; 6] {
+    use klib::core::named_pitch::NamedPitch;
+    use klib::core::octave::Octave;
+    [
+        Note::new(NamedPitch::E, Octave::Four),
+        Note::new(NamedPitch::B, Octave::Three),
+        Note::new(NamedPitch::G, Octave::Three),
+        Note::new(NamedPitch::D, Octave::Three),
+        Note::new(NamedPitch::A, Octave::Two),
+        Note::new(NamedPitch::E, Octave::Two),
+    ]
+}
+
+/// Name of a string for showing to the user, e.g. "G", or "low E" when more
+/// than one string has the same name.
+pub(crate) fn string_name(tuning: &[Note], string: usize) -> String {
+    let name = sharp_name(tuning[string], true);
+    let same_name: Vec<usize> = (0..tuning.len())
+        .filter(|i| sharp_name(tuning[*i], true) == name)
+        .collect();
+
+    match same_name.iter().position(|i| *i == string) {
+        Some(_) if same_name.len() == 1 => name,
+        Some(0) => tr_with("high {}", &name),
+        Some(i) if i == same_name.len() - 1 => tr_with("low {}", &name),
+        _ => format!("{} ({})", name, string + 1),
     }
-    handles.push(handle);
 }
 
 fn note_button<'a>(
     note: Note,
     selected: bool,
+    show_name: bool,
     horizontal: bool,
     settings: &'a Settings,
-    playback_handles: &'a mut Vec<PlaybackHandle>,
+    speller: &'a Speller,
+    player: &'a mut Player,
 ) -> impl egui::Widget + 'a {
     move |ui: &mut egui::Ui| {
         // Scope is in case we want to do style changes for this button
@@ -169,10 +540,11 @@ fn note_button<'a>(
         ui.scope(|ui| {
             let hide_octaves = settings.hide_octaves;
             let note_name: WidgetText = match ui.is_enabled() {
-                true => format_note_name(note, hide_octaves).into(),
+                _ if !show_name => RichText::new(" ").into(),
+                true => speller.name(note, hide_octaves).into(),
                 false => {
                     if settings.show_disabled_notes {
-                        RichText::new(format_note_name(note, hide_octaves))
+                        RichText::new(speller.name(note, hide_octaves))
                             .weak()
                             .into()
                     } else {
@@ -189,19 +561,9 @@ fn note_button<'a>(
             };
             let response = ui.add_sized(button_size, label);
             if response.clicked() {
-                let dur = Duration::from_millis(500);
-                // TODO: this crackles, just use the frequency and a different lib
-                // to play sound?
-                let ret = note.play(Duration::from_millis(0), dur, Duration::from_millis(0));
-
-                match ret {
-                    Ok(h) => {
-                        log::debug!("played note {}", note);
-                        // Have to keep the handle around to play the sound.
-                        playback_handle_add(h, playback_handles);
-                    }
-                    Err(e) => log::error!("error playing note: {}", e),
-                }
+                let frequency = theory::frequency(theory::midi_number(note));
+                player.play(frequency, &settings.playback);
+                log::debug!("played note {}", note);
             }
 
             // Draw a line through the button if it's disabled, to help align frets
@@ -228,9 +590,74 @@ fn note_button<'a>(
     }
 }
 
+/// A note button drawn the way a quiz wants it, outlined if the answer has
+/// been marked.
+fn quiz_cell_button(
+    ui: &mut egui::Ui,
+    note: Note,
+    cell: &QuizCell,
+    horizontal: bool,
+    settings: &Settings,
+    speller: &Speller,
+    player: &mut Player,
+) -> egui::Response {
+    let response = ui.add_enabled(
+        cell.enabled,
+        note_button(
+            note,
+            cell.selected,
+            cell.show_name,
+            horizontal,
+            settings,
+            speller,
+            player,
+        ),
+    );
+    if let Some(mark) = cell.mark {
+        let color = match mark {
+            Mark::Correct => SUCCESS_COLOR,
+            Mark::Wrong => ui.visuals().error_fg_color,
+            Mark::Missing => ui.visuals().warn_fg_color,
+        };
+        ui.painter()
+            .rect_stroke(response.rect, 4.0, egui::Stroke::new(2.0, color));
+    }
+    response
+}
+
+/// The typed chord with the part that couldn't be read underlined.
+fn underline_error(ui: &egui::Ui, text: &str, error: &SymbolError) -> egui::text::LayoutJob {
+    let font = egui::TextStyle::Monospace.resolve(ui.style());
+    let color = ui.visuals().text_color();
+    let error_color = ui.visuals().error_fg_color;
+    let normal = egui::TextFormat::simple(font.clone(), color);
+    let underlined = egui::TextFormat {
+        color: error_color,
+        underline: egui::Stroke::new(2.0, error_color),
+        ..egui::TextFormat::simple(font, color)
+    };
+
+    let chars: Vec<char> = text.chars().collect();
+    let part = |from: usize, to: usize| -> String {
+        chars[from.min(chars.len())..to.min(chars.len())]
+            .iter()
+            .collect()
+    };
+    let mut job = egui::text::LayoutJob::default();
+    job.append(&part(0, error.start), 0.0, normal.clone());
+    // Something missing at the end gets a gap to underline
+    let bad = match part(error.start, error.end) {
+        bad if bad.is_empty() => " ".to_owned(),
+        bad => bad,
+    };
+    job.append(&bad, 0.0, underlined);
+    job.append(&part(error.end, chars.len()), 0.0, normal);
+    job
+}
+
 fn fret_label(fret: usize) -> String {
     match fret {
-        0 => "Open",
+        0 => tr("Open"),
         3 => "3",
         5 => "5",
         7 => "7",
@@ -245,45 +672,6 @@ fn fret_label(fret: usize) -> String {
     .to_owned()
 }
 
-// Normalize the chordname so kord::parse can recognize it
-fn fix_chord_name(chord: &str) -> String {
-    // First, capitalize the first letter if it's a-g
-    let mut chord = chord.to_owned();
-    if let Some(c) = chord.chars().next() {
-        if let 'a'..='g' = c {
-            chord = format!("{}{}", c.to_ascii_uppercase(), &chord[1..]);
-        }
-    }
-
-    // Uppercase the first letter after a slash (if it's a note)
-    let mut last_was_slash = false;
-    let mut ret = String::new();
-    for c in chord.chars() {
-        if last_was_slash {
-            if let 'a'..='g' = c {
-                ret.push(c.to_ascii_uppercase());
-            } else {
-                ret.push(c);
-            }
-        } else {
-            ret.push(c);
-        }
-        last_was_slash = c == '/';
-    }
-    // Change sharps/flats to the unicode symbol
-    ret = ret.replace("b", "♭");
-    ret = ret.replace("#", "♯");
-
-    // convert Maj, MAj, etc, to lowercase (yes it's not exhaustive, but this is
-    // meant to only catch common errors and adding the regex crate adds almost
-    // 1MB to the wasm binary
-    ret = ret.replace("Maj", "maj");
-    ret = ret.replace("MAj", "maj");
-    ret = ret.replace("MAJ", "maj");
-
-    ret
-}
-
 impl eframe::App for TemplateApp {
     /// Called by the frame work to save state before shutdown.
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
@@ -292,6 +680,26 @@ impl eframe::App for TemplateApp {
 
     /// Called each time the UI needs repainting, which may be many times per second.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // On stage, just the chords
+        if let Some(performance) = &mut self.performance {
+            egui::CentralPanel::default().show(ctx, |_ui| {
+                // Just here to paint a background
+            });
+            let mut performing = true;
+            main_area(ctx).show(ctx, |ui| {
+                set_main_style(ui.style_mut());
+                let horizontal = horizontal_layout(ctx.available_rect());
+                performing = performance.ui(ui, horizontal, &standard_tuning());
+            });
+            if !performing {
+                self.performance = None;
+                ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(false));
+            }
+            return;
+        }
+
+        let naming = self.settings.naming.clone();
+        let language = self.settings.language;
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             // The top panel is often a good place for a menu bar:
 
@@ -299,28 +707,144 @@ impl eframe::App for TemplateApp {
                 // NOTE: no File->Quit on web pages!
                 let is_web = cfg!(target_arch = "wasm32");
                 if !is_web {
-                    ui.menu_button("File", |ui| {
-                        if ui.button("Quit").clicked() {
+                    ui.menu_button(tr("File"), |ui| {
+                        if ui.button(tr("Quit")).clicked() {
                             ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                         }
                     });
                     ui.add_space(16.0);
                 }
-                ui.menu_button("Settings", |ui| {
+                ui.menu_button(tr("Tools"), |ui| {
+                    ui.checkbox(&mut self.song.open, tr("Song"))
+                        .on_hover_text(tr("Step through the chords of a ChordPro song sheet"));
+                    ui.checkbox(&mut self.library.open, tr("Song library"))
+                        .on_hover_text(tr(
+                            "Keep every song you play, and find them by key or chord",
+                        ));
+                    ui.checkbox(&mut self.diagrams.open, tr("Chord diagram"))
+                        .on_hover_text(tr(
+                            "Draw the selected voicing as a chord box, and export diagrams",
+                        ));
+                    ui.checkbox(&mut self.piano.open, tr("Piano"))
+                        .on_hover_text(tr("Show the chord or voicing on a piano keyboard"));
+                    ui.checkbox(&mut self.staff.open, tr("Staff notation"))
+                        .on_hover_text(tr("Write the chord or voicing on a staff"));
+                    ui.checkbox(&mut self.tab.open, tr("Tab"))
+                        .on_hover_text(tr("Write a voicing or progression as text tab to share"));
+                    ui.checkbox(&mut self.image_export.open, tr("Export image"))
+                        .on_hover_text(tr("Save the fretboard as it's shown as a PNG"));
+                    ui.checkbox(&mut self.timeline.open, tr("Recording timeline"))
+                        .on_hover_text(tr("Find the chords in a recording, bar by bar"));
+                    ui.checkbox(&mut self.metronome.open, tr("Metronome"))
+                        .on_hover_text(tr("Practise a progression against a click"));
+                    ui.checkbox(&mut self.quiz.open, tr("Fretboard quiz"))
+                        .on_hover_text(tr("Learn where the notes are on the fretboard"));
+                    ui.checkbox(&mut self.shapes.open, tr("Chord shape quiz"))
+                        .on_hover_text(tr("Play a named chord in any voicing"));
+                    ui.checkbox(&mut self.ear.open, tr("Ear training"))
+                        .on_hover_text(tr("Recognise intervals, chord qualities and inversions"));
+                    ui.separator();
+                    ui.checkbox(&mut self.reviews.open, tr("Practice progress"))
+                        .on_hover_text(tr(
+                            "See what's due for review, and move progress between devices",
+                        ));
+                });
+                ui.menu_button(tr("Settings"), |ui| {
                     // add a toggle for showing disabled notes
-                    ui.checkbox(&mut self.settings.show_disabled_notes, "Show all notes")
-                        .on_hover_text("Always show all notes, even if they're not in the chord");
+                    ui.checkbox(&mut self.settings.show_disabled_notes, tr("Show all notes"))
+                        .on_hover_text(tr(
+                            "Always show all notes, even if they're not in the chord",
+                        ));
 
                     // add a toggle for defaulting to disabling sharps
                     ui.checkbox(
                         &mut self.settings.default_disable_sharps,
-                        "Disable sharps by default",
+                        tr("Disable sharps by default"),
                     )
-                    .on_hover_text("When no chord is entered, don't show the sharps");
+                    .on_hover_text(tr("When no chord is entered, don't show the sharps"));
 
                     // add a toggle for hiding octaves
-                    ui.checkbox(&mut self.settings.hide_octaves, "Hide octaves")
-                        .on_hover_text("Hide the octave number in the note name");
+                    ui.checkbox(&mut self.settings.hide_octaves, tr("Hide octaves"))
+                        .on_hover_text(tr("Hide the octave number in the note name"));
+
+                    ui.separator();
+                    ui.label(tr("Note names"));
+                    ui.radio_value(
+                        &mut self.settings.accidentals,
+                        Accidentals::Auto,
+                        tr("Auto"),
+                    )
+                    .on_hover_text(tr(
+                        "Spell notes the way the chord and its key do, like B♭ D F",
+                    ));
+                    ui.radio_value(
+                        &mut self.settings.accidentals,
+                        Accidentals::Sharps,
+                        tr("Sharps"),
+                    );
+                    ui.radio_value(
+                        &mut self.settings.accidentals,
+                        Accidentals::Flats,
+                        tr("Flats"),
+                    );
+
+                    ui.separator();
+                    ui.label(tr("Naming system"));
+                    for system in System::ALL {
+                        ui.radio_value(
+                            &mut self.settings.naming.system,
+                            system,
+                            tr(system.label()),
+                        );
+                    }
+                    if self.settings.naming.system.relative() {
+                        let key = tr_with("In the key of {}", &self.settings.naming.key);
+                        ui.menu_button(key, |ui| {
+                            for key in KEYS {
+                                let selected = self.settings.naming.key == key;
+                                if ui.selectable_label(selected, key).clicked() {
+                                    self.settings.naming.key = key.to_owned();
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                    }
+
+                    ui.separator();
+                    ui.label(tr("Tidy the chord's spelling"));
+                    ui.radio_value(&mut self.settings.spelling, Spelling::Keep, tr("Never"))
+                        .on_hover_text(tr("Leave what you type alone, and show how it was read"));
+                    ui.radio_value(
+                        &mut self.settings.spelling,
+                        Spelling::WhenDone,
+                        tr("When done typing"),
+                    );
+                    ui.radio_value(
+                        &mut self.settings.spelling,
+                        Spelling::AsYouType,
+                        tr("As you type"),
+                    )
+                    .on_hover_text(tr("Can confuse phone keyboards' autocorrect"));
+
+                    ui.separator();
+                    ui.label(tr("Language"));
+                    let system = tr_with("System ({})", self.system_language.name());
+                    ui.radio_value(&mut self.settings.language, None, system);
+                    for language in Language::ALL.into_iter().filter(|l| l.displayable()) {
+                        ui.radio_value(
+                            &mut self.settings.language,
+                            Some(language),
+                            language.name(),
+                        );
+                    }
+
+                    ui.separator();
+                    ui.label(tr("Playback"));
+                    self.settings.playback.ui(ui);
+                    if ui.button(tr("▶ Preview")).clicked() {
+                        self.player
+                            .play(theory::frequency(69), &self.settings.playback);
+                    }
                 });
 
                 // Align dark mode buttons buttons on the top right
@@ -330,207 +854,228 @@ impl eframe::App for TemplateApp {
             });
         });
 
+        if self.settings.language != language {
+            self.set_language(ctx);
+        }
+        // Show the chord in the naming system just picked
+        if self.settings.naming != naming {
+            let chord = self.chord_normalized.clone();
+            self.set_chord(&chord);
+        }
+        self.handle_dropped_files(ctx);
+        if let Some(chord) = self.timeline.show(ctx) {
+            self.set_chord(&chord);
+        }
+        if let Some(chord) = self.metronome.show(ctx) {
+            self.set_chord(&chord);
+        }
+        match self.library.show(ctx, self.song.song()) {
+            Some(Chosen::Song(song)) => self.song.open_song(song),
+            Some(Chosen::Setlist(songs)) => {
+                self.performance = Performance::new(songs);
+                ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(true));
+            }
+            None => {}
+        }
+        if let Some((chord, voicing)) = self.song.show(ctx, &self.selection, &standard_tuning()) {
+            self.set_chord(&chord);
+            self.selection = voicing.unwrap_or_default();
+        }
+        // The quizzes and tab are about notes, not the chord's
+        let note_speller = Speller::new(self.settings.accidentals, &self.settings.naming, "");
+        self.quiz
+            .show(ctx, &standard_tuning(), &mut self.reviews, &note_speller);
+        self.shapes.show(
+            ctx,
+            &standard_tuning(),
+            &mut self.selection,
+            &mut self.reviews,
+        );
+        self.ear.show(
+            ctx,
+            &mut self.player,
+            &self.settings.playback,
+            &mut self.reviews,
+            &note_speller,
+        );
+        self.reviews.show(ctx);
+        self.diagrams.show(
+            ctx,
+            &self.chord_normalized,
+            &mut self.selection,
+            &standard_tuning(),
+        );
+        let speller = Speller::new(
+            self.settings.accidentals,
+            &self.settings.naming,
+            &self.chord_normalized,
+        );
+        let clicked = self.piano.show(
+            ctx,
+            &self.chord_normalized,
+            &self.selection,
+            &standard_tuning(),
+            &speller,
+        );
+        if let Some(midi) = clicked {
+            self.player
+                .play(theory::frequency(midi), &self.settings.playback);
+        }
+        self.staff.show(
+            ctx,
+            &self.chord_normalized,
+            &self.selection,
+            &standard_tuning(),
+            &speller,
+        );
+
+        self.tab.show(
+            ctx,
+            &self.chord_normalized,
+            &self.selection,
+            &standard_tuning(),
+            &note_speller,
+        );
+        if self.image_export.show(ctx) {
+            self.export_image(ctx);
+        }
+
         egui::CentralPanel::default().show(ctx, |_ui| {
             // Just here to paint a background
         });
 
-        // Estimate the space needed to not overlap the bottom panel
-        const BOTTOM_PANEL_SLOP: f32 = 24.0;
-        egui::Area::new("main")
-            .anchor(egui::Align2::CENTER_TOP, [0.0, 5.0])
-            // The goal of this constrain is to stop it from overlapping
-            // the bottom panel.
-            .constrain_to(
-                ctx.available_rect()
-                    .translate([0.0, -BOTTOM_PANEL_SLOP].into())
-                    .shrink2([0.0, BOTTOM_PANEL_SLOP].into()),
-            )
-            .show(ctx, |ui| {
-                // If screen is narrow, (e.g. phones in portrait mode), make
-                // things more compact vertically
-                let screen_rect = ctx.available_rect();
-                let wide_enough = screen_rect.width() > BUTTON_SIZE[0] * (MAX_FRET as f32);
-                let tall_enough = screen_rect.height() > BUTTON_SIZE[1] * (MAX_FRET as f32 + 3.0);
-                let aspect_ratio = screen_rect.width() / screen_rect.height();
-                let max_aspect_ratio = MAX_FRET as f32 / 10.0;
-
-                // Needs to be wide enough *or* if its narrow enough up to a certain point
-                let horizontal = wide_enough && !tall_enough || aspect_ratio > max_aspect_ratio;
-
-                let style = ui.style_mut();
-                style
-                    .text_styles
-                    .get_mut(&egui::TextStyle::Button)
-                    .unwrap()
-                    .size = MAIN_FONT_SIZE;
-
-                style
-                    .text_styles
-                    .get_mut(&egui::TextStyle::Body)
-                    .unwrap()
-                    .size = MAIN_FONT_SIZE;
-
-                style
-                    .text_styles
-                    .get_mut(&egui::TextStyle::Heading)
-                    .unwrap()
-                    .size = 12.0;
-
-                style.spacing.item_spacing = egui::Vec2::new(0.0, 0.0);
-
-                // store chord pitches (need HasPitch to convert Note to Pitch)
-                let mut chord_pitches: Vec<Pitch> = Vec::new();
-
-                ui.horizontal(|ui| {
-                    ui.vertical(|ui| {
-                        ui.heading("Chord finder");
-                        if ui
-                            .add_sized(
-                                [150.0, BUTTON_HEIGHT],
-                                egui::TextEdit::singleline(&mut self.chord)
-                                    .vertical_align(egui::Align::Center),
-                            )
-                            .changed()
-                        {
-                            // TODO: this fucks with autocorrect when this
-                            // actually changes the text
-                            self.chord_normalized = fix_chord_name(self.chord.as_str());
-                            // Remove this if instead of auto-correct we want to
-                            // just leave whatever the user typed in.
-                            //
-                            // TODO: make this an option? or print out what
-                            // we're parsing in the next box
-                            self.chord = self.chord_normalized.clone();
+        main_area(ctx).show(ctx, |ui| {
+            let horizontal = horizontal_layout(ctx.available_rect());
+
+            set_main_style(ui.style_mut());
+
+            ui.horizontal(|ui| {
+                ui.vertical(|ui| {
+                    ui.heading(tr("Chord finder"));
+                    let text_box = ui.add_sized(
+                        [150.0, BUTTON_HEIGHT],
+                        egui::TextEdit::singleline(&mut self.chord)
+                            .vertical_align(egui::Align::Center),
+                    );
+                    // Read what's typed in the naming system into letters
+                    let naming = &self.settings.naming;
+                    let read = naming.read(&self.chord);
+                    if text_box.changed() {
+                        self.chord_normalized = fix_chord_name(&read);
+                    }
+                    let spelling = self.settings.spelling;
+                    let rewrite = spelling == Spelling::AsYouType && text_box.changed()
+                        || spelling == Spelling::WhenDone && text_box.lost_focus();
+                    if rewrite {
+                        if let Ok(name) = normalize(&read) {
+                            self.chord = naming.chord(&name);
                         }
-                    });
-
-                    // Add a text field for the user to enter a chord name
-                    use klib::core::base::Parsable;
-
-                    // TODO: accept comma or space separated chords
-                    // TODO: accept raw note input?
-                    // parse the chord and show it
-                    let chord = Chord::parse(self.chord_normalized.as_str());
-                    if !self.chord_normalized.is_empty() {
-                        ui.add_space(15.0);
-                        ui.separator();
-                        ui.add_space(15.0);
-                        ui.vertical(|ui| {
-                            match chord {
-                                Ok(chord) => {
-                                    ui.heading("Chord notes");
-                                    ui.horizontal(|ui| {
-                                        chord.chord().iter().for_each(|note| {
-                                            if horizontal {
-                                                ui.add(note_button(
-                                                    *note,
-                                                    false,
-                                                    true,
-                                                    &self.settings,
-                                                    &mut self.playback_handles,
-                                                ));
-                                            } else {
-                                                use egui::widgets::Label;
-                                                ui.add_sized(
-                                                    [0.0, BUTTON_SIZE[1]],
-                                                    Label::new(format_note_name(*note, true)),
-                                                );
-                                                ui.add_space(8.0);
-                                            }
-
-                                            // store pitch
-                                            chord_pitches.push(note.pitch());
-                                        });
-                                    });
-                                }
-                                Err(_e) => {
-                                    ui.heading(format!("Invalid chord: {}", self.chord));
+                    }
+                    // How what was typed was read, when it's spelled
+                    // differently
+                    if let Ok(name) = normalize(&naming.read(&self.chord)) {
+                        let name = naming.chord(&name);
+                        if name != self.chord {
+                            ui.horizontal(|ui| {
+                                ui.label(RichText::new(tr_with("Read as {}", &name)).weak());
+                                if ui
+                                    .small_button(tr("Apply"))
+                                    .on_hover_text(tr("Use this spelling in the text box"))
+                                    .clicked()
+                                {
+                                    self.chord = name;
                                 }
-                            }
-                        });
+                            });
+                        }
+                    }
+                    let typed = self.settings.naming.read(&self.chord);
+                    if let Some(chord) = self.autocomplete.ui(
+                        ui,
+                        &text_box,
+                        &typed,
+                        self.settings.accidentals,
+                        &self.settings.naming,
+                    ) {
+                        self.set_chord(&chord);
+                    }
+                    if let Some(chord) = self.history.ui(
+                        ui,
+                        &text_box,
+                        &self.chord_normalized,
+                        &self.settings.naming,
+                    ) {
+                        self.set_chord(&chord);
                     }
                 });
 
-                ui.add_space(20.0);
-
-                ui.heading("Fretboard");
-
-                egui::ScrollArea::vertical().show(ui, |ui| {
-                    use klib::core::named_pitch::NamedPitch;
-                    use klib::core::octave::Octave;
-                    // Standard guitar tuning -- TODO: make this configurable
-                    let tuning: [Note; 6] = [
-                        Note::new(NamedPitch::E, Octave::Four),
-                        Note::new(NamedPitch::B, Octave::Three),
-                        Note::new(NamedPitch::G, Octave::Three),
-                        Note::new(NamedPitch::D, Octave::Three),
-                        Note::new(NamedPitch::A, Octave::Two),
-                        Note::new(NamedPitch::E, Octave::Two),
-                    ];
-
-                    let fret_label_widget = |ui: &mut egui::Ui, fret: usize| {
-                        ui.add_sized(
-                            [BUTTON_SIZE[0], BUTTON_SIZE[1] / 2.0],
-                            egui::Label::new(
-                                egui::RichText::new(fret_label(fret)).strong().size(12.0),
-                            ),
-                        );
-                    };
-
-                    let mut fret_note_widget = |ui: &mut egui::Ui, string: Note, fret: usize| {
-                        let note = note_for_fret(string, fret);
-                        // enable only if chord pitches are empty or note is in the chord
-
-                        let enabled = if !chord_pitches.is_empty() {
-                            chord_pitches.contains(&note.pitch())
-                        } else {
-                            !self.settings.default_disable_sharps
-                                || self.c_scale.contains(&note.pitch())
-                        };
-
-                        ui.add_enabled(
-                            enabled,
-                            note_button(
-                                note,
-                                false,
-                                horizontal,
-                                &self.settings,
-                                &mut self.playback_handles,
-                            ),
-                        );
-                    };
-
-                    egui::Grid::new("fretboard").show(ui, |ui| {
-                        // I forget what this does
-                        // ui.style_mut().visuals.widgets.hovered.bg_fill = egui::Color32::DARK_GRAY;
-
-                        // Add fretboard labels as the first row if horizontal
-                        if horizontal {
-                            for fret in 0..MAX_FRET {
-                                fret_label_widget(ui, fret);
-                            }
-                            ui.end_row();
-
-                            // add a row of buttons for each of the 6 strings
-                            for string in tuning {
-                                for fret in 0..MAX_FRET {
-                                    fret_note_widget(ui, string, fret);
-                                }
-                                ui.end_row();
+                // Add a text field for the user to enter a chord name
+                use klib::core::base::Parsable;
+
+                // TODO: accept comma or space separated chords
+                // TODO: accept raw note input?
+                // parse the chord and show it
+                let chord = Chord::parse(self.chord_normalized.as_str());
+                if !self.chord_normalized.is_empty() {
+                    ui.add_space(15.0);
+                    ui.separator();
+                    ui.add_space(15.0);
+                    ui.vertical(|ui| match chord {
+                        Ok(chord) => {
+                            ui.heading(tr("Chord notes"));
+                            let speller = Speller::new(
+                                self.settings.accidentals,
+                                &self.settings.naming,
+                                &self.chord_normalized,
+                            );
+                            ui.horizontal(|ui| {
+                                chord.chord().iter().for_each(|note| {
+                                    if horizontal {
+                                        ui.add(note_button(
+                                            *note,
+                                            false,
+                                            true,
+                                            true,
+                                            &self.settings,
+                                            &speller,
+                                            &mut self.player,
+                                        ));
+                                    } else {
+                                        use egui::widgets::Label;
+                                        ui.add_sized(
+                                            [0.0, BUTTON_SIZE[1]],
+                                            Label::new(speller.name(*note, true)),
+                                        );
+                                        ui.add_space(8.0);
+                                    }
+                                });
+                            });
+                        }
+                        Err(_e) => match normalize(&self.settings.naming.read(&self.chord)) {
+                            Err(error) => {
+                                // The error's position is in the text read
+                                let read = self.settings.naming.read(&self.chord);
+                                ui.heading(tr("Invalid chord"));
+                                ui.label(underline_error(ui, &read, &error));
+                                ui.colored_label(ui.visuals().error_fg_color, error.message());
                             }
-                        } else {
-                            for fret in 0..MAX_FRET {
-                                // Reverse string tuning
-                                fret_label_widget(ui, fret);
-                                for string in tuning.iter().rev() {
-                                    fret_note_widget(ui, *string, fret);
-                                }
-                                ui.end_row();
+                            Ok(name) => {
+                                ui.heading(tr_with("Invalid chord: {}", &self.chord));
+                                ui.label(tr_with("{} isn't a chord the parser knows", &name));
                             }
-                        }
+                        },
                     });
-                });
+                }
+            });
+
+            ui.add_space(20.0);
+
+            ui.heading(tr("Fretboard"));
+
+            let chord_pitches = self.fretboard_pitches();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                self.fretboard_ui(ui, horizontal, &chord_pitches);
             });
+        });
 
         egui::TopBottomPanel::bottom("bottom_panel").show(ctx, |ui| {
             egui::warn_if_debug_build(ui);
@@ -544,18 +1089,18 @@ fn powered_by_egui_and_eframe(ui: &mut egui::Ui) {
         ui.spacing_mut().item_spacing.x = 0.0;
         ui.add(egui::github_link_file!(
             "https://github.com/kelvie/chord-finder/blob/master/",
-            "Source code"
+            tr("Source code")
         ));
         ui.label(".");
         ui.add_space(5.0);
-        ui.label("Powered by ");
+        ui.label(tr("Powered by "));
         ui.hyperlink_to("egui", "https://github.com/emilk/egui");
         ui.label(", ");
         ui.hyperlink_to(
             "eframe",
             "https://github.com/emilk/egui/tree/master/crates/eframe",
         );
-        ui.label(", and ");
+        ui.label(tr(", and "));
         ui.hyperlink_to("kord", "https://github.com/twitchax/kord");
         ui.label(". ");
     });
@@ -573,4 +1118,13 @@ mod tests {
             FSharp
         );
     }
+
+    #[test]
+    fn test_string_names() {
+        let tuning = super::standard_tuning();
+        let names: Vec<String> = (0..tuning.len())
+            .map(|i| super::string_name(&tuning, i))
+            .collect();
+        assert_eq!(names, ["high E", "B", "G", "D", "A", "low E"]);
+    }
 }
@@ -0,0 +1,376 @@
+//! Reading and writing ChordPro song sheets.
+//!
+//! Chords are written in brackets before the syllable they're played on,
+//! like `[Am7]Hello`, and everything else is a directive in braces like
+//! `{title: Song}`. See <https://www.chordpro.org/chordpro/>.
+
+use crate::locale::{tr, tr_fill, tr_with};
+use crate::song::{chord_name, is_annotation, Define, Line, Problem, Segment, Song};
+use crate::voicing::{find_voicing, fingers, Voicing};
+use klib::core::note::Note;
+use std::fmt::Write;
+
+// Strings in a {define}, which only makes sense for standard guitar
+const STRINGS: usize = 6;
+
+/// Read a ChordPro sheet. Anything that can't be read is skipped and
+/// reported, so a typo doesn't lose the whole song.
+pub(crate) fn parse(text: &str) -> (Song, Vec<Problem>) {
+    let mut song = Song::default();
+    let mut problems = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let number = i + 1;
+        let mut problem = |message: String| {
+            problems.push(Problem {
+                line: number,
+                message,
+            })
+        };
+
+        let line = line.trim_end();
+        // Comments for whoever edits the file, not for showing
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let trimmed = line.trim();
+        if let Some(inner) = trimmed
+            .strip_prefix('{')
+            .and_then(|rest| rest.strip_suffix('}'))
+        {
+            let (name, value) = match inner.split_once(|c: char| c == ':' || c.is_whitespace()) {
+                Some((name, value)) => (name.trim(), value.trim()),
+                None => (inner.trim(), ""),
+            };
+            let name = name.to_lowercase();
+            let name = directive_name(&name);
+            match name {
+                "title" => song.title = value.to_owned(),
+                "artist" => song.artist = value.to_owned(),
+                "key" => match chord_name(value) {
+                    Some(key) => song.key = Some(key),
+                    None => problem(tr_with("can't read the key {}", value)),
+                },
+                "capo" => match value.parse() {
+                    Ok(capo) => song.capo = capo,
+                    Err(_) => problem(tr_with("can't read the capo {}", value)),
+                },
+                "define" => match parse_define(value) {
+                    Ok(define) => song.defines.push(define),
+                    Err(e) => problem(e),
+                },
+                "comment" | "comment_italic" | "comment_box" => {
+                    song.lines.push(Line::Comment(value.to_owned()))
+                }
+                _ => song.lines.push(Line::Directive {
+                    name: name.to_owned(),
+                    value: value.to_owned(),
+                }),
+            }
+            continue;
+        }
+
+        let segments = parse_lyrics(line);
+        for segment in &segments {
+            if let Some(chord) = &segment.chord {
+                if chord_name(chord).is_none() && !is_annotation(chord) {
+                    problem(tr_with("can't read the chord {}", &format!("[{}]", chord)));
+                }
+            }
+        }
+        song.lines.push(Line::Lyrics(segments));
+    }
+
+    (song, problems)
+}
+
+/// The full name of a directive, for the ones with short forms.
+fn directive_name(name: &str) -> &str {
+    match name {
+        "t" => "title",
+        "c" => "comment",
+        "ci" => "comment_italic",
+        "cb" => "comment_box",
+        "soc" => "start_of_chorus",
+        "eoc" => "end_of_chorus",
+        "sov" => "start_of_verse",
+        "eov" => "end_of_verse",
+        "sob" => "start_of_bridge",
+        "eob" => "end_of_bridge",
+        "sot" => "start_of_tab",
+        "eot" => "end_of_tab",
+        name => name,
+    }
+}
+
+fn parse_lyrics(line: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut current = Segment::default();
+    let mut rest = line;
+    while let Some(start) = rest.find('[') {
+        let Some(end) = rest[start..].find(']').map(|end| start + end) else {
+            break;
+        };
+        current.text.push_str(&rest[..start]);
+        if current.chord.is_some() || !current.text.is_empty() {
+            segments.push(current);
+        }
+        current = Segment {
+            chord: Some(rest[start + 1..end].to_owned()),
+            text: String::new(),
+        };
+        rest = &rest[end + 1..];
+    }
+    current.text.push_str(rest);
+    if current.chord.is_some() || !current.text.is_empty() {
+        segments.push(current);
+    }
+    segments
+}
+
+/// Read a chord definition like `Am base-fret 1 frets x 0 2 2 1 0`, with the
+/// frets from the lowest string up. Fingers and anything else are ignored.
+fn parse_define(value: &str) -> Result<Define, String> {
+    let mut tokens = value.split_whitespace();
+    let name = tokens
+        .next()
+        .ok_or_else(|| tr("{define} needs a chord name"))?
+        .to_owned();
+
+    let mut base_fret = 1;
+    let mut frets = Vec::new();
+    let mut keyword = "";
+    for token in tokens {
+        match token {
+            "base-fret" | "frets" | "fingers" | "keys" | "display" | "format" | "copy" => {
+                keyword = token;
+                continue;
+            }
+            _ => {}
+        }
+        match keyword {
+            "base-fret" => {
+                base_fret = token
+                    .parse()
+                    .map_err(|_| tr_fill("can't read the base fret {} of {}", &[token, &name]))?;
+            }
+            "frets" => frets.push(match token {
+                "x" | "X" | "N" | "-1" => None,
+                _ => match token.parse::<usize>() {
+                    Ok(0) => Some(0),
+                    Ok(fret) => Some(base_fret + fret - 1),
+                    Err(_) => return Err(tr_fill("can't read the fret {} of {}", &[token, &name])),
+                },
+            }),
+            _ => {}
+        }
+    }
+
+    if frets.len() != STRINGS {
+        return Err(tr_fill(
+            "{define} of {} has {} frets, it needs one for each of the {} strings",
+            &[&name, &frets.len().to_string(), &STRINGS.to_string()],
+        ));
+    }
+    // Voicings go from the highest string down
+    frets.reverse();
+    Ok(Define {
+        name,
+        voicing: Voicing { frets },
+    })
+}
+
+/// Write a song as ChordPro, with a `{define}` for each of its chords so
+/// other apps show the same shapes. Chords the song doesn't have a voicing
+/// for get the one the app would suggest.
+pub(crate) fn write(song: &Song, tuning: &[Note]) -> String {
+    let mut text = String::new();
+    if !song.title.is_empty() {
+        writeln!(text, "{{title: {}}}", song.title).unwrap();
+    }
+    if !song.artist.is_empty() {
+        writeln!(text, "{{artist: {}}}", song.artist).unwrap();
+    }
+    if let Some(key) = &song.key {
+        writeln!(text, "{{key: {}}}", key).unwrap();
+    }
+    if song.capo > 0 {
+        writeln!(text, "{{capo: {}}}", song.capo).unwrap();
+    }
+
+    for chord in song.written_chords() {
+        let name = chord_name(chord).unwrap_or_default();
+        let voicing = match song.define(&name) {
+            Some(voicing) => voicing.clone(),
+            None => match find_voicing(&name, tuning) {
+                Some(voicing) => voicing,
+                None => continue,
+            },
+        };
+        writeln!(text, "{}", define_directive(chord, &voicing, tuning)).unwrap();
+    }
+    // A blank line between the header and the song, unless it has one
+    let blank_first = matches!(song.lines.first(), Some(Line::Lyrics(s)) if s.is_empty());
+    if !text.is_empty() && !blank_first {
+        text.push('\n');
+    }
+
+    for line in &song.lines {
+        match line {
+            Line::Lyrics(segments) => {
+                for segment in segments {
+                    if let Some(chord) = &segment.chord {
+                        write!(text, "[{}]", chord).unwrap();
+                    }
+                    text.push_str(&segment.text);
+                }
+            }
+            Line::Comment(comment) => write!(text, "{{comment: {}}}", comment).unwrap(),
+            Line::Directive { name, value } if value.is_empty() => {
+                write!(text, "{{{}}}", name).unwrap()
+            }
+            Line::Directive { name, value } => write!(text, "{{{}: {}}}", name, value).unwrap(),
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// A `{define}` for a voicing, with the frets relative to a base fret when
+/// the shape is up the neck, and which finger frets each string.
+fn define_directive(name: &str, voicing: &Voicing, tuning: &[Note]) -> String {
+    let fretted: Vec<usize> = voicing
+        .frets
+        .iter()
+        .flatten()
+        .copied()
+        .filter(|f| *f > 0)
+        .collect();
+    let lowest = fretted.iter().copied().min().unwrap_or(1);
+    let highest = fretted.iter().copied().max().unwrap_or(1);
+    // Shapes that fit in the first few frets are drawn from the nut
+    let base_fret = if highest <= 4 { 1 } else { lowest };
+
+    // ChordPro lists the strings from the lowest up
+    let frets: Vec<String> = (0..tuning.len())
+        .rev()
+        .map(|string| match voicing.fret(string) {
+            None => "x".to_owned(),
+            Some(0) => "0".to_owned(),
+            Some(fret) => (fret + 1 - base_fret).to_string(),
+        })
+        .collect();
+    let fingers: Vec<String> = fingers(voicing, tuning)
+        .into_iter()
+        .rev()
+        .map(|finger| finger.unwrap_or(0).to_string())
+        .collect();
+
+    format!(
+        "{{define: {} base-fret {} frets {} fingers {}}}",
+        name,
+        base_fret,
+        frets.join(" "),
+        fingers.join(" ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::standard_tuning;
+
+    #[test]
+    fn test_parse() {
+        let text = "\
+{title: Example}
+{key: Am}
+{capo: 2}
+{define: Am base-fret 1 frets x 0 2 2 1 0}
+{define: Bm base-fret 2 frets x 1 3 3 2 1}
+# Not shown
+
+{soc}
+[Am]Hello [C]there, [N.C.]stop
+I'm [Hm]wrong
+{eoc}";
+        let (song, problems) = parse(text);
+        assert_eq!(song.title, "Example");
+        assert_eq!(song.key.as_deref(), Some("Am"));
+        assert_eq!(song.capo, 2);
+        assert_eq!(song.chords(), ["Am", "C"]);
+        assert_eq!(
+            problems,
+            [Problem {
+                line: 10,
+                message: "can't read the chord [Hm]".to_owned()
+            }]
+        );
+
+        let bm = song.define("Bm").unwrap();
+        assert_eq!(
+            bm.frets,
+            [Some(2), Some(3), Some(4), Some(4), Some(2), None]
+        );
+        assert_eq!(
+            song.lines[2],
+            Line::Lyrics(vec![
+                Segment {
+                    chord: Some("Am".to_owned()),
+                    text: "Hello ".to_owned()
+                },
+                Segment {
+                    chord: Some("C".to_owned()),
+                    text: "there, ".to_owned()
+                },
+                Segment {
+                    chord: Some("N.C.".to_owned()),
+                    text: "stop".to_owned()
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_write() {
+        let tuning = standard_tuning();
+        let text = "{title: Example}
+{define: Bm base-fret 2 frets x 1 3 3 2 1}
+
+{start_of_chorus: Chorus}
+[Bm]Hello [F#]there
+{comment: Twice}
+{end_of_chorus}
+";
+        let (song, _) = parse(text);
+        let written = write(&song, &tuning);
+        assert_eq!(
+            written,
+            "{title: Example}
+{define: Bm base-fret 1 frets x 2 4 4 3 2 fingers 0 1 3 4 2 1}
+{define: F# base-fret 1 frets 2 4 4 3 2 2 fingers 1 3 4 2 1 1}
+
+{start_of_chorus: Chorus}
+[Bm]Hello [F#]there
+{comment: Twice}
+{end_of_chorus}
+"
+        );
+
+        // Reading it back gives the same song, with the new definition
+        let (reread, problems) = parse(&written);
+        assert!(problems.is_empty());
+        assert_eq!(reread.lines, song.lines);
+        assert_eq!(reread.defines.len(), 2);
+
+        // Shapes up the neck are written from their lowest fret
+        let a = Voicing {
+            frets: vec![Some(5), Some(5), Some(6), Some(7), Some(7), Some(5)],
+        };
+        assert_eq!(
+            define_directive("A", &a, &tuning),
+            "{define: A base-fret 5 frets 1 3 3 2 1 1 fingers 1 3 4 2 1 1}"
+        );
+    }
+}
@@ -0,0 +1,280 @@
+//! Completions for a chord name as it's typed, so it's clear which
+//! spellings the chord parser understands.
+
+use crate::app::SUCCESS_COLOR;
+use crate::naming::Naming;
+use crate::spelling::{Accidentals, Speller};
+use crate::symbol::fix_chord_name;
+use klib::core::base::Parsable;
+use klib::core::chord::{Chord, HasChord};
+
+// Qualities the parser knows, in rough order of how often they're played
+const QUALITIES: [(&str, &str); 23] = [
+    ("", "major"),
+    ("m", "minor"),
+    ("7", "dominant seventh"),
+    ("maj7", "major seventh"),
+    ("m7", "minor seventh"),
+    ("sus4", "suspended fourth"),
+    ("sus2", "suspended second"),
+    ("6", "sixth"),
+    ("m6", "minor sixth"),
+    ("9", "ninth"),
+    ("add9", "added ninth"),
+    ("maj9", "major ninth"),
+    ("m9", "minor ninth"),
+    ("7sus4", "seventh, suspended fourth"),
+    ("dim", "diminished"),
+    ("dim7", "diminished seventh"),
+    ("m7(♭5)", "half diminished"),
+    ("+", "augmented"),
+    ("m(maj7)", "minor, major seventh"),
+    ("7(♯9)", "seventh, sharp ninth"),
+    ("7(♭9)", "seventh, flat ninth"),
+    ("11", "eleventh"),
+    ("13", "thirteenth"),
+];
+const MAX_SHOWN: usize = 12;
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Completion {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    /// The notes of the chord, or none if it doesn't parse
+    pub(crate) notes: Option<Vec<String>>,
+}
+
+fn notes(name: &str, accidentals: Accidentals, naming: &Naming) -> Option<Vec<String>> {
+    let chord = Chord::parse(name).ok()?;
+    let speller = Speller::new(accidentals, naming, name);
+    Some(
+        chord
+            .chord()
+            .iter()
+            .map(|note| speller.name(*note, true))
+            .collect(),
+    )
+}
+
+/// Split a chord name into its root, like `C♯`, and the rest.
+fn split_root(name: &str) -> Option<(&str, &str)> {
+    let mut chars = name.char_indices();
+    let (_, letter) = chars.next()?;
+    if !('A'..='G').contains(&letter) {
+        return None;
+    }
+    let end = match chars.next() {
+        Some((i, '♯' | '♭')) => i + '♯'.len_utf8(),
+        Some((i, _)) => i,
+        None => name.len(),
+    };
+    Some(name.split_at(end))
+}
+
+/// What the typed chord could be: itself first, then the qualities that
+/// start with what's typed after the root. Names are in English letters and
+/// notes in the naming system.
+pub(crate) fn completions(
+    typed: &str,
+    accidentals: Accidentals,
+    naming: &Naming,
+) -> Vec<Completion> {
+    let name = fix_chord_name(typed.trim());
+    if name.is_empty() {
+        return Vec::new();
+    }
+    let Some((root, rest)) = split_root(&name) else {
+        return vec![Completion {
+            description: "chords start with a note from A to G".to_owned(),
+            notes: None,
+            name,
+        }];
+    };
+
+    let mut completions = vec![Completion {
+        description: QUALITIES
+            .iter()
+            .find(|(quality, _)| *quality == rest)
+            .map_or("", |(_, description)| description)
+            .to_owned(),
+        notes: notes(&name, accidentals, naming),
+        name: name.clone(),
+    }];
+    // Slash chords are typed out in full
+    if rest.contains('/') {
+        return completions;
+    }
+    for (quality, description) in QUALITIES {
+        if quality == rest || !quality.starts_with(rest) {
+            continue;
+        }
+        let name = format!("{}{}", root, quality);
+        completions.push(Completion {
+            notes: notes(&name, accidentals, naming),
+            description: description.to_owned(),
+            name,
+        });
+    }
+    completions.truncate(MAX_SHOWN);
+    completions
+}
+
+/// Which completion Down moves to, or none from the last one.
+fn next_highlight(highlighted: usize, count: usize) -> Option<usize> {
+    (highlighted + 1 < count).then_some(highlighted + 1)
+}
+
+/// The dropdown of completions under the chord text box.
+#[derive(Default)]
+pub(crate) struct Autocomplete {
+    open: bool,
+    highlighted: usize,
+    // Whether the pointer was over the dropdown last frame, so clicking it
+    // doesn't close it before the click lands
+    hovered: bool,
+}
+
+impl Autocomplete {
+    /// Show completions while the chord is being typed, which is read into
+    /// English letters already. Up and down move through them and Enter
+    /// picks one. Returns the chord picked.
+    ///
+    /// The recent chords under the text box also use Down, to move focus to
+    /// them, so Down on the last completion is left for them and closes the
+    /// dropdown.
+    pub(crate) fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        text_box: &egui::Response,
+        typed: &str,
+        accidentals: Accidentals,
+        naming: &Naming,
+    ) -> Option<String> {
+        use egui::Key;
+
+        if text_box.changed() {
+            self.open = true;
+            self.highlighted = 0;
+        }
+        let entered = text_box.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+        if !text_box.has_focus() && !self.hovered && !entered {
+            self.open = false;
+        }
+        if !self.open {
+            return None;
+        }
+        let completions = completions(typed, accidentals, naming);
+        if completions.is_empty() {
+            self.open = false;
+            return None;
+        }
+        self.highlighted = self.highlighted.min(completions.len() - 1);
+
+        let mut picked = None;
+        if entered {
+            picked = Some(self.highlighted);
+        }
+        let pressed = |key| ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, key));
+        if ui.input(|i| i.key_pressed(Key::ArrowDown)) {
+            match next_highlight(self.highlighted, completions.len()) {
+                Some(next) => {
+                    pressed(Key::ArrowDown);
+                    self.highlighted = next;
+                }
+                None => {
+                    self.open = false;
+                    return None;
+                }
+            }
+        }
+        if pressed(Key::ArrowUp) {
+            self.highlighted = self.highlighted.saturating_sub(1);
+        }
+        if pressed(Key::Escape) {
+            self.open = false;
+            return None;
+        }
+
+        let area = egui::Area::new(text_box.id.with("completions"))
+            .order(egui::Order::Foreground)
+            .fixed_pos(text_box.rect.left_bottom())
+            .show(ui.ctx(), |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    egui::Grid::new("completions").show(ui, |ui| {
+                        for (i, completion) in completions.iter().enumerate() {
+                            let (mark, color) = match completion.notes {
+                                Some(_) => ("✔", SUCCESS_COLOR),
+                                None => ("✖", ui.visuals().error_fg_color),
+                            };
+                            ui.colored_label(color, mark);
+                            if ui
+                                .selectable_label(
+                                    i == self.highlighted,
+                                    naming.chord(&completion.name),
+                                )
+                                .clicked()
+                            {
+                                picked = Some(i);
+                            }
+                            ui.label(egui::RichText::new(&completion.description).weak());
+                            match &completion.notes {
+                                Some(notes) => ui.label(notes.join(" ")),
+                                None => ui.colored_label(color, "Invalid chord"),
+                            };
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+        self.hovered = area.response.hovered();
+
+        let picked = picked?;
+        self.open = false;
+        self.hovered = false;
+        Some(completions[picked].name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completions() {
+        let names = |typed| -> Vec<String> {
+            completions(typed, Accidentals::Auto, &Naming::default())
+                .into_iter()
+                .map(|c| c.name)
+                .collect()
+        };
+        assert_eq!(names("c#m7"), ["C♯m7", "C♯m7(♭5)"]);
+        assert_eq!(names("Gsu"), ["Gsu", "Gsus4", "Gsus2"]);
+        assert!(names("A").len() == MAX_SHOWN);
+        assert_eq!(names("Am/G"), ["Am/G"]);
+
+        let completions = completions("Dm", Accidentals::Auto, &Naming::default());
+        assert_eq!(completions[0].description, "minor");
+        assert_eq!(
+            completions[0].notes.as_deref(),
+            Some(&["D".to_owned(), "F".to_owned(), "A".to_owned()][..])
+        );
+        assert!(completions.iter().all(|c| c.notes.is_some()));
+        // Spelled the way the chord is, and as preferred
+        let notes = |typed, accidentals| {
+            super::completions(typed, accidentals, &Naming::default())[0]
+                .notes
+                .clone()
+                .unwrap()
+        };
+        assert_eq!(notes("Bb", Accidentals::Auto), ["B♭", "D", "F"]);
+        assert_eq!(notes("Bb", Accidentals::Sharps), ["A♯", "D", "F"]);
+
+        let invalid = super::completions("Hm", Accidentals::Auto, &Naming::default());
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].notes, None);
+
+        // Down past the last completion is left for the recent chords
+        assert_eq!(next_highlight(0, 3), Some(1));
+        assert_eq!(next_highlight(2, 3), None);
+    }
+}
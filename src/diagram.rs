@@ -0,0 +1,456 @@
+//! Chord box diagrams, drawn on screen and exported as SVG.
+//!
+//! A diagram is laid out once as a list of simple shapes, which can then be
+//! painted with egui or written out as SVG, so both always look the same.
+
+use crate::app::feedback_label;
+use crate::files::{file_name, save_feedback};
+use crate::locale::{tr, tr_with};
+use crate::progression::Progression;
+use crate::voicing::{find_voicing, fingers, Voicing};
+use klib::core::note::Note;
+use std::fmt::Write;
+
+// Layout, in SVG user units (pixels at 1x)
+const STRING_SPACING: f32 = 20.0;
+const FRET_SPACING: f32 = 24.0;
+const LEFT: f32 = 34.0;
+const TITLE_Y: f32 = 18.0;
+const MARKER_Y: f32 = 42.0;
+const TOP: f32 = 54.0;
+const DOT_RADIUS: f32 = 8.0;
+const MIN_FRETS: usize = 4;
+pub(crate) const WIDTH: f32 = 150.0;
+const SHEET_COLUMNS: usize = 4;
+
+/// Whether a shape is drawn in the foreground colour, or knocked out of it
+/// (finger numbers on dots).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Ink {
+    Foreground,
+    Background,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) enum Shape {
+    Line {
+        from: [f32; 2],
+        to: [f32; 2],
+        width: f32,
+    },
+    Circle {
+        center: [f32; 2],
+        radius: f32,
+        filled: bool,
+    },
+    Text {
+        pos: [f32; 2],
+        text: String,
+        size: f32,
+        ink: Ink,
+    },
+}
+
+/// A laid out diagram, with its origin at the top left.
+pub(crate) struct Diagram {
+    pub(crate) shapes: Vec<Shape>,
+    pub(crate) height: f32,
+}
+
+impl Diagram {
+    pub(crate) fn new(name: &str, voicing: &Voicing, tuning: &[Note]) -> Self {
+        let strings = tuning.len();
+        let fingers = fingers(voicing, tuning);
+        // Strings are drawn lowest on the left, the tuning is highest first
+        let x = |string: usize| LEFT + (strings - 1 - string) as f32 * STRING_SPACING;
+
+        let fretted: Vec<usize> = voicing
+            .frets
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|f| *f > 0)
+            .collect();
+        let lowest = fretted.iter().copied().min().unwrap_or(1);
+        let highest = fretted.iter().copied().max().unwrap_or(1);
+        // Chords that fit below the 5th fret are shown from the nut
+        let first_fret = if highest <= MIN_FRETS + 1 { 1 } else { lowest };
+        let frets_shown = (highest + 1 - first_fret).max(MIN_FRETS + 1);
+        let y = |fret: usize| TOP + (fret + 1 - first_fret) as f32 * FRET_SPACING;
+        let left = x(strings - 1);
+        let right = x(0);
+        let bottom = y(first_fret + frets_shown - 1);
+
+        let mut shapes = vec![Shape::Text {
+            pos: [(left + right) / 2.0, TITLE_Y],
+            text: name.to_owned(),
+            size: 18.0,
+            ink: Ink::Foreground,
+        }];
+
+        // Nut, or the position of the first fret
+        if first_fret == 1 {
+            shapes.push(Shape::Line {
+                from: [left - 1.0, TOP],
+                to: [right + 1.0, TOP],
+                width: 5.0,
+            });
+        } else {
+            shapes.push(Shape::Text {
+                pos: [left - 20.0, TOP + FRET_SPACING / 2.0],
+                text: format!("{}fr", first_fret),
+                size: 12.0,
+                ink: Ink::Foreground,
+            });
+        }
+        for fret in 0..=frets_shown {
+            let y = TOP + fret as f32 * FRET_SPACING;
+            shapes.push(Shape::Line {
+                from: [left, y],
+                to: [right, y],
+                width: 1.0,
+            });
+        }
+        for string in 0..strings {
+            shapes.push(Shape::Line {
+                from: [x(string), TOP],
+                to: [x(string), bottom],
+                width: 1.0,
+            });
+        }
+
+        // A barre across the strings the index finger holds down
+        let barred: Vec<usize> = (0..strings)
+            .filter(|s| fingers[*s] == Some(1) && voicing.fret(*s) == Some(lowest))
+            .collect();
+        if barred.len() > 1 {
+            let center_y = y(lowest) - FRET_SPACING / 2.0;
+            shapes.push(Shape::Line {
+                from: [x(barred[barred.len() - 1]), center_y],
+                to: [x(barred[0]), center_y],
+                width: DOT_RADIUS * 2.0,
+            });
+        }
+
+        for (string, finger) in fingers.iter().enumerate() {
+            match voicing.fret(string) {
+                None => shapes.push(Shape::Text {
+                    pos: [x(string), MARKER_Y],
+                    text: "×".to_owned(),
+                    size: 14.0,
+                    ink: Ink::Foreground,
+                }),
+                Some(0) => shapes.push(Shape::Circle {
+                    center: [x(string), MARKER_Y],
+                    radius: 5.0,
+                    filled: false,
+                }),
+                Some(fret) => {
+                    let center = [x(string), y(fret) - FRET_SPACING / 2.0];
+                    shapes.push(Shape::Circle {
+                        center,
+                        radius: DOT_RADIUS,
+                        filled: true,
+                    });
+                    if let Some(finger) = finger {
+                        shapes.push(Shape::Text {
+                            pos: center,
+                            text: finger.to_string(),
+                            size: 11.0,
+                            ink: Ink::Background,
+                        });
+                    }
+                }
+            }
+        }
+
+        Self {
+            shapes,
+            height: bottom + 16.0,
+        }
+    }
+
+    /// Paint the diagram `scale` times its size, with its top left corner at
+    /// `origin`.
+    pub(crate) fn paint(
+        &self,
+        painter: &egui::Painter,
+        origin: egui::Pos2,
+        scale: f32,
+        foreground: egui::Color32,
+        background: egui::Color32,
+    ) {
+        let pos = |p: [f32; 2]| origin + egui::vec2(p[0], p[1]) * scale;
+        let color = |ink: Ink| match ink {
+            Ink::Foreground => foreground,
+            Ink::Background => background,
+        };
+        for shape in &self.shapes {
+            match shape {
+                Shape::Line { from, to, width } => {
+                    painter.line_segment([pos(*from), pos(*to)], (*width * scale, foreground));
+                }
+                Shape::Circle {
+                    center,
+                    radius,
+                    filled: true,
+                } => {
+                    painter.circle_filled(pos(*center), *radius * scale, foreground);
+                }
+                Shape::Circle { center, radius, .. } => {
+                    painter.circle_stroke(pos(*center), *radius * scale, (1.5 * scale, foreground));
+                }
+                Shape::Text {
+                    pos: p,
+                    text,
+                    size,
+                    ink,
+                } => {
+                    painter.text(
+                        pos(*p),
+                        egui::Align2::CENTER_CENTER,
+                        text,
+                        egui::FontId::proportional(*size * scale),
+                        color(*ink),
+                    );
+                }
+            }
+        }
+    }
+
+    fn write_svg(&self, svg: &mut String) {
+        for shape in &self.shapes {
+            // Writing to a String can't fail
+            let _ = match shape {
+                Shape::Line { from, to, width } => writeln!(
+                    svg,
+                    r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="black" stroke-width="{}" stroke-linecap="{}"/>"#,
+                    from[0],
+                    from[1],
+                    to[0],
+                    to[1],
+                    width,
+                    if *width > DOT_RADIUS {
+                        "round"
+                    } else {
+                        "square"
+                    }
+                ),
+                Shape::Circle {
+                    center,
+                    radius,
+                    filled,
+                } => writeln!(
+                    svg,
+                    r#"<circle cx="{}" cy="{}" r="{}" {}/>"#,
+                    center[0],
+                    center[1],
+                    radius,
+                    if *filled {
+                        r#"fill="black""#
+                    } else {
+                        r#"fill="none" stroke="black" stroke-width="1.5""#
+                    }
+                ),
+                Shape::Text {
+                    pos,
+                    text,
+                    size,
+                    ink,
+                } => writeln!(
+                    svg,
+                    r#"<text x="{}" y="{}" font-size="{}" fill="{}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+                    pos[0],
+                    pos[1],
+                    size,
+                    if *ink == Ink::Foreground {
+                        "black"
+                    } else {
+                        "white"
+                    },
+                    escape(text)
+                ),
+            };
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// An SVG document with the diagrams laid out in rows, black on white for
+/// printing.
+pub(crate) fn svg_sheet(diagrams: &[Diagram]) -> String {
+    let columns = diagrams.len().clamp(1, SHEET_COLUMNS);
+    let row_height = diagrams.iter().map(|d| d.height).fold(0.0, f32::max);
+    let rows = (diagrams.len() + columns - 1) / columns;
+    let width = columns as f32 * WIDTH;
+    let height = rows.max(1) as f32 * row_height;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}" font-family="sans-serif">"#,
+        width, height
+    );
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+    for (i, diagram) in diagrams.iter().enumerate() {
+        let _ = writeln!(
+            svg,
+            r#"<g transform="translate({} {})">"#,
+            (i % columns) as f32 * WIDTH,
+            (i / columns) as f32 * row_height
+        );
+        diagram.write_svg(&mut svg);
+        let _ = writeln!(svg, "</g>");
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Window showing the selected voicing as a chord diagram.
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+#[serde(default)]
+pub(crate) struct Diagrams {
+    pub(crate) open: bool,
+    sheet_text: String,
+
+    #[serde(skip)]
+    message: Option<(bool, String)>,
+}
+
+impl Diagrams {
+    pub(crate) fn show(
+        &mut self,
+        ctx: &egui::Context,
+        chord: &str,
+        selection: &mut Voicing,
+        tuning: &[Note],
+    ) {
+        let mut open = self.open;
+        egui::Window::new(tr("Chord diagram"))
+            .id(egui::Id::new("chord_diagram"))
+            .open(&mut open)
+            .show(ctx, |ui| self.ui(ui, chord, selection, tuning));
+        self.open = open;
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, chord: &str, selection: &mut Voicing, tuning: &[Note]) {
+        ui.horizontal(|ui| {
+            if ui
+                .button(tr("Find a voicing"))
+                .on_hover_text(tr_with("Select a voicing of {} on the fretboard", chord))
+                .clicked()
+            {
+                match find_voicing(chord, tuning) {
+                    Some(voicing) => *selection = voicing,
+                    None => {
+                        self.message =
+                            Some((false, tr_with("Couldn't find a voicing of {}", chord)))
+                    }
+                }
+            }
+            if ui.button(tr("Clear")).clicked() {
+                selection.clear();
+            }
+        });
+
+        if selection.is_empty() {
+            ui.label(tr("Click notes on the fretboard to build a voicing"));
+        } else {
+            let diagram = Diagram::new(chord, selection, tuning);
+            let (rect, _) =
+                ui.allocate_exact_size(egui::vec2(WIDTH, diagram.height), egui::Sense::hover());
+            diagram.paint(
+                ui.painter(),
+                rect.min,
+                1.0,
+                ui.visuals().strong_text_color(),
+                ui.visuals().window_fill,
+            );
+
+            if ui.button(tr("💾 Export SVG")).clicked() {
+                let svg = svg_sheet(&[diagram]);
+                self.save(&file_name(chord, "svg"), svg);
+            }
+        }
+
+        ui.separator();
+        ui.label(tr("Export a sheet of diagrams for a progression:"));
+        ui.text_edit_singleline(&mut self.sheet_text);
+        if ui
+            .add_enabled(
+                !self.sheet_text.trim().is_empty(),
+                egui::Button::new(tr("💾 Export sheet")),
+            )
+            .clicked()
+        {
+            match sheet(&self.sheet_text, tuning) {
+                Ok(svg) => self.save("chord_sheet.svg", svg),
+                Err(e) => self.message = Some((false, e)),
+            }
+        }
+
+        feedback_label(ui, &self.message);
+    }
+
+    fn save(&mut self, name: &str, svg: String) {
+        self.message = Some(save_feedback(name, "image/svg+xml", svg.as_bytes()));
+    }
+}
+
+/// A sheet with one diagram for each different chord in a progression.
+pub(crate) fn sheet(progression: &str, tuning: &[Note]) -> Result<String, String> {
+    let progression = Progression::parse(progression)?;
+    let mut names: Vec<&str> = Vec::new();
+    for chord in &progression.chords {
+        if !names.contains(&chord.name.as_str()) {
+            names.push(&chord.name);
+        }
+    }
+
+    let mut diagrams = Vec::new();
+    for name in names {
+        let voicing = find_voicing(name, tuning)
+            .ok_or_else(|| tr_with("Couldn't find a voicing of {}", name))?;
+        diagrams.push(Diagram::new(name, &voicing, tuning));
+    }
+    Ok(svg_sheet(&diagrams))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::standard_tuning;
+
+    #[test]
+    fn test_sheet() {
+        let tuning = standard_tuning();
+        let svg = sheet("C:2 F G C", &tuning).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<g ").count(), 3);
+        assert!(svg.contains(">F</text>"));
+
+        // The C has one muted string, two open ones and its third finger
+        let c = Diagram::new("C", &find_voicing("C", &tuning).unwrap(), &tuning);
+        let count = |text: &str| {
+            c.shapes
+                .iter()
+                .filter(|s| matches!(s, Shape::Text { text: t, .. } if t == text))
+                .count()
+        };
+        assert_eq!(count("×"), 1);
+        assert_eq!(count("3"), 1);
+        let open = c
+            .shapes
+            .iter()
+            .filter(|s| matches!(s, Shape::Circle { filled: false, .. }))
+            .count();
+        assert_eq!(open, 2);
+
+        assert!(sheet("C Xyz", &tuning).is_err());
+    }
+}
@@ -0,0 +1,494 @@
+use crate::app::feedback_label;
+use crate::locale::{tr, tr_fill};
+use crate::random::Rng;
+use crate::review::{self, Reviews};
+use crate::spelling::Speller;
+use crate::synth::{PlaybackSettings, Player};
+use crate::theory::{self, PITCHES};
+use klib::core::pitch::Pitch;
+use std::time::Duration;
+
+// Keep the persisted history short, it's only there to look back at
+const MAX_HISTORY: usize = 50;
+// Gap between notes when arpeggiating
+const ARPEGGIO_GAP: Duration = Duration::from_millis(450);
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Debug)]
+enum Exercise {
+    Intervals,
+    Chords,
+    Inversions,
+}
+
+impl Exercise {
+    const ALL: [Exercise; 3] = [Exercise::Intervals, Exercise::Chords, Exercise::Inversions];
+
+    fn review_category(&self) -> &'static str {
+        match self {
+            Exercise::Intervals => "interval",
+            Exercise::Chords => "chord",
+            Exercise::Inversions => "inversion",
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Exercise::Intervals => "Intervals",
+            Exercise::Chords => "Chord qualities",
+            Exercise::Inversions => "Inversions",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, PartialOrd)]
+enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+        }
+    }
+}
+
+/// Something the user can answer with: its name, the semitones above the
+/// lowest note, and the lowest difficulty it shows up at.
+struct Answer {
+    name: &'static str,
+    semitones: &'static [i32],
+    difficulty: Difficulty,
+}
+
+const fn answer(name: &'static str, semitones: &'static [i32], difficulty: Difficulty) -> Answer {
+    Answer {
+        name,
+        semitones,
+        difficulty,
+    }
+}
+
+const INTERVALS: [Answer; 12] = [
+    answer("Minor 2nd", &[0, 1], Difficulty::Medium),
+    answer("Major 2nd", &[0, 2], Difficulty::Medium),
+    answer("Minor 3rd", &[0, 3], Difficulty::Easy),
+    answer("Major 3rd", &[0, 4], Difficulty::Easy),
+    answer("Perfect 4th", &[0, 5], Difficulty::Easy),
+    answer("Tritone", &[0, 6], Difficulty::Hard),
+    answer("Perfect 5th", &[0, 7], Difficulty::Easy),
+    answer("Minor 6th", &[0, 8], Difficulty::Medium),
+    answer("Major 6th", &[0, 9], Difficulty::Medium),
+    answer("Minor 7th", &[0, 10], Difficulty::Medium),
+    answer("Major 7th", &[0, 11], Difficulty::Medium),
+    answer("Octave", &[0, 12], Difficulty::Easy),
+];
+
+const CHORDS: [Answer; 6] = [
+    answer("maj7", &[0, 4, 7, 11], Difficulty::Easy),
+    answer("m7", &[0, 3, 7, 10], Difficulty::Easy),
+    answer("7", &[0, 4, 7, 10], Difficulty::Easy),
+    answer("m7♭5", &[0, 3, 6, 10], Difficulty::Medium),
+    answer("dim7", &[0, 3, 6, 9], Difficulty::Medium),
+    answer("aug", &[0, 4, 8], Difficulty::Hard),
+];
+
+// Inversions of a major triad, or of a dominant 7th at the hardest level
+const INVERSIONS: [Answer; 4] = [
+    answer("Root position", &[0, 4, 7], Difficulty::Easy),
+    answer("1st inversion", &[4, 7, 12], Difficulty::Easy),
+    answer("2nd inversion", &[7, 12, 16], Difficulty::Easy),
+    answer("3rd inversion", &[10, 12, 16, 19], Difficulty::Hard),
+];
+const SEVENTH_INVERSIONS: [&[i32]; 4] = [
+    &[0, 4, 7, 10],
+    &[4, 7, 10, 12],
+    &[7, 10, 12, 16],
+    &[10, 12, 16, 19],
+];
+
+fn answers(exercise: Exercise) -> &'static [Answer] {
+    match exercise {
+        Exercise::Intervals => &INTERVALS,
+        Exercise::Chords => &CHORDS,
+        Exercise::Inversions => &INVERSIONS,
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+struct HistoryEntry {
+    exercise: Exercise,
+    asked: String,
+    answered: String,
+    correct: bool,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+struct Score {
+    attempts: u32,
+    correct: u32,
+    streak: u32,
+    best_streak: u32,
+}
+
+struct Question {
+    answer: usize,
+    // MIDI note numbers, lowest first
+    notes: Vec<i32>,
+    asked_at: f64,
+    answered: bool,
+}
+
+/// Ear training for intervals, chord qualities and inversions.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub(crate) struct EarTraining {
+    pub(crate) open: bool,
+    exercise: Exercise,
+    difficulty: Difficulty,
+    history: Vec<HistoryEntry>,
+    // One per exercise, in the order of `Exercise::ALL`
+    scores: [Score; 3],
+
+    #[serde(skip)]
+    question: Option<Question>,
+    #[serde(skip)]
+    feedback: Option<(bool, String)>,
+    #[serde(skip)]
+    rng: Rng,
+}
+
+impl Default for EarTraining {
+    fn default() -> Self {
+        Self {
+            open: false,
+            exercise: Exercise::Intervals,
+            difficulty: Difficulty::Easy,
+            history: Vec::new(),
+            scores: Default::default(),
+            question: None,
+            feedback: None,
+            rng: Rng::default(),
+        }
+    }
+}
+
+impl EarTraining {
+    /// The notes of the last question, once it's been answered, so they can
+    /// be shown on the fretboard.
+    pub(crate) fn revealed_pitches(&self) -> Option<Vec<Pitch>> {
+        let question = self.question.as_ref().filter(|q| q.answered && self.open)?;
+        Some(
+            question
+                .notes
+                .iter()
+                .map(|n| PITCHES[n.rem_euclid(12) as usize])
+                .collect(),
+        )
+    }
+
+    /// Whether a question has been asked and not answered yet.
+    fn waiting(&self) -> bool {
+        self.question.as_ref().is_some_and(|q| !q.answered)
+    }
+
+    fn available(&self) -> Vec<usize> {
+        answers(self.exercise)
+            .iter()
+            .enumerate()
+            .filter(|(_, a)| a.difficulty <= self.difficulty)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn review_key(&self, answer: usize) -> String {
+        format!(
+            "{}:{}",
+            self.exercise.review_category(),
+            answers(self.exercise)[answer].name
+        )
+    }
+
+    fn ask(&mut self, answer: usize, now: f64) {
+        // Somewhere comfortable in the middle of a guitar's range
+        let lowest = 48 + self.rng.below(12) as i32;
+        let semitones = match (self.exercise, self.difficulty) {
+            (Exercise::Inversions, Difficulty::Hard) => SEVENTH_INVERSIONS[answer],
+            _ => answers(self.exercise)[answer].semitones,
+        };
+        self.question = Some(Question {
+            answer,
+            notes: semitones.iter().map(|s| lowest + s).collect(),
+            asked_at: now,
+            answered: false,
+        });
+    }
+
+    fn new_question(&mut self, reviews: &Reviews, now: f64) {
+        // Whatever is due for review comes first
+        let available = self.available();
+        let keys: Vec<String> = available.iter().map(|a| self.review_key(*a)).collect();
+        let last = self.question.as_ref().map(|q| self.review_key(q.answer));
+        let answer = available[reviews.pick(&keys, last.as_deref(), &mut self.rng)];
+        self.ask(answer, now);
+    }
+
+    fn play(&self, player: &mut Player, settings: &PlaybackSettings) {
+        let Some(question) = &self.question else {
+            return;
+        };
+
+        // Easy questions are arpeggiated first, hard intervals are only
+        // played together
+        let arpeggiate = match self.exercise {
+            Exercise::Intervals => self.difficulty != Difficulty::Hard,
+            _ => self.difficulty == Difficulty::Easy,
+        };
+        let mut delay = Duration::ZERO;
+        if arpeggiate {
+            for note in &question.notes {
+                player.play_after(delay, theory::frequency(*note), settings);
+                delay += ARPEGGIO_GAP;
+            }
+        }
+        if !(arpeggiate && self.exercise == Exercise::Intervals) {
+            for note in &question.notes {
+                player.play_after(delay, theory::frequency(*note), settings);
+            }
+        }
+    }
+
+    fn answer(&mut self, reviews: &mut Reviews, speller: &Speller, answer: usize, now: f64) {
+        let exercise = self.exercise;
+        let Some(question) = self.question.as_mut().filter(|q| !q.answered) else {
+            return;
+        };
+        question.answered = true;
+
+        let correct = question.answer == answer;
+        let seconds = (now - question.asked_at) as f32;
+        let asked_index = question.answer;
+        let asked = answers(exercise)[asked_index].name;
+        let notes: Vec<String> = question
+            .notes
+            .iter()
+            .map(|n| speller.name(theory::note_from_midi(*n), false))
+            .collect();
+
+        let score = &mut self.scores[Exercise::ALL
+            .iter()
+            .position(|e| *e == exercise)
+            .unwrap_or(0)];
+        score.attempts += 1;
+        if correct {
+            score.correct += 1;
+            score.streak += 1;
+            score.best_streak = score.best_streak.max(score.streak);
+        } else {
+            score.streak = 0;
+        }
+
+        reviews.record(
+            &self.review_key(asked_index),
+            review::quality(correct, seconds),
+        );
+
+        self.feedback = Some((
+            correct,
+            format!(
+                "{} {}: {}",
+                if correct { "✔" } else { "✘" },
+                tr(asked),
+                notes.join(" ")
+            ),
+        ));
+
+        self.history.push(HistoryEntry {
+            exercise,
+            asked: asked.to_owned(),
+            answered: answers(exercise)[answer].name.to_owned(),
+            correct,
+        });
+        if self.history.len() > MAX_HISTORY {
+            self.history.remove(0);
+        }
+    }
+
+    pub(crate) fn show(
+        &mut self,
+        ctx: &egui::Context,
+        player: &mut Player,
+        settings: &PlaybackSettings,
+        reviews: &mut Reviews,
+        speller: &Speller,
+    ) {
+        let mut open = self.open;
+        egui::Window::new(tr("Ear training"))
+            .id(egui::Id::new("ear_training"))
+            .open(&mut open)
+            .show(ctx, |ui| self.ui(ui, player, settings, reviews, speller));
+        self.open = open;
+    }
+
+    fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        player: &mut Player,
+        settings: &PlaybackSettings,
+        reviews: &mut Reviews,
+        speller: &Speller,
+    ) {
+        let now = ui.input(|i| i.time);
+
+        ui.horizontal(|ui| {
+            for exercise in Exercise::ALL {
+                if ui
+                    .selectable_value(&mut self.exercise, exercise, tr(exercise.name()))
+                    .changed()
+                {
+                    self.question = None;
+                    self.feedback = None;
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            for difficulty in Difficulty::ALL {
+                if ui
+                    .selectable_value(&mut self.difficulty, difficulty, tr(difficulty.name()))
+                    .changed()
+                {
+                    self.question = None;
+                }
+            }
+        });
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!self.waiting(), egui::Button::new(tr("▶ New question")))
+                .clicked()
+            {
+                self.rng.seed(now);
+                self.feedback = None;
+                self.new_question(reviews, now);
+                self.play(player, settings);
+            }
+            if ui
+                .add_enabled(self.question.is_some(), egui::Button::new(tr("🔁 Replay")))
+                .clicked()
+            {
+                self.play(player, settings);
+            }
+        });
+
+        let available = self.available();
+        ui.add_enabled_ui(self.waiting(), |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for i in available {
+                    if ui.button(tr(answers(self.exercise)[i].name)).clicked() {
+                        self.answer(reviews, speller, i, now);
+                    }
+                }
+            });
+        });
+
+        feedback_label(ui, &self.feedback);
+
+        ui.separator();
+        let score = &self.scores[Exercise::ALL
+            .iter()
+            .position(|e| *e == self.exercise)
+            .unwrap_or(0)];
+        if score.attempts > 0 {
+            ui.label(tr_fill(
+                "Score: {} of {} ({}%), streak {}, best streak {}",
+                &[
+                    &score.correct.to_string(),
+                    &score.attempts.to_string(),
+                    &format!(
+                        "{:.0}",
+                        100.0 * score.correct as f32 / score.attempts as f32
+                    ),
+                    &score.streak.to_string(),
+                    &score.best_streak.to_string(),
+                ],
+            ));
+        }
+
+        ui.collapsing(tr("History"), |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(150.0)
+                .show(ui, |ui| {
+                    for entry in self.history.iter().rev() {
+                        let mark = if entry.correct { "✔" } else { "✘" };
+                        let asked = format!(
+                            "{} {}: {}",
+                            mark,
+                            tr(entry.exercise.name()),
+                            tr(&entry.asked)
+                        );
+                        let text = if entry.correct {
+                            asked
+                        } else {
+                            tr_fill("{} (answered {})", &[&asked, tr(&entry.answered)])
+                        };
+                        ui.label(text);
+                    }
+                });
+            if ui.button(tr("Reset score and history")).clicked() {
+                self.history.clear();
+                self.scores = Default::default();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_and_reveal() {
+        let mut ear = EarTraining {
+            open: true,
+            ..Default::default()
+        };
+        let mut reviews = Reviews::default();
+        let fifth = INTERVALS
+            .iter()
+            .position(|a| a.name == "Perfect 5th")
+            .unwrap();
+
+        ear.ask(fifth, 0.0);
+        assert_eq!(ear.revealed_pitches(), None);
+        ear.answer(&mut reviews, &Speller::default(), fifth, 1.0);
+        let revealed = ear.revealed_pitches().unwrap();
+        assert_eq!(
+            pitch_class_distance(revealed[0], revealed[1]),
+            7,
+            "{:?}",
+            revealed
+        );
+
+        ear.ask(fifth, 2.0);
+        ear.answer(&mut reviews, &Speller::default(), fifth + 1, 3.0);
+        // Answering twice doesn't count twice
+        ear.answer(&mut reviews, &Speller::default(), fifth, 4.0);
+
+        let score = &ear.scores[0];
+        assert_eq!((score.attempts, score.correct), (2, 1));
+        assert_eq!((score.streak, score.best_streak), (0, 1));
+        assert_eq!(ear.history.len(), 2);
+        assert_eq!(ear.history[1].answered, "Minor 6th");
+    }
+
+    fn pitch_class_distance(from: Pitch, to: Pitch) -> usize {
+        (theory::pitch_class(to) + 12 - theory::pitch_class(from)) % 12
+    }
+}
@@ -0,0 +1,133 @@
+//! Saving exported files. Native builds write to the Downloads folder (or
+//! the working directory if there isn't one), web builds download the file
+//! through the browser.
+
+use crate::locale::tr_with;
+
+// How long a download's file is kept around after it starts, in milliseconds
+#[cfg(target_arch = "wasm32")]
+const REVOKE_DELAY_MS: i32 = 60_000;
+
+/// Save a file, returning where it went to show to the user.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn save_file(name: &str, _mime: &str, bytes: &[u8]) -> Result<String, String> {
+    let downloads = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| std::path::PathBuf::from(home).join("Downloads"))
+        .filter(|dir| dir.is_dir());
+    let dir = match downloads {
+        Some(dir) => dir,
+        None => std::env::current_dir().map_err(|e| e.to_string())?,
+    };
+
+    let path = free_path(&dir, name);
+    std::fs::write(&path, bytes).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(path.display().to_string())
+}
+
+/// Where to save a file so nothing's overwritten, adding " (1)", " (2)"
+/// and so on before the extension if the name's taken, like browsers do.
+#[cfg(not(target_arch = "wasm32"))]
+fn free_path(dir: &std::path::Path, name: &str) -> std::path::PathBuf {
+    let path = dir.join(name);
+    if !path.exists() {
+        return path;
+    }
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) => (stem, format!(".{}", extension)),
+        None => (name, String::new()),
+    };
+    (1..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+        .find(|path| !path.exists())
+        .unwrap_or(path)
+}
+
+/// Save a file, returning where it went to show to the user.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn save_file(name: &str, mime: &str, bytes: &[u8]) -> Result<String, String> {
+    use wasm_bindgen::JsCast;
+
+    let js_error = |e: wasm_bindgen::JsValue| format!("{:?}", e);
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+    let mut options = web_sys::BlobPropertyBag::new();
+    options.type_(mime);
+    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+        .map_err(js_error)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js_error)?;
+
+    // Clicking a link with a download attribute is the only way to save a
+    // file from a page
+    let window = web_sys::window().ok_or("no window to download from")?;
+    let document = window.document().ok_or("no document to download from")?;
+    let link: web_sys::HtmlAnchorElement = document
+        .create_element("a")
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(|_| "couldn't create a download link")?;
+    link.set_href(&url);
+    link.set_download(name);
+    link.click();
+
+    // Freeing the file right after the click cancels the download in some
+    // browsers, so give it time to start
+    let revoke = wasm_bindgen::closure::Closure::once_into_js(move || {
+        if let Err(e) = web_sys::Url::revoke_object_url(&url) {
+            log::error!("couldn't free a download: {:?}", e);
+        }
+    });
+    window
+        .set_timeout_with_callback_and_timeout_and_arguments_0(
+            revoke.unchecked_ref(),
+            REVOKE_DELAY_MS,
+        )
+        .map_err(js_error)?;
+    Ok(tr_with("your downloads as {}", name))
+}
+
+/// Save a file, describing how it went for `feedback_label`.
+pub(crate) fn save_feedback(name: &str, mime: &str, bytes: &[u8]) -> (bool, String) {
+    match save_file(name, mime, bytes) {
+        Ok(path) => (true, tr_with("Saved to {}", &path)),
+        Err(e) => {
+            log::error!("error saving {}: {}", name, e);
+            (false, e)
+        }
+    }
+}
+
+/// Turn something like a chord name into a file name that's safe everywhere.
+pub(crate) fn file_name(name: &str, extension: &str) -> String {
+    let mut file_name = String::new();
+    for c in name.chars() {
+        match c {
+            '♯' | '#' => file_name.push_str("sharp"),
+            '♭' => file_name.push('b'),
+            '/' => file_name.push_str("_over_"),
+            c if c.is_ascii_alphanumeric() || c == '-' || c == '_' => file_name.push(c),
+            ' ' => file_name.push('_'),
+            _ => {}
+        }
+    }
+    if file_name.is_empty() {
+        file_name.push_str("export");
+    }
+    format!("{}.{}", file_name, extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_free_path() {
+        let dir = std::env::temp_dir().join(format!("chord_finder_files_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(free_path(&dir, "Am.svg"), dir.join("Am.svg"));
+        std::fs::write(dir.join("Am.svg"), "").unwrap();
+        assert_eq!(free_path(&dir, "Am.svg"), dir.join("Am (1).svg"));
+        std::fs::write(dir.join("Am (1).svg"), "").unwrap();
+        assert_eq!(free_path(&dir, "Am.svg"), dir.join("Am (2).svg"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,251 @@
+//! Recently looked up chords and favourites, to pick again without typing.
+
+use crate::locale::{tr, tr_with};
+use crate::naming::Naming;
+use klib::core::base::Parsable;
+use klib::core::chord::Chord;
+
+// How many recent chords to keep
+const MAX_RECENT: usize = 24;
+// How long a typed chord has to be left alone to be remembered, so the
+// chords passed through while typing another aren't
+const SETTLE_SECONDS: f64 = 2.0;
+const CHIPS_WIDTH: f32 = 320.0;
+
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+#[serde(default)]
+pub(crate) struct History {
+    /// Most recent first
+    recent: Vec<String>,
+    favourites: Vec<String>,
+
+    #[serde(skip)]
+    typing: Option<Typing>,
+}
+
+/// The chord in the text box, and whether it's been remembered yet.
+struct Typing {
+    chord: String,
+    since: f64,
+    recorded: bool,
+}
+
+impl History {
+    /// Remember a chord, moving it to the front if it's already there.
+    /// Chords that don't parse aren't kept.
+    pub(crate) fn record(&mut self, chord: &str) {
+        if chord.is_empty() || Chord::parse(chord).is_err() {
+            return;
+        }
+        self.recent.retain(|c| c != chord);
+        self.recent.insert(0, chord.to_owned());
+        self.recent.truncate(MAX_RECENT);
+    }
+
+    /// Keep track of the chord being typed, remembering it once it's been
+    /// left alone for a moment or when typing's done. Returns whether it's
+    /// still waiting to be remembered.
+    fn typed(&mut self, chord: &str, now: f64, done: bool) -> bool {
+        if self
+            .typing
+            .as_ref()
+            .map_or(true, |typing| typing.chord != chord)
+        {
+            self.typing = Some(Typing {
+                chord: chord.to_owned(),
+                since: now,
+                recorded: false,
+            });
+        }
+        let Some(typing) = &mut self.typing else {
+            return false;
+        };
+        if typing.recorded {
+            return false;
+        }
+        if done || now - typing.since >= SETTLE_SECONDS {
+            typing.recorded = true;
+            let chord = typing.chord.clone();
+            self.record(&chord);
+            return false;
+        }
+        true
+    }
+
+    pub(crate) fn is_favourite(&self, chord: &str) -> bool {
+        self.favourites.iter().any(|c| c == chord)
+    }
+
+    pub(crate) fn toggle_favourite(&mut self, chord: &str) {
+        if self.is_favourite(chord) {
+            self.favourites.retain(|c| c != chord);
+        } else if Chord::parse(chord).is_ok() {
+            self.favourites.push(chord.to_owned());
+        }
+    }
+
+    /// Favourites, then recent chords that aren't favourites.
+    fn chips(&self) -> Vec<(&str, bool)> {
+        let favourites = self.favourites.iter().map(|c| (c.as_str(), true));
+        let recent = self
+            .recent
+            .iter()
+            .filter(|c| !self.is_favourite(c))
+            .map(|c| (c.as_str(), false));
+        favourites.chain(recent).collect()
+    }
+
+    /// Chips for the favourites and recent chords, under the chord text box.
+    /// Down from the text box focuses the chips, left and right move between
+    /// them, Enter picks one and Escape goes back to the text box. Chords
+    /// are kept in English letters and shown in the naming system, and the
+    /// chord typed is remembered once it's settled. Returns the chord
+    /// picked.
+    pub(crate) fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        text_box: &egui::Response,
+        chord: &str,
+        naming: &Naming,
+    ) -> Option<String> {
+        use egui::Key;
+
+        if text_box.has_focus() || text_box.lost_focus() {
+            let now = ui.input(|i| i.time);
+            if self.typed(chord, now, text_box.lost_focus()) {
+                let settle = std::time::Duration::from_secs_f64(SETTLE_SECONDS);
+                ui.ctx().request_repaint_after(settle);
+            }
+        }
+
+        let mut picked = None;
+        let mut toggle = None;
+        let mut forget = None;
+        let mut ids = Vec::new();
+        ui.allocate_ui(egui::vec2(CHIPS_WIDTH, 0.0), |ui| {
+            ui.horizontal_wrapped(|ui| {
+                let valid = !chord.is_empty() && Chord::parse(chord).is_ok();
+                let favourite = self.is_favourite(chord);
+                let shown = naming.chord(chord);
+                let (star, hover) = if favourite {
+                    ("★", tr_with("Remove {} from favourites", &shown))
+                } else {
+                    ("☆", tr_with("Add {} to favourites", &shown))
+                };
+                if ui
+                    .add_enabled(valid, egui::Button::new(star).frame(false))
+                    .on_hover_text(hover)
+                    .clicked()
+                {
+                    toggle = Some(chord.to_owned());
+                }
+
+                for (name, favourite) in self.chips() {
+                    let text = if favourite {
+                        egui::RichText::new(naming.chord(name)).strong()
+                    } else {
+                        egui::RichText::new(naming.chord(name))
+                    };
+                    let response = ui.add(egui::Button::new(text).small().rounding(8.0));
+                    if response.clicked() {
+                        picked = Some(name.to_owned());
+                    }
+                    ids.push(response.id);
+                    response.context_menu(|ui| {
+                        let label = if favourite {
+                            tr("Remove from favourites")
+                        } else {
+                            tr("Add to favourites")
+                        };
+                        if ui.button(label).clicked() {
+                            toggle = Some(name.to_owned());
+                            ui.close_menu();
+                        }
+                        if !favourite && ui.button(tr("Remove from history")).clicked() {
+                            forget = Some(name.to_owned());
+                            ui.close_menu();
+                        }
+                    });
+                }
+            });
+        });
+
+        // Keyboard navigation between the text box and the chips. The
+        // completions under the text box are shown first, and leave Down
+        // for this once it's past their last one.
+        let focused = ids.iter().position(|id| ui.memory(|m| m.has_focus(*id)));
+        let pressed = |key| ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, key));
+        match focused {
+            None if text_box.has_focus() && !ids.is_empty() && pressed(Key::ArrowDown) => {
+                ui.memory_mut(|m| m.request_focus(ids[0]));
+            }
+            Some(i) if pressed(Key::ArrowRight) => {
+                ui.memory_mut(|m| m.request_focus(ids[(i + 1) % ids.len()]));
+            }
+            Some(i) if pressed(Key::ArrowLeft) => {
+                ui.memory_mut(|m| m.request_focus(ids[(i + ids.len() - 1) % ids.len()]));
+            }
+            Some(_) if pressed(Key::Escape) || pressed(Key::ArrowUp) => {
+                ui.memory_mut(|m| m.request_focus(text_box.id));
+            }
+            _ => {}
+        }
+
+        if let Some(chord) = toggle {
+            self.toggle_favourite(&chord);
+        }
+        if let Some(chord) = forget {
+            self.recent.retain(|c| *c != chord);
+        }
+        if let Some(chord) = &picked {
+            self.record(chord);
+        }
+        picked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let mut history = History::default();
+        for chord in ["C", "Am", "C", "Hm", "G7"] {
+            history.record(chord);
+        }
+        assert_eq!(history.recent, ["G7", "C", "Am"]);
+
+        let qualities = ["", "m", "7", "maj7", "m7", "sus4", "sus2", "dim"];
+        let roots = ["D", "E", "F", "A"];
+        for root in roots {
+            for quality in qualities {
+                history.record(&format!("{}{}", root, quality));
+            }
+        }
+        assert!(roots.len() * qualities.len() > MAX_RECENT);
+        assert_eq!(history.recent.len(), MAX_RECENT);
+        assert_eq!(history.recent[0], "Adim");
+        // The oldest ones were dropped
+        assert!(!history.recent.iter().any(|c| c == "G7" || c == "D"));
+
+        // A typed chord is remembered once it's settled, not on the way
+        let mut history = History::default();
+        assert!(history.typed("C", 0.0, false));
+        assert!(history.typed("Cm", 0.5, false));
+        assert!(!history.typed("Cm", 0.5 + SETTLE_SECONDS, false));
+        assert!(!history.typed("Cm7", 3.0, true));
+        assert_eq!(history.recent, ["Cm7", "Cm"]);
+
+        history.toggle_favourite("Am");
+        history.toggle_favourite("Hm");
+        assert_eq!(history.chips()[0], ("Am", true));
+        assert_eq!(
+            history.chips().iter().filter(|(c, _)| *c == "Am").count(),
+            1
+        );
+        history.toggle_favourite("Am");
+        assert!(!history.is_favourite("Am"));
+        assert!(history.favourites.is_empty());
+    }
+}
@@ -1,4 +1,35 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
+mod analysis;
 mod app;
+mod chordpro;
+mod complete;
+mod diagram;
+mod ear;
+mod files;
+mod history;
+mod library;
+mod locale;
+mod lyrics;
+mod metronome;
+mod musicxml;
+mod naming;
+mod performance;
+mod piano;
+mod png;
+mod progression;
+mod quiz;
+mod random;
+mod review;
+mod shapes;
+mod snapshot;
+mod song;
+mod spelling;
+mod staff;
+mod symbol;
+mod synth;
+mod tab;
+mod theory;
+mod timeline;
+mod voicing;
 pub use app::TemplateApp;
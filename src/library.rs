@@ -0,0 +1,679 @@
+//! The song library: every song the band plays, with its sections'
+//! progressions, saved with the rest of the app's state.
+
+use crate::app::feedback_label;
+use crate::locale::{tr, tr_fill, tr_with};
+use crate::progression::Progression;
+use crate::song::{chord_name, Line, Song};
+
+/// A part of a song, like a verse or chorus, and the chords played in it.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub(crate) struct LibrarySection {
+    pub(crate) name: String,
+    pub(crate) progression: Progression,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub(crate) struct LibrarySong {
+    /// Stays the same when the song is edited, so setlists can refer to it
+    /// even when two songs share a title. Given when it's added to the
+    /// library.
+    pub(crate) id: u64,
+    pub(crate) title: String,
+    pub(crate) artist: String,
+    pub(crate) key: Option<String>,
+    /// Beats per minute
+    pub(crate) tempo: u32,
+    pub(crate) capo: u32,
+    /// How the guitar is tuned, like "Drop D", just as a reminder
+    pub(crate) tuning: String,
+    pub(crate) sections: Vec<LibrarySection>,
+}
+
+impl Default for LibrarySong {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            title: String::new(),
+            artist: String::new(),
+            key: None,
+            tempo: 120,
+            capo: 0,
+            tuning: "Standard".to_owned(),
+            sections: Vec::new(),
+        }
+    }
+}
+
+impl LibrarySong {
+    /// Every chord in the song, in the order of its sections.
+    pub(crate) fn chords(&self) -> impl Iterator<Item = &str> {
+        self.sections
+            .iter()
+            .flat_map(|section| &section.progression.chords)
+            .map(|chord| chord.name.as_str())
+    }
+
+    /// Whether the song's title or artist contains the search, or it's in
+    /// the key searched for, or has the chord searched for.
+    pub(crate) fn matches(&self, search: &str) -> bool {
+        let search = search.trim();
+        if search.is_empty() {
+            return true;
+        }
+        let lower = search.to_lowercase();
+        if self.title.to_lowercase().contains(&lower) || self.artist.to_lowercase().contains(&lower)
+        {
+            return true;
+        }
+        let Some(chord) = chord_name(search) else {
+            return false;
+        };
+        self.key.as_deref() == Some(chord.as_str()) || self.chords().any(|c| c == chord)
+    }
+
+    /// Take a song from a sheet into the library, with a section for each of
+    /// the sheet's sections, or just one if it has none.
+    pub(crate) fn from_song(song: &Song) -> Result<Self, String> {
+        let to_section = |name: String, chords: Vec<String>| {
+            let progression =
+                Progression::parse(&chords.join(" ")).map_err(|e| format!("{}: {}", name, e))?;
+            Ok(LibrarySection { name, progression })
+        };
+        let mut sections = song
+            .sections()
+            .into_iter()
+            .filter(|section| !section.chords.is_empty())
+            .map(|section| to_section(section.label, section.chords))
+            .collect::<Result<Vec<_>, String>>()?;
+        if sections.is_empty() {
+            sections.push(to_section("Song".to_owned(), song.chords())?);
+        }
+
+        let tempo = song.lines.iter().find_map(|line| match line {
+            Line::Directive { name, value } if name == "tempo" => value.parse().ok(),
+            _ => None,
+        });
+        let defaults = Self::default();
+        Ok(Self {
+            title: song.title.clone(),
+            artist: song.artist.clone(),
+            key: song.key.clone(),
+            tempo: tempo.unwrap_or(defaults.tempo),
+            capo: song.capo,
+            sections,
+            ..defaults
+        })
+    }
+
+    /// The song as a sheet, to step through in the song window.
+    pub(crate) fn to_song(&self) -> Song {
+        let mut lines = vec![Line::Directive {
+            name: "tempo".to_owned(),
+            value: self.tempo.to_string(),
+        }];
+        for section in &self.sections {
+            // Sections ChordPro doesn't have a name for are verses
+            let kind: String = section
+                .name
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .filter(char::is_ascii_alphabetic)
+                .collect::<String>()
+                .to_lowercase();
+            let kind = match kind.as_str() {
+                "chorus" | "bridge" | "verse" => kind.as_str(),
+                _ => "verse",
+            };
+            lines.push(Line::Directive {
+                name: format!("start_of_{}", kind),
+                value: section.name.clone(),
+            });
+            lines.extend(Song::from_progression(&section.progression).lines);
+            lines.push(Line::Directive {
+                name: format!("end_of_{}", kind),
+                value: String::new(),
+            });
+        }
+        Song {
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            key: self.key.clone(),
+            capo: self.capo,
+            lines,
+            ..Default::default()
+        }
+    }
+}
+
+/// A song being edited, as typed in.
+#[derive(Clone, Default)]
+struct Draft {
+    /// Where the song is in the library, or none for a new song
+    index: Option<usize>,
+    title: String,
+    artist: String,
+    key: String,
+    tempo: u32,
+    capo: u32,
+    tuning: String,
+    /// Each section's name and progression
+    sections: Vec<(String, String)>,
+}
+
+impl Draft {
+    fn new(index: Option<usize>, song: &LibrarySong) -> Self {
+        Self {
+            index,
+            title: song.title.clone(),
+            artist: song.artist.clone(),
+            key: song.key.clone().unwrap_or_default(),
+            tempo: song.tempo,
+            capo: song.capo,
+            tuning: song.tuning.clone(),
+            sections: song
+                .sections
+                .iter()
+                .map(|section| (section.name.clone(), section.progression.text()))
+                .collect(),
+        }
+    }
+
+    fn song(&self) -> Result<LibrarySong, String> {
+        let title = self.title.trim();
+        if title.is_empty() {
+            return Err(tr("The song needs a title").to_owned());
+        }
+        let key = match self.key.trim() {
+            "" => None,
+            key => Some(chord_name(key).ok_or_else(|| tr_with("Invalid key: {}", key))?),
+        };
+        let mut sections = Vec::new();
+        for (name, text) in &self.sections {
+            let name = name.trim();
+            let name = if name.is_empty() { tr("Verse") } else { name };
+            let progression = Progression::parse(text).map_err(|e| format!("{}: {}", name, e))?;
+            sections.push(LibrarySection {
+                name: name.to_owned(),
+                progression,
+            });
+        }
+        Ok(LibrarySong {
+            title: title.to_owned(),
+            artist: self.artist.trim().to_owned(),
+            key,
+            tempo: self.tempo,
+            capo: self.capo,
+            tuning: self.tuning.trim().to_owned(),
+            sections,
+            ..Default::default()
+        })
+    }
+}
+
+/// Songs in the order they're played at a gig, by their ids.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub(crate) struct Setlist {
+    pub(crate) name: String,
+    pub(crate) songs: Vec<u64>,
+}
+
+/// What to do with something picked in the library.
+pub(crate) enum Chosen {
+    /// Step through a song in the song window
+    Song(Song),
+    /// Perform a setlist's songs
+    Setlist(Vec<LibrarySong>),
+}
+
+/// The "Song library" window, to find, edit and open saved songs, and put
+/// them in setlists.
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+#[serde(default)]
+pub(crate) struct Library {
+    pub(crate) open: bool,
+    songs: Vec<LibrarySong>,
+    search: String,
+    setlists: Vec<Setlist>,
+    showing_setlists: bool,
+    /// The setlist being edited
+    setlist: usize,
+
+    #[serde(skip)]
+    draft: Option<Draft>,
+    #[serde(skip)]
+    message: Option<(bool, String)>,
+}
+
+impl Library {
+    /// Show the library window. Returns a song to open in the song window,
+    /// or a setlist to perform.
+    pub(crate) fn show(&mut self, ctx: &egui::Context, current: Option<&Song>) -> Option<Chosen> {
+        let mut open = self.open;
+        let mut chosen = None;
+        egui::Window::new(tr("Song library"))
+            .id(egui::Id::new("song_library"))
+            .open(&mut open)
+            .default_width(450.0)
+            .show(ctx, |ui| {
+                if self.draft.is_some() {
+                    self.edit_ui(ui);
+                } else {
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut self.showing_setlists, false, tr("Songs"));
+                        ui.selectable_value(&mut self.showing_setlists, true, tr("Setlists"));
+                    });
+                    ui.separator();
+                    chosen = if self.showing_setlists {
+                        self.setlists_ui(ui)
+                    } else {
+                        self.list_ui(ui, current).map(Chosen::Song)
+                    };
+                }
+                feedback_label(ui, &self.message);
+            });
+        self.open = open;
+        chosen
+    }
+
+    /// Keep a song, replacing the one at `index` and keeping its id. Songs
+    /// are kept in title order.
+    fn save(&mut self, index: Option<usize>, mut song: LibrarySong) {
+        match index {
+            Some(index) if index < self.songs.len() => {
+                song.id = self.songs[index].id;
+                self.songs[index] = song;
+            }
+            _ => {
+                song.id = self.songs.iter().map(|song| song.id).max().unwrap_or(0) + 1;
+                self.songs.push(song);
+            }
+        }
+        self.songs
+            .sort_by_key(|song| (song.title.to_lowercase(), song.artist.to_lowercase()));
+    }
+
+    fn list_ui(&mut self, ui: &mut egui::Ui, current: Option<&Song>) -> Option<Song> {
+        ui.horizontal(|ui| {
+            if ui.button(tr("➕ New song")).clicked() {
+                self.draft = Some(Draft::new(None, &LibrarySong::default()));
+                self.message = None;
+            }
+            if let Some(song) = current {
+                if ui
+                    .button(tr("Add the open song"))
+                    .on_hover_text(tr("Add the song from the song window, with its sections"))
+                    .clicked()
+                {
+                    match LibrarySong::from_song(song) {
+                        Ok(song) => {
+                            self.save(None, song);
+                            self.message = Some((true, tr("Added to the library").to_owned()));
+                        }
+                        Err(e) => {
+                            log::warn!("couldn't add {} to the library: {}", song.title, e);
+                            self.message = Some((false, e));
+                        }
+                    }
+                }
+            }
+        });
+        ui.add(
+            egui::TextEdit::singleline(&mut self.search)
+                .hint_text(tr("Search by title, artist, key or chord")),
+        );
+
+        let mut chosen = None;
+        let mut edit = None;
+        let mut delete = None;
+        let found: Vec<usize> = (0..self.songs.len())
+            .filter(|i| self.songs[*i].matches(&self.search))
+            .collect();
+        ui.label(tr_fill(
+            "{} of {} songs",
+            &[&found.len().to_string(), &self.songs.len().to_string()],
+        ));
+        egui::ScrollArea::vertical()
+            .max_height(400.0)
+            .show(ui, |ui| {
+                egui::Grid::new("library").striped(true).show(ui, |ui| {
+                    for i in found {
+                        let song = &self.songs[i];
+                        ui.vertical(|ui| {
+                            ui.strong(&song.title);
+                            if !song.artist.is_empty() {
+                                ui.label(egui::RichText::new(&song.artist).weak());
+                            }
+                        });
+                        ui.label(song.key.as_deref().unwrap_or(""));
+                        ui.label(tr_with("{} BPM", &song.tempo.to_string()));
+                        if ui.button(tr("📂 Open")).clicked() {
+                            chosen = Some(song.to_song());
+                        }
+                        if ui.button(tr("Edit")).clicked() {
+                            edit = Some(i);
+                        }
+                        if ui.button("🗑").on_hover_text(tr("Delete")).clicked() {
+                            delete = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+
+        if let Some(i) = edit {
+            self.draft = Some(Draft::new(Some(i), &self.songs[i]));
+            self.message = None;
+        }
+        if let Some(i) = delete {
+            let song = self.songs.remove(i);
+            for setlist in &mut self.setlists {
+                setlist.songs.retain(|id| *id != song.id);
+            }
+            self.message = Some((true, tr_with("Deleted {}", &song.title)));
+        }
+        chosen
+    }
+
+    fn song(&self, id: u64) -> Option<&LibrarySong> {
+        self.songs.iter().find(|song| song.id == id)
+    }
+
+    /// The songs in a setlist, leaving out any no longer in the library.
+    fn setlist_songs(&self, setlist: &Setlist) -> Vec<LibrarySong> {
+        setlist
+            .songs
+            .iter()
+            .filter_map(|id| self.song(*id))
+            .cloned()
+            .collect()
+    }
+
+    fn setlists_ui(&mut self, ui: &mut egui::Ui) -> Option<Chosen> {
+        ui.horizontal(|ui| {
+            if !self.setlists.is_empty() {
+                let selected = self.setlist.min(self.setlists.len() - 1);
+                egui::ComboBox::from_id_source("setlist")
+                    .selected_text(&self.setlists[selected].name)
+                    .show_ui(ui, |ui| {
+                        for (i, setlist) in self.setlists.iter().enumerate() {
+                            ui.selectable_value(&mut self.setlist, i, &setlist.name);
+                        }
+                    });
+            }
+            if ui.button(tr("➕ New setlist")).clicked() {
+                self.setlists.push(Setlist {
+                    name: tr_with("Setlist {}", &(self.setlists.len() + 1).to_string()),
+                    songs: Vec::new(),
+                });
+                self.setlist = self.setlists.len() - 1;
+            }
+        });
+        if self.setlists.is_empty() {
+            ui.label(tr("Make a setlist to put songs in the order you play them"));
+            return None;
+        }
+        self.setlist = self.setlist.min(self.setlists.len() - 1);
+
+        let mut chosen = None;
+        let mut delete = false;
+        ui.horizontal(|ui| {
+            ui.label(tr("Name"));
+            ui.text_edit_singleline(&mut self.setlists[self.setlist].name);
+            if ui
+                .button("🗑")
+                .on_hover_text(tr("Delete the setlist"))
+                .clicked()
+            {
+                delete = true;
+            }
+        });
+
+        let setlist = &self.setlists[self.setlist];
+        let titles: Vec<String> = setlist
+            .songs
+            .iter()
+            .map(|id| {
+                self.song(*id)
+                    .map_or_else(String::new, |song| song.title.clone())
+            })
+            .collect();
+        let setlist = &mut self.setlists[self.setlist];
+        let mut move_up = None;
+        let mut remove = None;
+        egui::ScrollArea::vertical()
+            .max_height(400.0)
+            .show(ui, |ui| {
+                for (i, title) in titles.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{}.", i + 1));
+                        if ui
+                            .add_enabled(i > 0, egui::Button::new("⏶"))
+                            .on_hover_text(tr("Play earlier"))
+                            .clicked()
+                        {
+                            move_up = Some(i);
+                        }
+                        if ui
+                            .add_enabled(i + 1 < setlist.songs.len(), egui::Button::new("⏷"))
+                            .on_hover_text(tr("Play later"))
+                            .clicked()
+                        {
+                            move_up = Some(i + 1);
+                        }
+                        if ui
+                            .button("🗑")
+                            .on_hover_text(tr("Take out of the setlist"))
+                            .clicked()
+                        {
+                            remove = Some(i);
+                        }
+                        ui.label(title);
+                    });
+                }
+            });
+        if let Some(i) = move_up {
+            setlist.songs.swap(i - 1, i);
+        }
+        if let Some(i) = remove {
+            setlist.songs.remove(i);
+        }
+
+        let mut add = None;
+        egui::ComboBox::from_id_source("setlist_add")
+            .selected_text(tr("Add a song"))
+            .show_ui(ui, |ui| {
+                for song in &self.songs {
+                    if ui.selectable_label(false, &song.title).clicked() {
+                        add = Some(song.id);
+                    }
+                }
+            });
+        if let Some(id) = add {
+            setlist.songs.push(id);
+        }
+
+        if ui
+            .add_enabled(
+                !setlist.songs.is_empty(),
+                egui::Button::new(tr("▶ Perform")),
+            )
+            .on_hover_text(tr("Show the chords full screen, to play along on stage"))
+            .clicked()
+        {
+            let songs = self.setlist_songs(&self.setlists[self.setlist]);
+            if songs.iter().all(|song| song.chords().next().is_none()) {
+                self.message = Some((false, tr("None of the songs have chords yet").to_owned()));
+            } else {
+                chosen = Some(Chosen::Setlist(songs));
+            }
+        }
+
+        if delete {
+            let setlist = self.setlists.remove(self.setlist);
+            self.message = Some((true, tr_with("Deleted {}", &setlist.name)));
+        }
+        chosen
+    }
+
+    fn edit_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(draft) = &mut self.draft else {
+            return;
+        };
+        egui::Grid::new("library_song").show(ui, |ui| {
+            ui.label(tr("Title"));
+            ui.text_edit_singleline(&mut draft.title);
+            ui.end_row();
+            ui.label(tr("Artist"));
+            ui.text_edit_singleline(&mut draft.artist);
+            ui.end_row();
+            ui.label(tr("Key"));
+            ui.add(egui::TextEdit::singleline(&mut draft.key).hint_text(tr("e.g. Am")));
+            ui.end_row();
+            ui.label(tr("Tempo"));
+            ui.add(
+                egui::DragValue::new(&mut draft.tempo)
+                    .clamp_range(20..=300)
+                    .suffix(tr(" BPM")),
+            );
+            ui.end_row();
+            ui.label(tr("Capo"));
+            ui.add(egui::DragValue::new(&mut draft.capo).clamp_range(0..=12));
+            ui.end_row();
+            ui.label(tr("Tuning"));
+            ui.add(egui::TextEdit::singleline(&mut draft.tuning).hint_text(tr("e.g. Drop D")));
+            ui.end_row();
+        });
+
+        ui.separator();
+        ui.label(tr("Sections (chord:bars)"));
+        let mut remove = None;
+        for (i, (name, text)) in draft.sections.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(name)
+                        .hint_text(tr("Verse"))
+                        .desired_width(80.0),
+                );
+                ui.add(egui::TextEdit::singleline(text).hint_text("Am7:2 D7:2 G:4"));
+                if ui
+                    .button("🗑")
+                    .on_hover_text(tr("Remove the section"))
+                    .clicked()
+                {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            draft.sections.remove(i);
+        }
+        if ui.button(tr("➕ Section")).clicked() {
+            let name = if draft.sections.is_empty() {
+                tr("Verse")
+            } else {
+                tr("Chorus")
+            };
+            draft.sections.push((name.to_owned(), String::new()));
+        }
+
+        ui.separator();
+        let (save, cancel) = ui
+            .horizontal(|ui| {
+                (
+                    ui.button(tr("💾 Save")).clicked(),
+                    ui.button(tr("Cancel")).clicked(),
+                )
+            })
+            .inner;
+        if save {
+            let index = draft.index;
+            match draft.song() {
+                Ok(song) => {
+                    self.draft = None;
+                    self.save(index, song);
+                    self.message = Some((true, tr("Saved").to_owned()));
+                }
+                Err(e) => self.message = Some((false, e)),
+            }
+        }
+        if cancel {
+            self.draft = None;
+            self.message = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_library_song() {
+        let (sheet, _) = crate::chordpro::parse(
+            "{title: Hello}
+{key: Am}
+{tempo: 96}
+{start_of_verse: Verse}
+[Am]Hello [F]there
+{end_of_verse}
+{start_of_chorus}
+[C]La [G]la
+{end_of_chorus}",
+        );
+        let song = LibrarySong::from_song(&sheet).unwrap();
+        assert_eq!(song.tempo, 96);
+        let sections: Vec<(&str, String)> = song
+            .sections
+            .iter()
+            .map(|s| (s.name.as_str(), s.progression.text()))
+            .collect();
+        assert_eq!(
+            sections,
+            [("Verse", "Am F".to_owned()), ("Chorus", "C G".to_owned())]
+        );
+
+        assert!(song.matches("hel"));
+        assert!(song.matches("Am"));
+        assert!(song.matches("G"));
+        assert!(!song.matches("Dm"));
+
+        // Opening it and adding it back gives the same song
+        assert_eq!(LibrarySong::from_song(&song.to_song()).unwrap(), song);
+
+        let mut draft = Draft::new(None, &song);
+        draft.sections[1].1 = "C Hm".to_owned();
+        assert!(draft
+            .song()
+            .unwrap_err()
+            .starts_with("Chorus: Invalid chord Hm"));
+
+        // Setlists keep the right song when two share a title
+        let mut library = Library::default();
+        let cover = |artist: &str| LibrarySong {
+            artist: artist.to_owned(),
+            ..song.clone()
+        };
+        library.save(None, cover("Them"));
+        library.save(None, cover("Us"));
+        let ours = library.songs[1].id;
+        library.setlists.push(Setlist {
+            name: "Gig".to_owned(),
+            songs: vec![ours],
+        });
+        let renamed = LibrarySong {
+            title: "Goodbye".to_owned(),
+            ..cover("Them")
+        };
+        library.save(Some(0), renamed);
+        let setlist = library.setlists[0].clone();
+        let songs = library.setlist_songs(&setlist);
+        assert_eq!(songs.len(), 1);
+        assert_eq!((songs[0].title.as_str(), songs[0].id), ("Hello", ours));
+    }
+}
@@ -0,0 +1,221 @@
+//! Translations of the UI, from message catalogues built into the app. The
+//! English text is the key, so anything without a translation is shown in
+//! English.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum Language {
+    #[default]
+    English,
+    Spanish,
+    French,
+    German,
+    Japanese,
+}
+
+impl Language {
+    pub(crate) const ALL: [Language; 5] = [
+        Language::English,
+        Language::Spanish,
+        Language::French,
+        Language::German,
+        Language::Japanese,
+    ];
+
+    /// The language's name in itself, for picking it.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::Spanish => "Español",
+            Language::French => "Français",
+            Language::German => "Deutsch",
+            Language::Japanese => "日本語",
+        }
+    }
+
+    fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Spanish => "es",
+            Language::French => "fr",
+            Language::German => "de",
+            Language::Japanese => "ja",
+        }
+    }
+
+    fn catalogue(self) -> &'static str {
+        match self {
+            Language::English => "{}",
+            Language::Spanish => include_str!("../assets/locales/es.ron"),
+            Language::French => include_str!("../assets/locales/fr.ron"),
+            Language::German => include_str!("../assets/locales/de.ron"),
+            Language::Japanese => include_str!("../assets/locales/ja.ron"),
+        }
+    }
+
+    /// Whether the app has a font for the language's script. Japanese needs
+    /// one from the system, so isn't offered without it, like on the web.
+    pub(crate) fn displayable(self) -> bool {
+        self != Language::Japanese || cjk_font().is_some()
+    }
+
+    /// The language for a locale like `fr-CA` or `de_DE.UTF-8`, if there's a
+    /// catalogue for it.
+    pub(crate) fn from_locale(locale: &str) -> Option<Self> {
+        let code = locale.get(..2)?.to_ascii_lowercase();
+        let rest = &locale[2..];
+        if !(rest.is_empty() || rest.starts_with(['-', '_', '.'])) {
+            return None;
+        }
+        Self::ALL.into_iter().find(|l| l.code() == code)
+    }
+}
+
+// The language being shown, as its index in `Language::ALL`
+static LANGUAGE: AtomicUsize = AtomicUsize::new(0);
+static CATALOGUES: OnceLock<Vec<HashMap<String, String>>> = OnceLock::new();
+
+fn catalogues() -> &'static [HashMap<String, String>] {
+    CATALOGUES.get_or_init(|| {
+        Language::ALL
+            .iter()
+            .map(|language| {
+                ron::from_str(language.catalogue()).unwrap_or_else(|e| {
+                    log::error!("Couldn't read the {} catalogue: {}", language.code(), e);
+                    HashMap::new()
+                })
+            })
+            .collect()
+    })
+}
+
+pub(crate) fn set_language(language: Language) {
+    let index = Language::ALL.iter().position(|l| *l == language);
+    LANGUAGE.store(index.unwrap_or_default(), Ordering::Relaxed);
+}
+
+/// The text in the language being shown.
+pub(crate) fn tr(text: &str) -> &str {
+    catalogues()[LANGUAGE.load(Ordering::Relaxed)]
+        .get(text)
+        .map_or(text, String::as_str)
+}
+
+/// The text in the language being shown, with `{}` filled in.
+pub(crate) fn tr_with(text: &str, value: &str) -> String {
+    tr(text).replacen("{}", value, 1)
+}
+
+/// The text in the language being shown, with each `{}` filled in turn.
+pub(crate) fn tr_fill(text: &str, values: &[&str]) -> String {
+    fill(tr(text), values)
+}
+
+/// Fill in each `{}` in turn, for text that's already translated or
+/// shouldn't be.
+pub(crate) fn fill(text: &str, values: &[&str]) -> String {
+    values
+        .iter()
+        .fold(text.to_owned(), |text, value| text.replacen("{}", value, 1))
+}
+
+/// The system's language, or the browser's on the web, if there's a
+/// catalogue and a font for it.
+pub(crate) fn detect() -> Language {
+    #[cfg(target_arch = "wasm32")]
+    let locale = web_sys::window().and_then(|w| w.navigator().language());
+    // The first of these that's set is the one that counts
+    #[cfg(not(target_arch = "wasm32"))]
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty());
+
+    locale
+        .and_then(|locale| Language::from_locale(&locale))
+        .filter(|language| language.displayable())
+        .unwrap_or_default()
+}
+
+/// Japanese needs a font with kana and kanji, which is too big to build into
+/// the app, so use one the system has. It's read once and kept.
+pub(crate) fn cjk_font() -> Option<egui::FontData> {
+    static FONT: OnceLock<Option<&'static [u8]>> = OnceLock::new();
+    let font =
+        FONT.get_or_init(|| read_cjk_font().map(|font| &*Box::leak(font.into_boxed_slice())));
+    font.map(egui::FontData::from_static)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_cjk_font() -> Option<Vec<u8>> {
+    const PATHS: [&str; 8] = [
+        "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+        "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+        "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+        "/usr/share/fonts/truetype/takao-gothic/TakaoGothic.ttf",
+        "/System/Library/Fonts/ヒラギノ角ゴシック W3.ttc",
+        "/System/Library/Fonts/Hiragino Sans GB.ttc",
+        "C:\\Windows\\Fonts\\YuGothM.ttc",
+        "C:\\Windows\\Fonts\\msgothic.ttc",
+    ];
+    let font = PATHS.iter().find_map(|path| std::fs::read(path).ok());
+    if font.is_none() {
+        log::info!("Couldn't find a Japanese font, so Japanese isn't offered");
+    }
+    font
+}
+
+/// Browsers don't let pages read system fonts, so Japanese isn't offered on
+/// the web.
+#[cfg(target_arch = "wasm32")]
+fn read_cjk_font() -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalogues() {
+        for (language, catalogue) in Language::ALL.iter().zip(catalogues()) {
+            if *language != Language::English {
+                assert!(!catalogue.is_empty(), "{:?}", language);
+            }
+            // Placeholders have to survive translation
+            for (text, translation) in catalogue {
+                assert_eq!(
+                    text.matches("{}").count(),
+                    translation.matches("{}").count(),
+                    "{:?}: {}",
+                    language,
+                    text
+                );
+            }
+        }
+
+        // Every translation covers the same text
+        let keys = |catalogue: &'static HashMap<String, String>| {
+            let mut keys: Vec<&str> = catalogue.keys().map(String::as_str).collect();
+            keys.sort();
+            keys
+        };
+        for catalogue in catalogues()[2..].iter() {
+            assert_eq!(keys(catalogue), keys(&catalogues()[1]));
+        }
+
+        assert_eq!(Language::from_locale("de_DE.UTF-8"), Some(Language::German));
+        assert_eq!(Language::from_locale("fr-CA"), Some(Language::French));
+        assert_eq!(Language::from_locale("ja"), Some(Language::Japanese));
+        assert_eq!(Language::from_locale("C"), None);
+        assert_eq!(Language::from_locale("pt-BR"), None);
+        assert_eq!(Language::from_locale("esperanto"), None);
+
+        // Latin scripts are covered by the built in fonts
+        assert!(Language::English.displayable());
+        assert!(Language::French.displayable());
+    }
+}
@@ -0,0 +1,301 @@
+//! Reading plain text songs with the chords on their own lines above the
+//! lyrics, the way most song sheets are passed around.
+//!
+//! There's no markup, so lines are guessed at: a line is chords if most of
+//! its words read as chords, and section headings are short lines like
+//! `[Chorus]` or `Verse 1:`.
+
+use crate::locale::tr_with;
+use crate::song::{chord_name, is_annotation, Line, Problem, Segment, Song};
+
+// Tab stops, for sheets that line chords up with tabs
+const TAB_WIDTH: usize = 8;
+
+/// Things on a chord line that aren't chords but don't make it lyrics, like
+/// bar lines and repeat counts.
+fn is_filler(token: &str) -> bool {
+    let token = token.trim_matches(|c| c == '(' || c == ')');
+    token
+        .chars()
+        .all(|c| matches!(c, '|' | '/' | '-' | '.' | '%' | ':'))
+        || token
+            .strip_prefix(['x', 'X'])
+            .or_else(|| token.strip_suffix(['x', 'X']))
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+        || is_annotation(token)
+}
+
+// Chords with the column each starts at
+type Placed = Vec<(usize, String)>;
+
+/// The chords on a line, if it's a chord line, and the words that looked
+/// like chords but weren't.
+fn chord_line(line: &[char]) -> Option<(Placed, Vec<String>)> {
+    let mut chords = Vec::new();
+    let mut unreadable = Vec::new();
+    let mut column = 0;
+    while column < line.len() {
+        if line[column].is_whitespace() {
+            column += 1;
+            continue;
+        }
+        let start = column;
+        while column < line.len() && !line[column].is_whitespace() {
+            column += 1;
+        }
+        let token: String = line[start..column].iter().collect();
+        if is_filler(&token) {
+            continue;
+        }
+        match chord_name(&token) {
+            Some(_) => chords.push((start, token)),
+            None => unreadable.push(token),
+        }
+    }
+
+    // Mostly chords, so a lyric that happens to start with "A" isn't one
+    (!chords.is_empty() && chords.len() >= 2 * unreadable.len()).then_some((chords, unreadable))
+}
+
+/// A section heading like `[Chorus]`, `Verse 2:` or `INTRO`, as the name of
+/// the section and the label to show.
+fn section_heading(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    let label = line
+        .strip_prefix('[')
+        .and_then(|l| l.strip_suffix(']'))
+        .or_else(|| line.strip_suffix(':'))
+        .unwrap_or(line)
+        .trim();
+    let name: String = label
+        .split_whitespace()
+        .next()?
+        .chars()
+        .filter(char::is_ascii_alphabetic)
+        .collect::<String>()
+        .to_lowercase();
+    const SECTIONS: [&str; 10] = [
+        "intro",
+        "verse",
+        "prechorus",
+        "pre",
+        "chorus",
+        "bridge",
+        "solo",
+        "instrumental",
+        "interlude",
+        "outro",
+    ];
+    // Short, so a lyric starting with "Chorus" isn't a heading
+    let words = label.split_whitespace().count();
+    (SECTIONS.contains(&name.as_str()) && words <= 3).then(|| {
+        let name = if name == "pre" { "prechorus" } else { &name };
+        (name.to_owned(), label.to_owned())
+    })
+}
+
+fn expand_tabs(line: &str) -> Vec<char> {
+    let mut chars = Vec::new();
+    for c in line.chars() {
+        if c == '\t' {
+            let next = (chars.len() / TAB_WIDTH + 1) * TAB_WIDTH;
+            chars.resize(next, ' ');
+        } else {
+            chars.push(c);
+        }
+    }
+    chars
+}
+
+/// Lyrics split where the chords above them start, so each chord stays over
+/// the syllable it was written above. Lyrics shorter than the chord line
+/// are padded out with spaces.
+fn segments(chords: &[(usize, String)], lyrics: &[char]) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let end = chords
+        .last()
+        .map_or(0, |(column, name)| column + name.chars().count() + 1)
+        .max(lyrics.len());
+    let text = |from: usize, to: usize| -> String {
+        (from..to)
+            .map(|i| lyrics.get(i).copied().unwrap_or(' '))
+            .collect()
+    };
+
+    let first = chords.first().map_or(end, |(column, _)| *column);
+    if first > 0 {
+        segments.push(Segment {
+            chord: None,
+            text: text(0, first),
+        });
+    }
+    for (i, (column, name)) in chords.iter().enumerate() {
+        let next = chords.get(i + 1).map_or(end, |(column, _)| *column);
+        let mut text = text(*column, next);
+        if i + 1 == chords.len() {
+            text.truncate(text.trim_end().len());
+        }
+        segments.push(Segment {
+            chord: Some(name.clone()),
+            text,
+        });
+    }
+    segments
+}
+
+/// Read a chords over lyrics song sheet. Sections become ChordPro style
+/// sections, and words on chord lines that aren't chords are reported.
+pub(crate) fn parse(text: &str) -> (Song, Vec<Problem>) {
+    let mut song = Song::default();
+    let mut problems = Vec::new();
+    let lines: Vec<Vec<char>> = text.lines().map(expand_tabs).collect();
+    let mut section: Option<String> = None;
+
+    let mut i = 0;
+    while i < lines.len() {
+        let line = &lines[i];
+        let text: String = line.iter().collect();
+        let number = i + 1;
+        i += 1;
+
+        if text.trim().is_empty() {
+            song.lines.push(Line::Lyrics(Vec::new()));
+            continue;
+        }
+
+        if let Some((name, label)) = section_heading(&text) {
+            if let Some(open) = section.take() {
+                song.lines.push(Line::Directive {
+                    name: format!("end_of_{}", open),
+                    value: String::new(),
+                });
+            }
+            song.lines.push(Line::Directive {
+                name: format!("start_of_{}", name),
+                value: label,
+            });
+            section = Some(name);
+            continue;
+        }
+
+        let Some((chords, unreadable)) = chord_line(line) else {
+            song.lines.push(Line::Lyrics(vec![Segment {
+                chord: None,
+                text: text.trim_end().to_owned(),
+            }]));
+            continue;
+        };
+        for token in unreadable {
+            problems.push(Problem {
+                line: number,
+                message: tr_with("can't read the chord {}", &token),
+            });
+        }
+
+        // The lyrics the chords go over, unless the next line is chords or
+        // a heading too
+        let lyrics = lines.get(i).filter(|next| {
+            let next_text: String = next.iter().collect();
+            !next_text.trim().is_empty()
+                && chord_line(next).is_none()
+                && section_heading(&next_text).is_none()
+        });
+        let lyrics = match lyrics {
+            Some(lyrics) => {
+                i += 1;
+                lyrics.clone()
+            }
+            None => Vec::new(),
+        };
+        song.lines.push(Line::Lyrics(segments(&chords, &lyrics)));
+    }
+
+    if let Some(open) = section {
+        song.lines.push(Line::Directive {
+            name: format!("end_of_{}", open),
+            value: String::new(),
+        });
+    }
+    (song, problems)
+}
+
+/// Whether pasted text is ChordPro rather than chords over lyrics: it has
+/// directives, or chords in brackets.
+pub(crate) fn looks_like_chordpro(text: &str) -> bool {
+    text.lines().any(|line| {
+        let line = line.trim();
+        if line.starts_with('{') && line.ends_with('}') {
+            return true;
+        }
+        line.split('[')
+            .skip(1)
+            .filter_map(|rest| rest.split_once(']'))
+            .any(|(chord, _)| chord_name(chord).is_some())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::standard_tuning;
+
+    #[test]
+    fn test_parse() {
+        let text = "\
+[Verse 1]
+Am        F       C
+Hello darkness my old friend
+A day in the life
+
+Chorus:
+G    Hm   | C  x2
+
+Outro
+Am";
+        let (song, problems) = parse(text);
+        assert_eq!(
+            problems,
+            [Problem {
+                line: 7,
+                message: "can't read the chord Hm".to_owned()
+            }]
+        );
+        assert_eq!(song.chords(), ["Am", "F", "C", "G", "C", "Am"]);
+        assert_eq!(song.sections().len(), 3);
+        let chorus = &song.sections()[1];
+        assert_eq!(chorus.label, "Chorus");
+        assert_eq!(chorus.chords, ["G", "C"]);
+        assert_eq!(chorus.start, 3);
+        assert_eq!(
+            song.lines[1],
+            Line::Lyrics(vec![
+                Segment {
+                    chord: Some("Am".to_owned()),
+                    text: "Hello dark".to_owned()
+                },
+                Segment {
+                    chord: Some("F".to_owned()),
+                    text: "ness my ".to_owned()
+                },
+                Segment {
+                    chord: Some("C".to_owned()),
+                    text: "old friend".to_owned()
+                },
+            ])
+        );
+        // A lyric that starts with a chord name isn't a chord line
+        assert_eq!(
+            song.lines[2],
+            Line::Lyrics(vec![Segment {
+                chord: None,
+                text: "A day in the life".to_owned()
+            }])
+        );
+
+        let chordpro = crate::chordpro::write(&song, &standard_tuning());
+        assert!(chordpro
+            .contains("{start_of_verse: Verse 1}\n[Am]Hello dark[F]ness my [C]old friend\n"));
+        assert!(looks_like_chordpro(&chordpro));
+        assert!(!looks_like_chordpro(text));
+    }
+}
@@ -0,0 +1,310 @@
+use crate::locale::{tr, tr_with};
+use crate::progression::Progression;
+use rodio::source::{SineWave, Source, Zero};
+use std::time::Duration;
+
+const CLICK_LENGTH: Duration = Duration::from_millis(30);
+const ACCENT_FREQUENCY: f32 = 1760.0;
+const CLICK_FREQUENCY: f32 = 880.0;
+// How many beats of clicks to keep queued up ahead of the current beat
+const BEATS_AHEAD: u64 = 2;
+
+/// The clicks are queued on a single sink, one beat at a time, so their
+/// timing comes from the audio clock rather than from when we get repainted.
+struct Clicks {
+    _stream: rodio::OutputStream,
+    sink: rodio::Sink,
+    queued_beats: u64,
+}
+
+impl Clicks {
+    fn new() -> Result<Self, String> {
+        let (stream, handle) = rodio::OutputStream::try_default().map_err(|e| e.to_string())?;
+        let sink = rodio::Sink::try_new(&handle).map_err(|e| e.to_string())?;
+        Ok(Self {
+            _stream: stream,
+            sink,
+            queued_beats: 0,
+        })
+    }
+
+    fn queue_beat(&mut self, beat_length: Duration, accent: bool) {
+        let frequency = if accent {
+            ACCENT_FREQUENCY
+        } else {
+            CLICK_FREQUENCY
+        };
+        let click_length = CLICK_LENGTH.min(beat_length);
+
+        self.sink.append(
+            SineWave::new(frequency)
+                .take_duration(click_length)
+                .amplify(0.5),
+        );
+        self.sink
+            .append(Zero::<f32>::new(1, 48000).take_duration(beat_length - click_length));
+        self.queued_beats += 1;
+    }
+}
+
+struct Running {
+    // UI time the first beat (of the count-in, if any) started
+    started_at: f64,
+    clicks: Option<Clicks>,
+}
+
+/// A metronome that can loop a chord progression, one or more bars per chord.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub(crate) struct Metronome {
+    pub(crate) open: bool,
+    bpm: u32,
+    beats_per_bar: u32,
+    beat_unit: u32,
+    count_in_bars: u32,
+    progression_text: String,
+    progression: Progression,
+
+    #[serde(skip)]
+    progression_error: Option<String>,
+    #[serde(skip)]
+    running: Option<Running>,
+    #[serde(skip)]
+    current_chord: Option<usize>,
+}
+
+impl Default for Metronome {
+    fn default() -> Self {
+        Self {
+            open: false,
+            bpm: 90,
+            beats_per_bar: 4,
+            beat_unit: 4,
+            count_in_bars: 1,
+            progression_text: String::new(),
+            progression: Progression::default(),
+            progression_error: None,
+            running: None,
+            current_chord: None,
+        }
+    }
+}
+
+impl Metronome {
+    fn parse_progression(&mut self) {
+        match Progression::parse(&self.progression_text) {
+            Ok(progression) => {
+                self.progression = progression;
+                self.progression_error = None;
+            }
+            Err(e) => self.progression_error = Some(e),
+        }
+    }
+
+    /// How long each click lasts. The tempo is in quarter notes, and the
+    /// clicks follow the beat unit, so 6/8 clicks in eighths.
+    fn beat_length(&self) -> Duration {
+        let quarter = 60.0 / self.bpm.max(1) as f64;
+        Duration::from_secs_f64(quarter * 4.0 / self.beat_unit.max(1) as f64)
+    }
+
+    fn count_in_beats(&self) -> u64 {
+        (self.count_in_bars * self.beats_per_bar) as u64
+    }
+
+    fn start(&mut self, now: f64) {
+        let clicks = match Clicks::new() {
+            Ok(clicks) => Some(clicks),
+            Err(e) => {
+                log::error!("error starting metronome clicks: {}", e);
+                None
+            }
+        };
+        self.running = Some(Running {
+            started_at: now,
+            clicks,
+        });
+        self.current_chord = None;
+    }
+
+    fn stop(&mut self) {
+        if let Some(Running {
+            clicks: Some(clicks),
+            ..
+        }) = self.running.take()
+        {
+            clicks.sink.stop();
+        }
+        self.current_chord = None;
+    }
+
+    /// Show the metronome window and keep the clicks going. Returns the
+    /// chord to show on the fretboard when the progression moves on.
+    pub(crate) fn show(&mut self, ctx: &egui::Context) -> Option<String> {
+        let mut open = self.open;
+        egui::Window::new(tr("Metronome"))
+            .id(egui::Id::new("metronome"))
+            .open(&mut open)
+            .show(ctx, |ui| self.ui(ui));
+        self.open = open;
+
+        if !self.open {
+            self.stop();
+        }
+        self.tick(ctx)
+    }
+
+    /// Current beat (counting from 0 at the start of the count-in), and how
+    /// far into it we are as a fraction.
+    fn current_beat(&self, now: f64) -> Option<(u64, f64)> {
+        let running = self.running.as_ref()?;
+        let beats = (now - running.started_at).max(0.0) / self.beat_length().as_secs_f64();
+        Some((beats.floor() as u64, beats.fract()))
+    }
+
+    fn tick(&mut self, ctx: &egui::Context) -> Option<String> {
+        let now = ctx.input(|i| i.time);
+        let (beat, fraction) = self.current_beat(now)?;
+
+        let beat_length = self.beat_length();
+        let beats_per_bar = self.beats_per_bar as u64;
+        if let Some(Running {
+            clicks: Some(clicks),
+            ..
+        }) = &mut self.running
+        {
+            while clicks.queued_beats <= beat + BEATS_AHEAD {
+                let accent = clicks.queued_beats % beats_per_bar == 0;
+                clicks.queue_beat(beat_length, accent);
+            }
+        }
+
+        let time_to_next_beat = (1.0 - fraction) * beat_length.as_secs_f64();
+        ctx.request_repaint_after(Duration::from_secs_f64(time_to_next_beat));
+
+        let count_in = self.count_in_beats();
+        if beat < count_in {
+            return None;
+        }
+        let bar = ((beat - count_in) / beats_per_bar) as u32;
+        let chord = self.progression.chord_at_bar(bar);
+        if chord == self.current_chord {
+            return None;
+        }
+        self.current_chord = chord;
+        chord.map(|i| self.progression.chords[i].name.clone())
+    }
+
+    fn timing_ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(
+            egui::DragValue::new(&mut self.bpm)
+                .clamp_range(20..=300)
+                .suffix(tr(" BPM")),
+        )
+        .on_hover_text(tr("Quarter notes per minute"));
+        ui.separator();
+        ui.add(egui::DragValue::new(&mut self.beats_per_bar).clamp_range(1..=16));
+        ui.label("/");
+        egui::ComboBox::from_id_source("beat_unit")
+            .width(40.0)
+            .selected_text(self.beat_unit.to_string())
+            .show_ui(ui, |ui| {
+                for unit in [2, 4, 8, 16] {
+                    ui.selectable_value(&mut self.beat_unit, unit, unit.to_string());
+                }
+            });
+        ui.separator();
+        ui.label(tr("Count-in"));
+        ui.add(
+            egui::DragValue::new(&mut self.count_in_bars)
+                .clamp_range(0..=4)
+                .suffix(tr(" bars")),
+        );
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let now = ui.input(|i| i.time);
+
+        // Timing is worked out from these, so they're locked while running
+        ui.add_enabled_ui(self.running.is_none(), |ui| {
+            ui.horizontal(|ui| self.timing_ui(ui));
+        });
+
+        ui.label(tr("Progression (chord:bars)"));
+        if ui
+            .add(egui::TextEdit::singleline(&mut self.progression_text).hint_text("Am7:2 D7:2 G:4"))
+            .changed()
+        {
+            self.parse_progression();
+        }
+        if let Some(error) = &self.progression_error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+
+        ui.horizontal(|ui| {
+            if self.running.is_some() {
+                if ui.button(tr("⏹ Stop")).clicked() {
+                    self.stop();
+                }
+            } else if ui.button(tr("▶ Start")).clicked() {
+                self.start(now);
+            }
+
+            // Beat indicator, the current beat is lit up
+            let current = self.current_beat(now);
+            let count_in = self.count_in_beats();
+            for i in 0..self.beats_per_bar as u64 {
+                let lit =
+                    matches!(current, Some((beat, _)) if beat % self.beats_per_bar as u64 == i);
+                let (rect, _) = ui.allocate_exact_size([20.0, 20.0].into(), egui::Sense::hover());
+                let color = match (lit, current) {
+                    (true, Some((beat, _))) if beat < count_in => ui.visuals().warn_fg_color,
+                    (true, _) => ui.visuals().selection.bg_fill,
+                    (false, _) => ui.visuals().widgets.inactive.bg_fill,
+                };
+                ui.painter().circle_filled(rect.center(), 7.0, color);
+            }
+
+            if let Some((beat, _)) = current {
+                if beat < count_in {
+                    ui.label(tr_with(
+                        "Count-in {}",
+                        &(beat % self.beats_per_bar as u64 + 1).to_string(),
+                    ));
+                }
+            }
+        });
+
+        ui.horizontal_wrapped(|ui| {
+            for (i, chord) in self.progression.chords.iter().enumerate() {
+                let selected = self.current_chord == Some(i);
+                let text = if chord.bars == 1 {
+                    chord.name.clone()
+                } else {
+                    format!("{} ×{}", chord.name, chord.bars)
+                };
+                ui.add(egui::SelectableLabel::new(selected, text));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_beat_length() {
+        let mut metronome = Metronome {
+            bpm: 120,
+            ..Default::default()
+        };
+        assert_eq!(metronome.beat_length(), Duration::from_millis(500));
+        // 6/8 clicks in eighths, at the same tempo
+        metronome.beats_per_bar = 6;
+        metronome.beat_unit = 8;
+        assert_eq!(metronome.beat_length(), Duration::from_millis(250));
+        metronome.beat_unit = 2;
+        assert_eq!(metronome.beat_length(), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,867 @@
+//! Reading and writing chord symbols in MusicXML, as `<harmony>` elements
+//! with `<frame>` fretboard diagrams.
+//!
+//! Only the harmony is read from a score; notes are ignored. Exports are a
+//! single guitar part of rests with the chords above, in 4/4.
+
+use crate::locale::tr_with;
+use crate::song::{chord_name, Define, Line, Problem, Segment, Song};
+use crate::voicing::{find_voicing, fingers, Voicing};
+use klib::core::note::Note;
+use std::fmt::Write;
+
+// Measures on each line of an imported song
+const MEASURES_PER_LINE: usize = 4;
+// Divisions of a quarter note, enough to split a bar into 1, 2, 3, 4 or 6
+const DIVISIONS: usize = 12;
+const MEASURE: usize = 4 * DIVISIONS;
+// Strings a frame has to have to be read as a voicing
+const STRINGS: usize = 6;
+
+/// MusicXML chord kinds and the suffixes they're written with.
+const KINDS: [(&str, &str); 25] = [
+    ("major", ""),
+    ("minor", "m"),
+    ("augmented", "+"),
+    ("diminished", "dim"),
+    ("dominant", "7"),
+    ("major-seventh", "maj7"),
+    ("minor-seventh", "m7"),
+    ("diminished-seventh", "dim7"),
+    ("augmented-seventh", "+7"),
+    ("half-diminished", "m7(♭5)"),
+    ("major-minor", "m(maj7)"),
+    ("major-sixth", "6"),
+    ("minor-sixth", "m6"),
+    ("dominant-ninth", "9"),
+    ("major-ninth", "maj9"),
+    ("minor-ninth", "m9"),
+    ("dominant-11th", "11"),
+    ("major-11th", "maj11"),
+    ("minor-11th", "m11"),
+    ("dominant-13th", "13"),
+    ("major-13th", "maj13"),
+    ("minor-13th", "m13"),
+    ("suspended-second", "sus2"),
+    ("suspended-fourth", "sus4"),
+    ("power", "5"),
+];
+
+/// Major keys by the number of sharps, from seven flats to seven sharps.
+const MAJOR_KEYS: [&str; 15] = [
+    "C♭", "G♭", "D♭", "A♭", "E♭", "B♭", "F", "C", "G", "D", "A", "E", "B", "F♯", "C♯",
+];
+const MINOR_KEYS: [&str; 15] = [
+    "A♭", "E♭", "B♭", "F", "C", "G", "D", "A", "E", "B", "F♯", "C♯", "G♯", "D♯", "A♯",
+];
+
+/// Just enough of an XML element tree to read a score.
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Node>,
+    // Where it starts in the file, counting from 1
+    line: usize,
+}
+
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|e| e.name == name)
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn text(&self) -> String {
+        let mut text = String::new();
+        for node in &self.children {
+            if let Node::Text(t) = node {
+                text.push_str(t);
+            }
+        }
+        text.trim().to_owned()
+    }
+
+    fn child_text(&self, name: &str) -> Option<String> {
+        self.child(name).map(Element::text)
+    }
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse()))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn parse_attributes(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut attributes = Vec::new();
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let (name, value) = rest
+            .split_once('=')
+            .ok_or_else(|| format!("attribute without a value: {}", rest))?;
+        let value = value.trim_start();
+        let quote = value
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+            .ok_or_else(|| format!("unquoted attribute {}", name.trim()))?;
+        let end = value[1..]
+            .find(quote)
+            .ok_or_else(|| format!("unclosed attribute {}", name.trim()))?;
+        attributes.push((name.trim().to_owned(), unescape(&value[1..end + 1])));
+        rest = value[end + 2..].trim_start();
+    }
+    Ok(attributes)
+}
+
+/// Read an XML document into its root element. Comments, processing
+/// instructions and the doctype are skipped.
+fn parse_xml(text: &str) -> Result<Element, String> {
+    let mut stack = vec![Element {
+        name: String::new(),
+        attributes: Vec::new(),
+        children: Vec::new(),
+        line: 0,
+    }];
+    let mut pos = 0;
+    let mut line = 1;
+    // Moves past some text, keeping count of the lines
+    let advance = |pos: &mut usize, line: &mut usize, to: usize| {
+        *line += text[*pos..to].matches('\n').count();
+        *pos = to;
+        *line
+    };
+    let skip_past = |pos: usize, end: &str| {
+        text[pos..]
+            .find(end)
+            .map(|i| pos + i + end.len())
+            .ok_or_else(|| format!("expected {} before the end of the file", end))
+    };
+
+    while pos < text.len() {
+        let rest = &text[pos..];
+        if rest.starts_with("<!--") {
+            let end = skip_past(pos, "-->")?;
+            advance(&mut pos, &mut line, end);
+        } else if rest.starts_with("<![CDATA[") {
+            let end = skip_past(pos, "]]>")?;
+            let data = text[pos + 9..end - 3].to_owned();
+            stack.last_mut().unwrap().children.push(Node::Text(data));
+            advance(&mut pos, &mut line, end);
+        } else if rest.starts_with("<?") {
+            let end = skip_past(pos, "?>")?;
+            advance(&mut pos, &mut line, end);
+        } else if rest.starts_with("<!") {
+            // A doctype, possibly with declarations in brackets
+            let bracket = rest.find('[');
+            let close = rest.find('>').unwrap_or(rest.len());
+            let end = match bracket {
+                Some(bracket) if bracket < close => skip_past(pos, "]>")?,
+                _ => skip_past(pos, ">")?,
+            };
+            advance(&mut pos, &mut line, end);
+        } else if let Some(closing) = rest.strip_prefix("</") {
+            let end = closing
+                .find('>')
+                .ok_or("unclosed tag at the end of the file")?;
+            let name = closing[..end].trim();
+            let end = pos + end + 3;
+            let at = advance(&mut pos, &mut line, end);
+            let element = stack.pop().filter(|e| !e.name.is_empty());
+            match element {
+                Some(element) if element.name == name => stack
+                    .last_mut()
+                    .unwrap()
+                    .children
+                    .push(Node::Element(element)),
+                _ => return Err(format!("line {}: unexpected </{}>", at, name)),
+            }
+        } else if let Some(tag) = rest.strip_prefix('<') {
+            // Find the end of the tag, skipping over quoted attribute values
+            let mut quote = None;
+            let end = tag
+                .char_indices()
+                .find(|(_, c)| match quote {
+                    Some(q) if *c == q => {
+                        quote = None;
+                        false
+                    }
+                    Some(_) => false,
+                    None if *c == '"' || *c == '\'' => {
+                        quote = Some(*c);
+                        false
+                    }
+                    None => *c == '>',
+                })
+                .map(|(i, _)| i)
+                .ok_or("unclosed tag at the end of the file")?;
+            let (tag, self_closing) = match tag[..end].strip_suffix('/') {
+                Some(tag) => (tag, true),
+                None => (&tag[..end], false),
+            };
+            let (name, attributes) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+            let start = line;
+            let element = Element {
+                name: name.to_owned(),
+                attributes: parse_attributes(attributes)
+                    .map_err(|e| format!("line {}: {}", start, e))?,
+                children: Vec::new(),
+                line: start,
+            };
+            let end = pos + end + 2;
+            advance(&mut pos, &mut line, end);
+            if self_closing {
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .children
+                    .push(Node::Element(element));
+            } else {
+                stack.push(element);
+            }
+        } else {
+            let end = rest.find('<').map_or(text.len(), |i| pos + i);
+            let data = unescape(&text[pos..end]);
+            if stack.len() > 1 && !data.trim().is_empty() {
+                stack.last_mut().unwrap().children.push(Node::Text(data));
+            }
+            advance(&mut pos, &mut line, end);
+        }
+    }
+
+    if stack.len() > 1 {
+        let open = stack.last().unwrap();
+        return Err(format!(
+            "line {}: <{}> is never closed",
+            open.line, open.name
+        ));
+    }
+    let document = stack.pop().unwrap();
+    document
+        .children
+        .into_iter()
+        .find_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+        .ok_or_else(|| "the file is empty".to_owned())
+}
+
+fn alter_symbol(alter: i32) -> String {
+    match alter {
+        a if a > 0 => "♯".repeat(a as usize),
+        a => "♭".repeat(-a as usize),
+    }
+}
+
+/// A note name from a step and an alteration, like `F` and `1` for F♯.
+fn pitch_name(step: &str, alter: Option<String>) -> Result<String, String> {
+    if !matches!(step, "A" | "B" | "C" | "D" | "E" | "F" | "G") {
+        return Err(format!("unknown step {}", step));
+    }
+    let alter: f32 = match alter {
+        Some(alter) => alter
+            .parse()
+            .map_err(|_| format!("can't read the alteration {}", alter))?,
+        None => 0.0,
+    };
+    Ok(format!("{}{}", step, alter_symbol(alter.round() as i32)))
+}
+
+/// The chord name for a `<harmony>` element, or `None` for "no chord".
+fn harmony_name(harmony: &Element) -> Result<Option<String>, String> {
+    let root = harmony
+        .child("root")
+        .ok_or("chord symbols without a root aren't supported")?;
+    let root = pitch_name(
+        &root.child_text("root-step").unwrap_or_default(),
+        root.child_text("root-alter"),
+    )?;
+
+    let kind_element = harmony.child("kind").ok_or("chord symbol without a kind")?;
+    let kind = kind_element.text();
+    if kind == "none" {
+        return Ok(None);
+    }
+    let mut suffix = match KINDS.iter().find(|(k, _)| *k == kind) {
+        Some((_, suffix)) => suffix.to_string(),
+        // "other" chords can only be told apart by how they're written
+        None => match kind_element.attribute("text") {
+            Some(text) => text.to_owned(),
+            None => return Err(tr_with("unknown chord kind {}", &kind)),
+        },
+    };
+
+    for degree in harmony.elements().filter(|e| e.name == "degree") {
+        let value = degree.child_text("degree-value").unwrap_or_default();
+        let alter: i32 = degree
+            .child_text("degree-alter")
+            .and_then(|a| a.parse::<f32>().ok())
+            .map_or(0, |a| a.round() as i32);
+        match degree.child_text("degree-type").as_deref() {
+            // A flat seventh added to a sus chord is a 7sus chord
+            Some("add") if value == "7" && alter == -1 && suffix.starts_with("sus") => {
+                suffix = format!("7{}", suffix)
+            }
+            Some("add") if alter == 0 => suffix.push_str(&format!("add{}", value)),
+            Some("add") | Some("alter") => {
+                suffix.push_str(&format!("({}{})", alter_symbol(alter), value))
+            }
+            Some("subtract") => suffix.push_str(&format!("omit{}", value)),
+            _ => return Err(format!("can't read the degree {}", value)),
+        }
+    }
+
+    let bass = match harmony.child("bass") {
+        Some(bass) => format!(
+            "/{}",
+            pitch_name(
+                &bass.child_text("bass-step").unwrap_or_default(),
+                bass.child_text("bass-alter"),
+            )?
+        ),
+        None => String::new(),
+    };
+    Ok(Some(format!("{}{}{}", root, suffix, bass)))
+}
+
+/// The voicing in a `<frame>`, if it's for six strings.
+fn frame_voicing(frame: &Element) -> Option<Voicing> {
+    let strings: usize = frame.child_text("frame-strings")?.parse().ok()?;
+    if strings != STRINGS {
+        return None;
+    }
+    // String 1 is the highest, the same order as the tuning
+    let mut frets = vec![None; STRINGS];
+    for note in frame.elements().filter(|e| e.name == "frame-note") {
+        let string: usize = note.child_text("string")?.parse().ok()?;
+        let fret: usize = note.child_text("fret")?.parse().ok()?;
+        *frets.get_mut(string.checked_sub(1)?)? = Some(fret);
+    }
+    Some(Voicing { frets })
+}
+
+/// Read the chord symbols from a MusicXML score into a song, a few measures
+/// to a line with bar lines between them. Chords that can't be read are
+/// reported with the line they're on in the file.
+pub(crate) fn import(text: &str) -> Result<(Song, Vec<Problem>), String> {
+    let score = parse_xml(text)?;
+    if score.name != "score-partwise" {
+        return Err(format!(
+            "expected a partwise MusicXML score, not <{}>",
+            score.name
+        ));
+    }
+
+    let mut song = Song {
+        title: score
+            .child("work")
+            .and_then(|w| w.child_text("work-title"))
+            .or_else(|| score.child_text("movement-title"))
+            .unwrap_or_default(),
+        artist: score
+            .child("identification")
+            .and_then(|i| {
+                i.elements()
+                    .find(|e| e.name == "creator" && e.attribute("type") == Some("composer"))
+            })
+            .map(Element::text)
+            .unwrap_or_default(),
+        ..Default::default()
+    };
+    let mut problems = Vec::new();
+
+    // The chords are usually all in one part, take the first that has any
+    let has_harmony = |part: &Element| {
+        part.elements()
+            .any(|measure| measure.elements().any(|e| e.name == "harmony"))
+    };
+    let parts: Vec<&Element> = score.elements().filter(|e| e.name == "part").collect();
+    let Some(part) = parts
+        .iter()
+        .copied()
+        .find(|part| has_harmony(part))
+        .or(parts.first().copied())
+    else {
+        return Err("the score has no parts".to_owned());
+    };
+
+    let measures: Vec<&Element> = part.elements().filter(|e| e.name == "measure").collect();
+    for line in measures.chunks(MEASURES_PER_LINE) {
+        let mut segments = vec![Segment {
+            chord: None,
+            text: "| ".to_owned(),
+        }];
+        for measure in line {
+            for element in measure.elements() {
+                match element.name.as_str() {
+                    "attributes" if song.key.is_none() => {
+                        song.key = element.child("key").and_then(key_name);
+                    }
+                    "harmony" => {
+                        let name = match harmony_name(element) {
+                            Ok(Some(name)) => name,
+                            Ok(None) => "N.C.".to_owned(),
+                            Err(e) => {
+                                problems.push(Problem {
+                                    line: element.line,
+                                    message: e,
+                                });
+                                continue;
+                            }
+                        };
+                        if chord_name(&name).is_none() && name != "N.C." {
+                            problems.push(Problem {
+                                line: element.line,
+                                message: tr_with("can't read the chord {}", &name),
+                            });
+                        }
+                        let voicing = element.child("frame").and_then(frame_voicing);
+                        if let Some(voicing) = voicing {
+                            if !song.defines.iter().any(|d| d.name == name) {
+                                song.defines.push(Define {
+                                    name: name.clone(),
+                                    voicing,
+                                });
+                            }
+                        }
+                        segments.push(Segment {
+                            chord: Some(name),
+                            text: " ".to_owned(),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            segments.last_mut().unwrap().text.push_str("  | ");
+        }
+        song.lines.push(Line::Lyrics(segments));
+    }
+    Ok((song, problems))
+}
+
+fn key_name(key: &Element) -> Option<String> {
+    let fifths: i32 = key.child_text("fifths")?.parse().ok()?;
+    let index = usize::try_from(fifths + 7).ok()?;
+    match key.child_text("mode").as_deref() {
+        Some("minor") => MINOR_KEYS.get(index).map(|k| format!("{}m", k)),
+        _ => MAJOR_KEYS.get(index).map(|k| k.to_string()),
+    }
+}
+
+/// Root, suffix and bass of a chord name, like `F♯`, `m7` and `Some("E")`.
+fn split_chord(name: &str) -> Option<(&str, &str, Option<&str>)> {
+    let (chord, bass) = match name.split_once('/') {
+        Some((chord, bass)) => (chord, Some(bass)),
+        None => (name, None),
+    };
+    if !chord.starts_with(|c: char| ('A'..='G').contains(&c)) {
+        return None;
+    }
+    let root_len = chord
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| *c != '♯' && *c != '♭')
+        .map_or(chord.len(), |(i, _)| i);
+    Some((&chord[..root_len], &chord[root_len..], bass))
+}
+
+fn write_pitch(xml: &mut String, element: &str, name: &str) {
+    let step = &name[..1];
+    let alter: i32 = name[1..]
+        .chars()
+        .map(|c| match c {
+            '♯' => 1,
+            '♭' => -1,
+            _ => 0,
+        })
+        .sum();
+    writeln!(xml, "        <{}>", element).unwrap();
+    writeln!(xml, "          <{0}-step>{1}</{0}-step>", element, step).unwrap();
+    if alter != 0 {
+        writeln!(xml, "          <{0}-alter>{1}</{0}-alter>", element, alter).unwrap();
+    }
+    writeln!(xml, "        </{}>", element).unwrap();
+}
+
+/// The kind for a suffix, and the degrees added to or altered in it.
+fn kind_and_degrees(suffix: &str) -> (&'static str, Vec<(u32, i32, &'static str)>) {
+    // A 7sus chord is a sus chord with a flat seventh added
+    if let Some(sus) = suffix.strip_prefix('7').filter(|s| s.starts_with("sus")) {
+        let (kind, mut degrees) = kind_and_degrees(sus);
+        degrees.insert(0, (7, -1, "add"));
+        return (kind, degrees);
+    }
+
+    // The longest suffix that matches, so "m7" isn't read as "m"
+    let (kind, base) = KINDS
+        .iter()
+        .filter(|(_, s)| suffix.starts_with(s))
+        .max_by_key(|(_, s)| s.len())
+        .copied()
+        .unwrap_or(("major", ""));
+    // Degrees up to this are in the chord already, and get altered
+    let highest = match base.trim_start_matches(|c: char| !c.is_ascii_digit()) {
+        d if d.starts_with("13") => 13,
+        d if d.starts_with("11") => 11,
+        d if d.starts_with('9') => 9,
+        d if d.starts_with('7') || d.starts_with('6') => 7,
+        _ => 5,
+    };
+
+    let mut degrees = Vec::new();
+    let mut rest = &suffix[base.len()..];
+    while !rest.is_empty() {
+        if let Some(added) = rest.strip_prefix("add") {
+            let digits = added.chars().take_while(char::is_ascii_digit).count();
+            if let Ok(value) = added[..digits].parse() {
+                degrees.push((value, 0, "add"));
+                rest = &added[digits..];
+                continue;
+            }
+        }
+        if let Some(inner) = rest.strip_prefix('(') {
+            if let Some(end) = inner.find(')') {
+                let alteration = &inner[..end];
+                let alter = match alteration.chars().next() {
+                    Some('♯') => 1,
+                    Some('♭') => -1,
+                    _ => 0,
+                };
+                let value: u32 = alteration
+                    .trim_start_matches(['♯', '♭'])
+                    .parse()
+                    .unwrap_or(0);
+                if value > 0 {
+                    let kind = if value <= highest { "alter" } else { "add" };
+                    degrees.push((value, alter, kind));
+                }
+                rest = &inner[end + 1..];
+                continue;
+            }
+        }
+        // Anything else isn't something MusicXML can describe
+        break;
+    }
+    (kind, degrees)
+}
+
+fn write_frame(xml: &mut String, voicing: &Voicing, tuning: &[Note]) {
+    let fretted: Vec<usize> = voicing
+        .frets
+        .iter()
+        .flatten()
+        .copied()
+        .filter(|f| *f > 0)
+        .collect();
+    let lowest = fretted.iter().copied().min().unwrap_or(1);
+    let highest = fretted.iter().copied().max().unwrap_or(1);
+    let first_fret = if highest <= 4 { 1 } else { lowest };
+    let fingers = fingers(voicing, tuning);
+
+    writeln!(xml, "        <frame>").unwrap();
+    writeln!(
+        xml,
+        "          <frame-strings>{}</frame-strings>",
+        tuning.len()
+    )
+    .unwrap();
+    writeln!(
+        xml,
+        "          <frame-frets>{}</frame-frets>",
+        (highest + 1 - first_fret).max(4)
+    )
+    .unwrap();
+    if first_fret > 1 {
+        writeln!(xml, "          <first-fret>{}</first-fret>", first_fret).unwrap();
+    }
+    // Lowest string first, the way notation software lists them
+    for string in (0..tuning.len()).rev() {
+        let Some(fret) = voicing.fret(string) else {
+            continue;
+        };
+        writeln!(xml, "          <frame-note>").unwrap();
+        writeln!(xml, "            <string>{}</string>", string + 1).unwrap();
+        writeln!(xml, "            <fret>{}</fret>", fret).unwrap();
+        if let Some(finger) = fingers[string] {
+            writeln!(xml, "            <fingering>{}</fingering>", finger).unwrap();
+        }
+        writeln!(xml, "          </frame-note>").unwrap();
+    }
+    writeln!(xml, "        </frame>").unwrap();
+}
+
+fn write_harmony(xml: &mut String, song: &Song, name: &str, tuning: &[Note]) {
+    let Some((root, suffix, bass)) = split_chord(name) else {
+        log::warn!("can't write {} as MusicXML", name);
+        return;
+    };
+    let (kind, degrees) = kind_and_degrees(suffix);
+
+    writeln!(xml, "      <harmony>").unwrap();
+    write_pitch(xml, "root", root);
+    writeln!(
+        xml,
+        "        <kind text=\"{}\">{}</kind>",
+        escape(suffix),
+        kind
+    )
+    .unwrap();
+    if let Some(bass) = bass {
+        write_pitch(xml, "bass", bass);
+    }
+    for (value, alter, kind) in degrees {
+        writeln!(xml, "        <degree>").unwrap();
+        writeln!(xml, "          <degree-value>{}</degree-value>", value).unwrap();
+        writeln!(xml, "          <degree-alter>{}</degree-alter>", alter).unwrap();
+        writeln!(xml, "          <degree-type>{}</degree-type>", kind).unwrap();
+        writeln!(xml, "        </degree>").unwrap();
+    }
+    let voicing = song
+        .define(name)
+        .cloned()
+        .or_else(|| find_voicing(name, tuning));
+    if let Some(voicing) = voicing {
+        write_frame(xml, &voicing, tuning);
+    }
+    writeln!(xml, "      </harmony>").unwrap();
+}
+
+/// Write a song's chords as a MusicXML score, a bar at a time, with a
+/// fretboard diagram for each chord.
+pub(crate) fn export(song: &Song, tuning: &[Note]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n");
+    xml.push_str(
+        "<!DOCTYPE score-partwise PUBLIC \"-//Recordare//DTD MusicXML 4.0 Partwise//EN\" \
+         \"http://www.musicxml.org/dtds/partwise.dtd\">\n",
+    );
+    xml.push_str("<score-partwise version=\"4.0\">\n");
+    if !song.title.is_empty() {
+        writeln!(
+            xml,
+            "  <work><work-title>{}</work-title></work>",
+            escape(&song.title)
+        )
+        .unwrap();
+    }
+    if !song.artist.is_empty() {
+        writeln!(
+            xml,
+            "  <identification><creator type=\"composer\">{}</creator></identification>",
+            escape(&song.artist)
+        )
+        .unwrap();
+    }
+    xml.push_str("  <part-list>\n");
+    xml.push_str("    <score-part id=\"P1\"><part-name>Guitar</part-name></score-part>\n");
+    xml.push_str("  </part-list>\n");
+    xml.push_str("  <part id=\"P1\">\n");
+
+    let (fifths, mode) = match song.key.as_deref() {
+        Some(key) if key.ends_with('m') => (
+            MINOR_KEYS.iter().position(|k| *k == &key[..key.len() - 1]),
+            "minor",
+        ),
+        Some(key) => (MAJOR_KEYS.iter().position(|k| *k == key), "major"),
+        None => (None, "major"),
+    };
+    let fifths = fifths.map_or(0, |i| i as i32 - 7);
+
+    for (number, bar) in song.bars().iter().enumerate() {
+        writeln!(xml, "    <measure number=\"{}\">", number + 1).unwrap();
+        if number == 0 {
+            writeln!(xml, "      <attributes>").unwrap();
+            writeln!(xml, "        <divisions>{}</divisions>", DIVISIONS).unwrap();
+            writeln!(
+                xml,
+                "        <key><fifths>{}</fifths><mode>{}</mode></key>",
+                fifths, mode
+            )
+            .unwrap();
+            writeln!(
+                xml,
+                "        <time><beats>4</beats><beat-type>4</beat-type></time>"
+            )
+            .unwrap();
+            // Guitar is written an octave up
+            writeln!(
+                xml,
+                "        <clef><sign>G</sign><line>2</line>\
+                 <clef-octave-change>-1</clef-octave-change></clef>"
+            )
+            .unwrap();
+            writeln!(xml, "      </attributes>").unwrap();
+        }
+
+        if bar.is_empty() {
+            writeln!(
+                xml,
+                "      <note><rest measure=\"yes\"/><duration>{}</duration></note>",
+                MEASURE
+            )
+            .unwrap();
+        }
+        // The bar is split evenly between its chords, with anything left
+        // over going to the last one
+        for (i, chord) in bar.iter().enumerate() {
+            let mut duration = MEASURE / bar.len();
+            if i == bar.len() - 1 {
+                duration = MEASURE - duration * (bar.len() - 1);
+            }
+            write_harmony(&mut xml, song, chord, tuning);
+            writeln!(
+                xml,
+                "      <note><rest/><duration>{}</duration></note>",
+                duration
+            )
+            .unwrap();
+        }
+        writeln!(xml, "    </measure>").unwrap();
+    }
+
+    xml.push_str("  </part>\n");
+    xml.push_str("</score-partwise>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::standard_tuning;
+
+    #[test]
+    fn test_import() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE score-partwise PUBLIC "-//Recordare//DTD MusicXML 4.0 Partwise//EN" "x">
+<score-partwise version="4.0">
+  <work><work-title>Blues &amp; more</work-title></work>
+  <part id="P1">
+    <measure number="1">
+      <attributes><key><fifths>-3</fifths><mode>minor</mode></key></attributes>
+      <harmony>
+        <root><root-step>F</root-step><root-alter>1</root-alter></root>
+        <kind>half-diminished</kind>
+      </harmony>
+      <!-- a comment -->
+      <harmony>
+        <root><root-step>G</root-step></root>
+        <kind text="7b9">dominant</kind>
+        <degree><degree-value>9</degree-value><degree-alter>-1</degree-alter><degree-type>add</degree-type></degree>
+        <bass><bass-step>B</bass-step></bass>
+      </harmony>
+    </measure>
+    <measure number="2">
+      <harmony>
+        <root><root-step>C</root-step></root>
+        <kind>minor</kind>
+        <frame>
+          <frame-strings>6</frame-strings><frame-frets>4</frame-frets>
+          <frame-note><string>5</string><fret>3</fret></frame-note>
+          <frame-note><string>4</string><fret>5</fret></frame-note>
+          <frame-note><string>3</string><fret>5</fret></frame-note>
+          <frame-note><string>2</string><fret>4</fret></frame-note>
+          <frame-note><string>1</string><fret>3</fret></frame-note>
+        </frame>
+      </harmony>
+      <harmony>
+        <root><root-step>H</root-step></root>
+        <kind>major</kind>
+      </harmony>
+    </measure>
+    <measure number="3"/>
+  </part>
+</score-partwise>"#;
+        let (song, problems) = import(text).unwrap();
+        assert_eq!(song.title, "Blues & more");
+        assert_eq!(song.key.as_deref(), Some("Cm"));
+        assert_eq!(song.chords(), ["F♯m7(♭5)", "G7(♭9)/B", "Cm"]);
+        assert_eq!(
+            song.bars(),
+            [vec!["F♯m7(♭5)", "G7(♭9)/B"], vec!["Cm"], vec![]]
+        );
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line, 33);
+        assert_eq!(
+            song.define("Cm").unwrap().frets,
+            [Some(3), Some(4), Some(5), Some(5), Some(3), None]
+        );
+
+        assert!(import("<score-partwise><part>").is_err());
+    }
+
+    #[test]
+    fn test_export() {
+        let tuning = standard_tuning();
+        let (song, _) =
+            crate::chordpro::parse("{title: Test}\n{key: Am}\n| [Am7]  | [D7(♭9)] [F♯/A♭]  | \n");
+        let xml = export(&song, &tuning);
+        assert!(xml.contains("<fifths>0</fifths><mode>minor</mode>"));
+        assert!(xml.contains("<kind text=\"7(♭9)\">dominant</kind>"));
+        assert!(xml.contains("<frame-strings>6</frame-strings>"));
+
+        // Reading it back gives the same chords in the same bars
+        let (reread, problems) = import(&xml).unwrap();
+        assert!(problems.is_empty());
+        assert_eq!(reread.title, "Test");
+        assert_eq!(reread.key.as_deref(), Some("Am"));
+        assert_eq!(reread.bars(), song.bars());
+        assert_eq!(reread.define("Am7"), find_voicing("Am7", &tuning).as_ref());
+    }
+}
@@ -0,0 +1,339 @@
+//! Note naming systems other than English letters, for showing notes and
+//! chords and for reading chords typed with them.
+
+use crate::symbol::normalize;
+
+// Semitones above C for each letter, and the major scale's steps
+const LETTERS: [char; 7] = ['C', 'D', 'E', 'F', 'G', 'A', 'B'];
+const LETTER_SEMITONES: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+const MAJOR_SCALE: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+const ACCIDENTALS: [(char, i32); 4] = [('♯', 1), ('♭', -1), ('𝄪', 2), ('𝄫', -2)];
+const FIXED_DO: [&str; 7] = ["Do", "Re", "Mi", "Fa", "Sol", "La", "Si"];
+// Movable do by scale step, with the raised and lowered syllables
+const MOVABLE_DO: [(&str, Option<&str>, Option<&str>); 7] = [
+    ("Do", Some("Di"), None),
+    ("Re", Some("Ri"), Some("Ra")),
+    ("Mi", None, Some("Me")),
+    ("Fa", Some("Fi"), None),
+    ("Sol", Some("Si"), Some("Se")),
+    ("La", Some("Li"), Some("Le")),
+    ("Ti", None, Some("Te")),
+];
+/// Keys for the systems that name notes relative to one.
+pub(crate) const KEYS: [&str; 15] = [
+    "C", "G", "D", "A", "E", "B", "F♯", "C♯", "F", "B♭", "E♭", "A♭", "D♭", "G♭", "C♭",
+];
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum System {
+    /// C D E F G A B
+    #[default]
+    English,
+    /// H for B and B for B♭
+    German,
+    /// Do Re Mi, with Do always C
+    FixedDo,
+    /// Do Re Mi, with Do the key's root
+    MovableDo,
+    /// Scale degrees of the key, like the Nashville number system
+    Degrees,
+}
+
+impl System {
+    pub(crate) const ALL: [System; 5] = [
+        System::English,
+        System::German,
+        System::FixedDo,
+        System::MovableDo,
+        System::Degrees,
+    ];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            System::English => "English (C D E)",
+            System::German => "German (H for B)",
+            System::FixedDo => "Fixed do (Do Re Mi)",
+            System::MovableDo => "Movable do, in a key",
+            System::Degrees => "Scale degrees (1 2 3), in a key",
+        }
+    }
+
+    /// Whether notes are named relative to a key.
+    pub(crate) fn relative(self) -> bool {
+        matches!(self, System::MovableDo | System::Degrees)
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub(crate) struct Naming {
+    pub(crate) system: System,
+    /// For movable do and scale degrees, in English letters
+    pub(crate) key: String,
+}
+
+impl Default for Naming {
+    fn default() -> Self {
+        Self {
+            system: System::English,
+            key: "C".to_owned(),
+        }
+    }
+}
+
+/// A note spelled in English letters, as a letter from 0 for C to 6 for B
+/// and how many semitones it's raised.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Spelled {
+    letter: usize,
+    alteration: i32,
+}
+
+impl Spelled {
+    /// Read the note at the start of an English name, returning the rest.
+    fn parse(name: &str) -> Option<(Self, &str)> {
+        let first = name.chars().next()?;
+        let letter = LETTERS.iter().position(|l| *l == first)?;
+        let mut alteration = 0;
+        let mut rest = &name[first.len_utf8()..];
+        while let Some(c) = rest.chars().next() {
+            let Some((_, change)) = ACCIDENTALS.iter().find(|(a, _)| *a == c) else {
+                break;
+            };
+            alteration += change;
+            rest = &rest[c.len_utf8()..];
+        }
+        Some((Self { letter, alteration }, rest))
+    }
+
+    fn semitones(self) -> i32 {
+        LETTER_SEMITONES[self.letter] + self.alteration
+    }
+
+    fn name(self) -> String {
+        format!("{}{}", LETTERS[self.letter], accidentals(self.alteration))
+    }
+
+    /// The step of the key's scale this is on, from 0, and how far it's
+    /// raised from the major scale.
+    fn degree(self, key: Spelled) -> (usize, i32) {
+        let step = (self.letter + 7 - key.letter) % 7;
+        let semitones = self.semitones() - key.semitones() - MAJOR_SCALE[step];
+        (step, (semitones + 6).rem_euclid(12) - 6)
+    }
+
+    /// The note on a step of the key's scale, raised by some semitones.
+    fn from_degree(key: Spelled, step: usize, alteration: i32) -> Self {
+        let letter = (key.letter + step) % 7;
+        let semitones = key.semitones() + MAJOR_SCALE[step] + alteration;
+        Self {
+            letter,
+            alteration: (semitones - LETTER_SEMITONES[letter] + 6).rem_euclid(12) - 6,
+        }
+    }
+}
+
+fn accidentals(alteration: i32) -> String {
+    let symbol = if alteration > 0 { "♯" } else { "♭" };
+    symbol.repeat(alteration.unsigned_abs() as usize)
+}
+
+/// Strip a prefix ignoring case, like `sol` from `Sol7`.
+fn strip_word<'a>(text: &'a str, word: &str) -> Option<&'a str> {
+    let head = text.get(..word.len())?;
+    head.eq_ignore_ascii_case(word).then(|| &text[word.len()..])
+}
+
+/// Choose between the ways of reading typed text that starts with a
+/// syllable. Syllables like Fa also start English chords like `Fadd9`, so
+/// the syllable is only taken if the chord then reads, or if the text
+/// doesn't read as English either.
+fn pick_reading<'a>(text: &str, mut readings: Vec<(String, &'a str)>) -> Option<(String, &'a str)> {
+    // The longest syllable that matches is tried first
+    readings.sort_by_key(|(_, rest)| rest.len());
+    let chord_reads =
+        |(note, rest): &(String, &str)| normalize(&format!("{}{}", note, rest)).is_ok();
+    if let Some(i) = readings.iter().position(chord_reads) {
+        return Some(readings.swap_remove(i));
+    }
+    if normalize(text).is_ok() {
+        return None;
+    }
+    readings.into_iter().next()
+}
+
+impl Naming {
+    fn key(&self) -> Spelled {
+        Spelled::parse(&self.key)
+            .map(|(key, _)| key)
+            .unwrap_or(Spelled {
+                letter: 0,
+                alteration: 0,
+            })
+    }
+
+    /// Name a note given in English letters, like `B♭` or `B♭₃`. Anything
+    /// after the note is kept.
+    pub(crate) fn note(&self, name: &str) -> String {
+        let Some((spelled, rest)) = Spelled::parse(name) else {
+            return name.to_owned();
+        };
+        let note = match self.system {
+            System::English => return name.to_owned(),
+            System::German => match (LETTERS[spelled.letter], spelled.alteration) {
+                ('B', alteration) if alteration < 0 => format!("B{}", accidentals(alteration + 1)),
+                ('B', alteration) => format!("H{}", accidentals(alteration)),
+                _ => spelled.name(),
+            },
+            System::FixedDo => {
+                format!(
+                    "{}{}",
+                    FIXED_DO[spelled.letter],
+                    accidentals(spelled.alteration)
+                )
+            }
+            System::MovableDo => {
+                let (step, alteration) = spelled.degree(self.key());
+                let (syllable, raised, lowered) = MOVABLE_DO[step];
+                match (alteration, raised, lowered) {
+                    (1, Some(raised), _) => raised.to_owned(),
+                    (-1, _, Some(lowered)) => lowered.to_owned(),
+                    _ => format!("{}{}", syllable, accidentals(alteration)),
+                }
+            }
+            System::Degrees => {
+                let (step, alteration) = spelled.degree(self.key());
+                format!("{}{}", accidentals(alteration), step + 1)
+            }
+        };
+        note + rest
+    }
+
+    /// Name a chord given in English letters, like `B♭m7/F`.
+    pub(crate) fn chord(&self, name: &str) -> String {
+        match name.split_once('/') {
+            Some((chord, bass)) => format!("{}/{}", self.note(chord), self.note(bass)),
+            None => self.note(name),
+        }
+    }
+
+    /// Read the note at the start of typed text into English letters, or
+    /// none if it doesn't start with a note in this system.
+    fn read_note<'a>(&self, text: &'a str) -> Option<(String, &'a str)> {
+        match self.system {
+            System::English => None,
+            System::German => {
+                if let Some(rest) = strip_word(text, "H") {
+                    Some(("B".to_owned(), rest))
+                } else {
+                    strip_word(text, "B").map(|rest| ("B♭".to_owned(), rest))
+                }
+            }
+            System::FixedDo => {
+                // Ti for Si is common too
+                let words = FIXED_DO.iter().enumerate().chain([(6, &"Ti")]);
+                let readings = words.filter_map(|(letter, word)| {
+                    strip_word(text, word).map(|rest| (LETTERS[letter].to_string(), rest))
+                });
+                pick_reading(text, readings.collect())
+            }
+            System::MovableDo => {
+                let key = self.key();
+                let mut syllables = Vec::new();
+                for (step, (syllable, raised, lowered)) in MOVABLE_DO.iter().enumerate() {
+                    syllables.push((*syllable, step, 0));
+                    syllables.extend(raised.map(|s| (s, step, 1)));
+                    syllables.extend(lowered.map(|s| (s, step, -1)));
+                }
+                let readings = syllables
+                    .into_iter()
+                    .filter_map(|(syllable, step, alteration)| {
+                        let rest = strip_word(text, syllable)?;
+                        Some((Spelled::from_degree(key, step, alteration).name(), rest))
+                    })
+                    .collect();
+                pick_reading(text, readings)
+            }
+            System::Degrees => {
+                let mut alteration = 0;
+                let mut rest = text;
+                while let Some(c) = rest.chars().next() {
+                    match c {
+                        '♯' | '#' => alteration += 1,
+                        '♭' | 'b' => alteration -= 1,
+                        _ => break,
+                    }
+                    rest = &rest[c.len_utf8()..];
+                }
+                let digit = rest.chars().next()?.to_digit(10)? as usize;
+                if !(1..=7).contains(&digit) {
+                    return None;
+                }
+                let note = Spelled::from_degree(self.key(), digit - 1, alteration);
+                Some((note.name(), &rest[1..]))
+            }
+        }
+    }
+
+    /// Turn a chord typed in this system into English letters, so `Sol7`
+    /// reads as `G7` in fixed do and `H7` as `B7` in German. Text that
+    /// doesn't start with a note in this system is left as it is.
+    pub(crate) fn read(&self, text: &str) -> String {
+        let text = text.trim();
+        let read = |part: &str| match self.read_note(part) {
+            Some((note, rest)) => note + rest,
+            None => part.to_owned(),
+        };
+        match text.split_once('/') {
+            Some((chord, bass)) => format!("{}/{}", read(chord), read(bass.trim_start())),
+            None => read(text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_naming() {
+        let naming = |system, key: &str| Naming {
+            system,
+            key: key.to_owned(),
+        };
+        let german = naming(System::German, "C");
+        assert_eq!(german.note("B"), "H");
+        assert_eq!(german.note("B♭₃"), "B₃");
+        assert_eq!(german.chord("B♭m7/F♯"), "Bm7/F♯");
+        assert_eq!(german.read("H7"), "B7");
+        assert_eq!(german.read("Bmaj7"), "B♭maj7");
+
+        let fixed = naming(System::FixedDo, "C");
+        assert_eq!(fixed.chord("G7"), "Sol7");
+        assert_eq!(fixed.note("B♭"), "Si♭");
+        assert_eq!(fixed.read("Sol7"), "G7");
+        assert_eq!(fixed.read("sibm"), "Bbm");
+        assert_eq!(fixed.read("Dom/Mi"), "Cm/E");
+        // English letters still work, even when they start like a syllable
+        assert_eq!(fixed.read("Am"), "Am");
+        assert_eq!(fixed.read("Fadd9"), "Fadd9");
+        assert_eq!(fixed.read("Faug"), "Faug");
+        assert_eq!(fixed.read("Fam7"), "Fm7");
+
+        let movable = naming(System::MovableDo, "E♭");
+        assert_eq!(movable.note("E♭"), "Do");
+        assert_eq!(movable.note("G♭"), "Me");
+        assert_eq!(movable.note("A"), "Fi");
+        assert_eq!(movable.read("Sol7"), "B♭7");
+        assert_eq!(movable.read("Te"), "D♭");
+
+        let degrees = naming(System::Degrees, "G");
+        assert_eq!(degrees.chord("Em7/D"), "6m7/5");
+        assert_eq!(degrees.note("F"), "♭7");
+        assert_eq!(degrees.read("4maj7"), "Cmaj7");
+        assert_eq!(degrees.read("b7"), "F");
+        assert_eq!(degrees.read("#4"), "C♯");
+
+        assert_eq!(Naming::default().read(" H7 "), "H7");
+    }
+}
@@ -0,0 +1,281 @@
+//! Performance mode: a setlist's chords one at a time, big enough to read
+//! on stage, stepped through with the keyboard, a page turner pedal or a
+//! tap.
+
+use crate::diagram::{Diagram, WIDTH};
+use crate::library::LibrarySong;
+use crate::locale::{tr, tr_with};
+use crate::voicing::{find_voicing, Voicing};
+use klib::core::note::Note;
+use std::collections::HashMap;
+
+const CHORD_SIZE: f32 = 120.0;
+const NEXT_SIZE: f32 = 48.0;
+// How much bigger than in the diagram window the diagrams are
+const DIAGRAM_SCALE: f32 = 2.0;
+const NEXT_DIAGRAM_SCALE: f32 = 1.0;
+
+/// Where a chord is in the setlist.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Cue {
+    song: usize,
+    section: usize,
+    chord: usize,
+}
+
+pub(crate) struct Performance {
+    songs: Vec<LibrarySong>,
+    cues: Vec<Cue>,
+    position: usize,
+    // Searching is too slow to do every frame on phones, or for a whole set
+    // at once, so each chord's voicing is found when it's first shown
+    voicings: HashMap<String, Option<Voicing>>,
+}
+
+impl Performance {
+    /// Start at the first chord of the first song. Returns none if there are
+    /// no chords to play.
+    pub(crate) fn new(songs: Vec<LibrarySong>) -> Option<Self> {
+        let mut cues = Vec::new();
+        for (song, library_song) in songs.iter().enumerate() {
+            for (section, library_section) in library_song.sections.iter().enumerate() {
+                for chord in 0..library_section.progression.chords.len() {
+                    cues.push(Cue {
+                        song,
+                        section,
+                        chord,
+                    });
+                }
+            }
+        }
+        (!cues.is_empty()).then_some(Self {
+            songs,
+            cues,
+            position: 0,
+            voicings: HashMap::new(),
+        })
+    }
+
+    pub(crate) fn next(&mut self) {
+        self.position = (self.position + 1).min(self.cues.len() - 1);
+    }
+
+    pub(crate) fn previous(&mut self) {
+        self.position = self.position.saturating_sub(1);
+    }
+
+    fn chord_name(&self, cue: &Cue) -> &str {
+        let section = &self.songs[cue.song].sections[cue.section];
+        &section.progression.chords[cue.chord].name
+    }
+
+    fn voicing(&mut self, name: &str, tuning: &[Note]) -> Option<Voicing> {
+        self.voicings
+            .entry(name.to_owned())
+            .or_insert_with(|| find_voicing(name, tuning))
+            .clone()
+    }
+
+    /// Advance or go back for the keys a page turner pedal sends, or the
+    /// arrow keys. Returns whether Escape was pressed to stop.
+    fn handle_keys(&mut self, ctx: &egui::Context) -> bool {
+        use egui::Key;
+
+        let (next, previous, stop) = ctx.input(|i| {
+            let any = |keys: &[Key]| keys.iter().any(|key| i.key_pressed(*key));
+            (
+                any(&[Key::PageDown, Key::ArrowRight, Key::ArrowDown, Key::Space]),
+                any(&[Key::PageUp, Key::ArrowLeft, Key::ArrowUp, Key::Backspace]),
+                i.key_pressed(Key::Escape),
+            )
+        });
+        if next {
+            self.next();
+        }
+        if previous {
+            self.previous();
+        }
+        stop
+    }
+
+    /// Show the current and next chords. Returns false when done performing.
+    pub(crate) fn ui(&mut self, ui: &mut egui::Ui, horizontal: bool, tuning: &[Note]) -> bool {
+        if self.handle_keys(ui.ctx()) {
+            return false;
+        }
+        let mut done = false;
+        let cue = self.cues[self.position];
+        let next = self.cues.get(self.position + 1).copied();
+        let name = self.chord_name(&cue).to_owned();
+        let voicing = self.voicing(&name, tuning);
+        let next_voicing = next.and_then(|next| {
+            let name = self.chord_name(&next).to_owned();
+            self.voicing(&name, tuning)
+        });
+        let song = &self.songs[cue.song];
+
+        ui.horizontal(|ui| {
+            if ui.button(tr("⏹ Stop")).clicked() {
+                done = true;
+            }
+            ui.heading(format!(
+                "{} ({}/{}) · {}",
+                song.title,
+                cue.song + 1,
+                self.songs.len(),
+                song.sections[cue.section].name
+            ));
+        });
+        let mut details = Vec::new();
+        if let Some(key) = &song.key {
+            details.push(tr_with("Key of {}", key));
+        }
+        details.push(tr_with("{} BPM", &song.tempo.to_string()));
+        if song.capo > 0 {
+            details.push(tr_with("Capo {}", &song.capo.to_string()));
+        }
+        if song.tuning != "Standard" && !song.tuning.is_empty() {
+            details.push(song.tuning.clone());
+        }
+        ui.label(details.join(" · "));
+
+        // Taps on the right go forward, on the left go back
+        let response = ui
+            .scope(|ui| {
+                let layout = if horizontal {
+                    egui::Layout::left_to_right(egui::Align::Min)
+                } else {
+                    egui::Layout::top_down(egui::Align::Min)
+                };
+                ui.with_layout(layout, |ui| {
+                    let voicing = voicing.as_ref();
+                    chord_ui(ui, &name, voicing, CHORD_SIZE, DIAGRAM_SCALE, tuning);
+                    ui.add_space(40.0);
+
+                    ui.vertical(|ui| match next {
+                        Some(next) => {
+                            let label = if next.song == cue.song {
+                                tr("Next").to_owned()
+                            } else {
+                                tr_with("Next song: {}", &self.songs[next.song].title)
+                            };
+                            ui.label(egui::RichText::new(label).weak());
+                            chord_ui(
+                                ui,
+                                self.chord_name(&next),
+                                next_voicing.as_ref(),
+                                NEXT_SIZE,
+                                NEXT_DIAGRAM_SCALE,
+                                tuning,
+                            );
+                        }
+                        None => {
+                            ui.label(egui::RichText::new(tr("End of the set")).weak());
+                        }
+                    });
+                });
+            })
+            .response
+            .interact(egui::Sense::click());
+        if response.clicked() {
+            let tap = response.interact_pointer_pos();
+            match tap {
+                Some(pos) if pos.x < response.rect.center().x => self.previous(),
+                _ => self.next(),
+            }
+        }
+
+        ui.label(
+            egui::RichText::new(tr("Next: right arrow, Page Down or tap the right · \
+                 Back: left arrow, Page Up or tap the left · Stop: Esc"))
+            .weak(),
+        );
+        !done
+    }
+}
+
+/// A chord name in large type, with the app's voicing for it underneath.
+fn chord_ui(
+    ui: &mut egui::Ui,
+    name: &str,
+    voicing: Option<&Voicing>,
+    size: f32,
+    scale: f32,
+    tuning: &[Note],
+) {
+    ui.vertical(|ui| {
+        ui.label(egui::RichText::new(name).size(size).strong());
+        let Some(voicing) = voicing else {
+            return;
+        };
+        let diagram = Diagram::new(name, voicing, tuning);
+        let (rect, _) = ui.allocate_exact_size(
+            egui::vec2(WIDTH, diagram.height) * scale,
+            egui::Sense::hover(),
+        );
+        diagram.paint(
+            ui.painter(),
+            rect.min,
+            scale,
+            ui.visuals().strong_text_color(),
+            ui.visuals().panel_fill,
+        );
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::standard_tuning;
+    use crate::library::LibrarySection;
+    use crate::progression::Progression;
+
+    #[test]
+    fn test_steps_through_setlist() {
+        let song = |title: &str, sections: &[&str]| LibrarySong {
+            title: title.to_owned(),
+            sections: sections
+                .iter()
+                .map(|text| LibrarySection {
+                    name: "Verse".to_owned(),
+                    progression: Progression::parse(text).unwrap(),
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let mut performance = Performance::new(vec![
+            song("One", &["Am F", "C"]),
+            song("Empty", &[]),
+            song("Two", &["G:2"]),
+        ])
+        .unwrap();
+
+        let mut names = Vec::new();
+        for _ in 0..5 {
+            names.push(
+                performance
+                    .chord_name(&performance.cues[performance.position])
+                    .to_owned(),
+            );
+            performance.next();
+        }
+        // Empty songs are skipped, and it stops at the end of the set
+        assert_eq!(names, ["Am", "F", "C", "G", "G"]);
+        performance.previous();
+        assert_eq!(performance.cues[performance.position].song, 0);
+
+        assert!(Performance::new(vec![song("Empty", &[])]).is_none());
+
+        // Voicings are found once for each chord, however often it's played
+        let mut performance = Performance::new(vec![song("Three", &["Am F Am F", "Am"])]).unwrap();
+        for _ in 0..5 {
+            let name = performance
+                .chord_name(&performance.cues[performance.position])
+                .to_owned();
+            let voicing = performance.voicing(&name, &standard_tuning());
+            assert_eq!(voicing, find_voicing(&name, &standard_tuning()));
+            performance.next();
+        }
+        assert_eq!(performance.voicings.len(), 2);
+    }
+}
@@ -0,0 +1,251 @@
+//! A piano keyboard showing the chord or the voicing picked on the
+//! fretboard, so pianists and guitarists can talk about the same notes.
+
+use crate::app::MAX_FRET;
+use crate::locale::{tr, tr_with};
+use crate::spelling::Speller;
+use crate::theory::{midi_number, note_from_midi};
+use crate::voicing::Voicing;
+use klib::core::base::Parsable;
+use klib::core::chord::{Chord, HasChord};
+use klib::core::note::Note;
+
+const WHITE_WIDTH: f32 = 22.0;
+const WHITE_HEIGHT: f32 = 100.0;
+const BLACK_WIDTH: f32 = 14.0;
+const BLACK_HEIGHT: f32 = 62.0;
+const BLACK_CLASSES: [i32; 5] = [1, 3, 6, 8, 10];
+const NAME_SIZE: f32 = 10.0;
+
+fn is_black(midi: i32) -> bool {
+    BLACK_CLASSES.contains(&midi.rem_euclid(12))
+}
+
+/// The keys from the C below the open low string to the B above the
+/// highest fret, as MIDI note numbers.
+fn range(tuning: &[Note]) -> (i32, i32) {
+    let midis = tuning.iter().map(|note| midi_number(*note));
+    let low = midis.clone().min().unwrap_or(48);
+    let high = midis.max().unwrap_or(72) + MAX_FRET as i32 - 1;
+    (low - low.rem_euclid(12), high + 11 - high.rem_euclid(12))
+}
+
+/// Where each key is drawn relative to the keyboard's top left. White keys
+/// come first so the black ones are painted over them.
+fn keys(low: i32, high: i32) -> Vec<(i32, egui::Rect)> {
+    let mut whites = Vec::new();
+    let mut blacks = Vec::new();
+    let mut x = 0.0;
+    for midi in low..=high {
+        if is_black(midi) {
+            blacks.push((
+                midi,
+                egui::Rect::from_min_size(
+                    egui::pos2(x - BLACK_WIDTH / 2.0, 0.0),
+                    egui::vec2(BLACK_WIDTH, BLACK_HEIGHT),
+                ),
+            ));
+        } else {
+            whites.push((
+                midi,
+                egui::Rect::from_min_size(
+                    egui::pos2(x, 0.0),
+                    egui::vec2(WHITE_WIDTH, WHITE_HEIGHT),
+                ),
+            ));
+            x += WHITE_WIDTH;
+        }
+    }
+    whites.extend(blacks);
+    whites
+}
+
+/// The key under a point, looking at the black keys first since they're on
+/// top.
+fn key_at(keys: &[(i32, egui::Rect)], pos: egui::Pos2) -> Option<i32> {
+    keys.iter()
+        .rev()
+        .find(|(_, rect)| rect.contains(pos))
+        .map(|(midi, _)| *midi)
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+#[serde(default)]
+pub(crate) struct Piano {
+    pub(crate) open: bool,
+    // The key last clicked, shown everywhere it's played on the fretboard
+    #[serde(skip)]
+    highlighted: Option<i32>,
+}
+
+impl Piano {
+    /// The pitch to highlight on the fretboard, as a MIDI note number.
+    pub(crate) fn highlighted(&self) -> Option<i32> {
+        self.highlighted.filter(|_| self.open)
+    }
+
+    /// Show the keyboard. Returns the key clicked, to be played.
+    pub(crate) fn show(
+        &mut self,
+        ctx: &egui::Context,
+        chord: &str,
+        selection: &Voicing,
+        tuning: &[Note],
+        speller: &Speller,
+    ) -> Option<i32> {
+        let mut open = self.open;
+        // The title is translated, so the window keeps its place by id
+        let clicked = egui::Window::new(tr("Piano"))
+            .id(egui::Id::new("piano"))
+            .open(&mut open)
+            .show(ctx, |ui| self.ui(ui, chord, selection, tuning, speller))
+            .and_then(|response| response.inner)
+            .flatten();
+        self.open = open;
+        clicked
+    }
+
+    fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        chord: &str,
+        selection: &Voicing,
+        tuning: &[Note],
+        speller: &Speller,
+    ) -> Option<i32> {
+        // The voicing's notes are in the octaves they're played in, the
+        // chord's are wherever kord puts them
+        let voicing: Vec<i32> = selection
+            .notes(tuning)
+            .iter()
+            .map(|(_, note)| midi_number(*note))
+            .collect();
+        let (tones, caption) = if !voicing.is_empty() {
+            (
+                voicing,
+                tr("The voicing picked on the fretboard").to_owned(),
+            )
+        } else {
+            match Chord::parse(chord) {
+                Ok(parsed) => (
+                    parsed
+                        .chord()
+                        .iter()
+                        .map(|note| midi_number(*note))
+                        .collect(),
+                    tr_with("{}, with other octaves dotted", chord),
+                ),
+                Err(_) => (Vec::new(), tr("Type a chord to show it here").to_owned()),
+            }
+        };
+        let classes: Vec<i32> = tones.iter().map(|midi| midi.rem_euclid(12)).collect();
+        ui.label(caption);
+
+        let (low, high) = range(tuning);
+        let keys = keys(low, high);
+        let whites = keys.iter().filter(|(midi, _)| !is_black(*midi)).count();
+        let (rect, response) = ui.allocate_exact_size(
+            egui::vec2(whites as f32 * WHITE_WIDTH, WHITE_HEIGHT),
+            egui::Sense::click(),
+        );
+
+        let visuals = ui.visuals();
+        let painter = ui.painter();
+        let outline = egui::Stroke::new(1.0, egui::Color32::from_gray(80));
+        for (midi, key) in &keys {
+            let key = key.translate(rect.min.to_vec2());
+            let black = is_black(*midi);
+            let played = tones.contains(midi);
+            let (fill, text_color) = match (played, black) {
+                (true, _) => (visuals.selection.bg_fill, visuals.selection.stroke.color),
+                (false, true) => (egui::Color32::from_gray(30), egui::Color32::from_gray(200)),
+                (false, false) => (egui::Color32::from_gray(245), egui::Color32::from_gray(90)),
+            };
+            painter.rect(key, 2.0, fill, outline);
+
+            let label_at = key.center_bottom() - egui::vec2(0.0, 10.0);
+            let font = egui::FontId::proportional(NAME_SIZE);
+            if played {
+                let name = speller.name(note_from_midi(*midi), true);
+                painter.text(
+                    label_at,
+                    egui::Align2::CENTER_CENTER,
+                    name,
+                    font,
+                    text_color,
+                );
+            } else if classes.contains(&midi.rem_euclid(12)) {
+                painter.circle_filled(label_at, 3.0, visuals.selection.bg_fill);
+            } else if midi.rem_euclid(12) == 0 {
+                // Label each C so the octaves can be found
+                let name = speller.name(note_from_midi(*midi), false);
+                painter.text(
+                    label_at,
+                    egui::Align2::CENTER_CENTER,
+                    name,
+                    font,
+                    text_color,
+                );
+            }
+        }
+        // Over the black keys, which would hide part of a white key's outline
+        let highlighted = keys
+            .iter()
+            .find(|(midi, _)| self.highlighted == Some(*midi));
+        if let Some((_, key)) = highlighted {
+            painter.rect_stroke(
+                key.translate(rect.min.to_vec2()).shrink(1.0),
+                2.0,
+                egui::Stroke::new(2.0, visuals.warn_fg_color),
+            );
+        }
+
+        let clicked = response
+            .interact_pointer_pos()
+            .filter(|_| response.clicked())
+            .and_then(|pos| key_at(&keys, pos - rect.min.to_vec2()));
+        if let Some(midi) = clicked {
+            self.highlighted = (self.highlighted != Some(midi)).then_some(midi);
+        }
+
+        match self.highlighted {
+            Some(midi) => ui.label(tr_with(
+                "{} is outlined wherever it's played on the fretboard",
+                &speller.name(note_from_midi(midi), false),
+            )),
+            None => ui.label(
+                egui::RichText::new(tr("Click a key to hear it and find it on the fretboard"))
+                    .weak(),
+            ),
+        };
+        clicked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::standard_tuning;
+
+    #[test]
+    fn test_keys() {
+        // Standard tuning goes from E2 to G5 at the highest fret
+        assert_eq!(range(&standard_tuning()), (36, 83));
+
+        let keys = keys(60, 71);
+        assert_eq!(keys.iter().filter(|(midi, _)| is_black(*midi)).count(), 5);
+        // The top of the gap between C and D is C♯, the bottom is C or D
+        let between = WHITE_WIDTH;
+        assert_eq!(key_at(&keys, egui::pos2(between, 10.0)), Some(61));
+        assert_eq!(key_at(&keys, egui::pos2(between - 2.0, 90.0)), Some(60));
+        assert_eq!(key_at(&keys, egui::pos2(between + 2.0, 90.0)), Some(62));
+        assert_eq!(
+            key_at(&keys, egui::pos2(7.0 * WHITE_WIDTH - 1.0, 10.0)),
+            Some(71)
+        );
+        assert_eq!(
+            key_at(&keys, egui::pos2(7.0 * WHITE_WIDTH + 1.0, 10.0)),
+            None
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! A small PNG encoder, so exporting images doesn't pull an image crate into
+//! the wasm build.
+//!
+//! Rows use the "up" filter and are compressed with LZ77 and the fixed
+//! Huffman codes from the deflate spec. That's nowhere near as good as zlib,
+//! but screenshots of the app are mostly flat colour and compress well
+//! anyway.
+
+/// Encode 8 bit RGBA pixels (not premultiplied) as a PNG file.
+pub(crate) fn encode_rgba(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let stride = width as usize * 4;
+    assert_eq!(rgba.len(), stride * height as usize);
+
+    // Each row starts with its filter type, "up" stores the difference from
+    // the row above so repeated rows become zeros
+    let mut filtered = Vec::with_capacity(rgba.len() + height as usize);
+    for y in 0..height as usize {
+        filtered.push(2);
+        let row = &rgba[y * stride..(y + 1) * stride];
+        if y == 0 {
+            filtered.extend_from_slice(row);
+        } else {
+            let above = &rgba[(y - 1) * stride..y * stride];
+            filtered.extend(row.iter().zip(above).map(|(a, b)| a.wrapping_sub(*b)));
+        }
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, adaptive filtering, no interlacing
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib(&filtered));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before the modulo is needed
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// Writes bits least significant first, the way deflate packs them.
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, bits: u32) {
+        self.buffer |= value << self.bits;
+        self.bits += bits;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    /// Huffman codes are stored most significant bit first.
+    fn write_code(&mut self, code: u32, bits: u32) {
+        let reversed = code.reverse_bits() >> (32 - bits);
+        self.write(reversed, bits);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+// (base length, extra bits) for length codes 257 to 285
+#[rustfmt::skip]
+const LENGTHS: [(u16, u8); 29] = [
+    (3, 0), (4, 0), (5, 0), (6, 0), (7, 0), (8, 0), (9, 0), (10, 0),
+    (11, 1), (13, 1), (15, 1), (17, 1), (19, 2), (23, 2), (27, 2), (31, 2),
+    (35, 3), (43, 3), (51, 3), (59, 3), (67, 4), (83, 4), (99, 4), (115, 4),
+    (131, 5), (163, 5), (195, 5), (227, 5), (258, 0),
+];
+
+// (base distance, extra bits) for distance codes 0 to 29
+#[rustfmt::skip]
+const DISTANCES: [(u16, u8); 30] = [
+    (1, 0), (2, 0), (3, 0), (4, 0), (5, 1), (7, 1), (9, 2), (13, 2),
+    (17, 3), (25, 3), (33, 4), (49, 4), (65, 5), (97, 5), (129, 6), (193, 6),
+    (257, 7), (385, 7), (513, 8), (769, 8), (1025, 9), (1537, 9), (2049, 10), (3073, 10),
+    (4097, 11), (6145, 11), (8193, 12), (12289, 12), (16385, 13), (24577, 13),
+];
+
+const WINDOW: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+// How many earlier positions to try for each match
+const MAX_CHAIN: usize = 32;
+
+fn write_symbol(out: &mut BitWriter, symbol: u16) {
+    // The fixed literal/length code from section 3.2.6 of RFC 1951
+    match symbol {
+        0..=143 => out.write_code(0x30 + symbol as u32, 8),
+        144..=255 => out.write_code(0x190 + (symbol as u32 - 144), 9),
+        256..=279 => out.write_code(symbol as u32 - 256, 7),
+        _ => out.write_code(0xc0 + (symbol as u32 - 280), 8),
+    }
+}
+
+fn write_match(out: &mut BitWriter, length: usize, distance: usize) {
+    let code = LENGTHS
+        .iter()
+        .rposition(|(base, _)| *base as usize <= length)
+        .unwrap();
+    let (base, extra) = LENGTHS[code];
+    write_symbol(out, 257 + code as u16);
+    out.write((length - base as usize) as u32, extra as u32);
+
+    let code = DISTANCES
+        .iter()
+        .rposition(|(base, _)| *base as usize <= distance)
+        .unwrap();
+    let (base, extra) = DISTANCES[code];
+    // Distance codes are all five bits in the fixed code
+    out.write_code(code as u32, 5);
+    out.write((distance - base as usize) as u32, extra as u32);
+}
+
+fn hash(data: &[u8]) -> usize {
+    let value = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+    (value.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Compress with a single fixed Huffman deflate block, wrapped for zlib.
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut out = BitWriter {
+        // Deflate with a 32K window and no preset dictionary
+        bytes: vec![0x78, 0x01],
+        buffer: 0,
+        bits: 0,
+    };
+    // Final block, fixed Huffman codes
+    out.write(1, 1);
+    out.write(1, 2);
+
+    // The most recent position for each hash, and the one before that for
+    // each position
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; data.len()];
+    let insert = |head: &mut [usize], previous: &mut [usize], pos: usize| {
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash(&data[pos..]);
+            previous[pos] = head[h];
+            head[h] = pos;
+        }
+    };
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let mut candidate = head[hash(&data[pos..])];
+            let max = MAX_MATCH.min(data.len() - pos);
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || pos - candidate > WINDOW {
+                    break;
+                }
+                let length = (0..max)
+                    .take_while(|i| data[candidate + i] == data[pos + i])
+                    .count();
+                if length > best.0 {
+                    best = (length, pos - candidate);
+                    if length == max {
+                        break;
+                    }
+                }
+                candidate = previous[candidate];
+            }
+        }
+
+        if best.0 >= MIN_MATCH {
+            write_match(&mut out, best.0, best.1);
+            for p in pos..pos + best.0 {
+                insert(&mut head, &mut previous, p);
+            }
+            pos += best.0;
+        } else {
+            write_symbol(&mut out, data[pos] as u16);
+            insert(&mut head, &mut previous, pos);
+            pos += 1;
+        }
+    }
+    write_symbol(&mut out, 256);
+
+    let mut bytes = out.finish();
+    bytes.extend_from_slice(&adler32(data).to_be_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_encode() {
+        // A 2x2 image: red, green / blue, transparent
+        let pixels = [255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 0, 0, 0, 0];
+        let png = encode_rgba(2, 2, &pixels);
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert_eq!(&png[12..16], b"IHDR");
+        assert!(png.ends_with(&[0xae, 0x42, 0x60, 0x82]));
+
+        // Flat colour should compress to almost nothing
+        let flat = vec![200; 400 * 300 * 4];
+        assert!(encode_rgba(400, 300, &flat).len() < 4000);
+    }
+}
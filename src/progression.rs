@@ -0,0 +1,108 @@
+use crate::locale::{tr_fill, tr_with};
+use crate::symbol::normalize;
+use klib::core::base::Parsable;
+use klib::core::chord::Chord;
+
+/// A chord in a progression, held for a number of bars.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct ProgressionChord {
+    pub(crate) name: String,
+    pub(crate) bars: u32,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default, PartialEq)]
+pub(crate) struct Progression {
+    pub(crate) chords: Vec<ProgressionChord>,
+}
+
+impl Progression {
+    /// Parse a space or comma separated list of chords, each optionally
+    /// followed by the number of bars to hold it for, e.g. `Am7:2 D7:2 G:4`.
+    pub(crate) fn parse(text: &str) -> Result<Self, String> {
+        let mut chords = Vec::new();
+        for token in text.split(|c: char| c == ',' || c.is_whitespace()) {
+            if token.is_empty() {
+                continue;
+            }
+
+            let (name, bars) = match token.rsplit_once(':') {
+                Some((name, bars)) => match bars.parse::<u32>() {
+                    Ok(bars) if bars > 0 => (name, bars),
+                    _ => return Err(tr_with("Invalid number of bars: {}", token)),
+                },
+                None => (token, 1),
+            };
+
+            let name = normalize(name)
+                .map_err(|e| tr_fill("Invalid chord {}: {}", &[name, &e.message()]))?;
+            if Chord::parse(&name).is_err() {
+                return Err(tr_with("Invalid chord: {}", &name));
+            }
+            chords.push(ProgressionChord { name, bars });
+        }
+        Ok(Self { chords })
+    }
+
+    /// Write the progression the way [`Progression::parse`] reads it, with
+    /// bar counts only for chords held longer than a bar.
+    pub(crate) fn text(&self) -> String {
+        let chords: Vec<String> = self
+            .chords
+            .iter()
+            .map(|chord| match chord.bars {
+                1 => chord.name.clone(),
+                bars => format!("{}:{}", chord.name, bars),
+            })
+            .collect();
+        chords.join(" ")
+    }
+
+    pub(crate) fn total_bars(&self) -> u32 {
+        self.chords.iter().map(|c| c.bars).sum()
+    }
+
+    /// Index of the chord playing in the given bar (counting from 0), looping
+    /// around at the end of the progression.
+    pub(crate) fn chord_at_bar(&self, bar: u32) -> Option<usize> {
+        let total = self.total_bars();
+        if total == 0 {
+            return None;
+        }
+
+        let mut bar = bar % total;
+        for (i, chord) in self.chords.iter().enumerate() {
+            if bar < chord.bars {
+                return Some(i);
+            }
+            bar -= chord.bars;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_durations() {
+        let progression = Progression::parse("Am7:2 D7:2, G:4 C").unwrap();
+        let bars: Vec<u32> = progression.chords.iter().map(|c| c.bars).collect();
+        assert_eq!(bars, [2, 2, 4, 1]);
+        assert_eq!(progression.total_bars(), 9);
+
+        assert_eq!(progression.text(), "Am7:2 D7:2 G:4 C");
+
+        assert!(Progression::parse("Am7:0").is_err());
+        assert!(Progression::parse("Am7:x").is_err());
+    }
+
+    #[test]
+    fn test_chord_at_bar_loops() {
+        let progression = Progression::parse("Am7:2 D7:2 G:4").unwrap();
+        let indices: Vec<Option<usize>> =
+            (0..10).map(|bar| progression.chord_at_bar(bar)).collect();
+        assert_eq!(indices, [0, 0, 1, 1, 2, 2, 2, 2, 0, 0].map(Some));
+        assert_eq!(Progression::default().chord_at_bar(0), None);
+    }
+}
@@ -0,0 +1,391 @@
+use crate::app::{feedback_label, note_for_fret, string_name, MAX_FRET};
+use crate::locale::{tr, tr_fill, tr_with};
+use crate::random::Rng;
+use crate::review::{self, Reviews};
+use crate::spelling::Speller;
+use crate::theory::pitch_class;
+use klib::core::note::Note;
+use klib::core::pitch::HasPitch;
+
+const NATURALS: [usize; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq)]
+enum QuizMode {
+    /// Name a note and string, the user finds it on the fretboard
+    FindNote,
+    /// Highlight a fret, the user names the note
+    NameNote,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+pub(crate) struct PositionStats {
+    pub(crate) attempts: u32,
+    pub(crate) correct: u32,
+    pub(crate) total_seconds: f32,
+}
+
+impl PositionStats {
+    fn accuracy(&self) -> Option<f32> {
+        (self.attempts > 0).then(|| self.correct as f32 / self.attempts as f32)
+    }
+
+    fn average_seconds(&self) -> Option<f32> {
+        (self.attempts > 0).then(|| self.total_seconds / self.attempts as f32)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Question {
+    string: usize,
+    fret: usize,
+    asked_at: f64,
+}
+
+fn review_key(string: usize, fret: usize) -> String {
+    format!("note:{}:{}", string, fret)
+}
+
+/// How an answer on the fretboard was judged.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Mark {
+    Correct,
+    Wrong,
+    Missing,
+}
+
+/// How the fretboard should draw a position while a quiz is running.
+pub(crate) struct QuizCell {
+    pub(crate) enabled: bool,
+    pub(crate) selected: bool,
+    pub(crate) show_name: bool,
+    pub(crate) mark: Option<Mark>,
+}
+
+/// Fretboard memorisation quiz, in both directions.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub(crate) struct Quiz {
+    pub(crate) open: bool,
+    mode: QuizMode,
+    highest_fret: usize,
+    naturals_only: bool,
+    // Indexed by string (as in the tuning) then fret
+    stats: Vec<Vec<PositionStats>>,
+
+    #[serde(skip)]
+    question: Option<Question>,
+    #[serde(skip)]
+    feedback: Option<(bool, String)>,
+    #[serde(skip)]
+    rng: Rng,
+}
+
+impl Default for Quiz {
+    fn default() -> Self {
+        Self {
+            open: false,
+            mode: QuizMode::FindNote,
+            highest_fret: 12,
+            naturals_only: false,
+            stats: Vec::new(),
+            question: None,
+            feedback: None,
+            rng: Rng::default(),
+        }
+    }
+}
+
+impl Quiz {
+    /// How to draw a fretboard position, if the quiz has taken over the
+    /// fretboard.
+    pub(crate) fn cell(&self, string: usize, fret: usize) -> Option<QuizCell> {
+        let question = self.question.as_ref().filter(|_| self.open)?;
+        let target = question.string == string && question.fret == fret;
+        Some(match self.mode {
+            QuizMode::FindNote => QuizCell {
+                enabled: true,
+                selected: false,
+                show_name: false,
+                mark: None,
+            },
+            QuizMode::NameNote => QuizCell {
+                enabled: target,
+                selected: target,
+                show_name: false,
+                mark: None,
+            },
+        })
+    }
+
+    fn stats_mut(&mut self, string: usize, fret: usize) -> &mut PositionStats {
+        if self.stats.len() <= string {
+            self.stats.resize(string + 1, Vec::new());
+        }
+        let frets = &mut self.stats[string];
+        if frets.len() <= fret {
+            frets.resize(fret + 1, PositionStats::default());
+        }
+        &mut frets[fret]
+    }
+
+    fn ask(&mut self, tuning: &[Note], reviews: &Reviews, now: f64) {
+        let positions: Vec<(usize, usize)> = (0..tuning.len())
+            .flat_map(|string| (0..=self.highest_fret).map(move |fret| (string, fret)))
+            .filter(|(string, fret)| {
+                let class = pitch_class(note_for_fret(tuning[*string], *fret).pitch());
+                !self.naturals_only || NATURALS.contains(&class)
+            })
+            .collect();
+
+        // Positions that are due for review come first
+        let keys: Vec<String> = positions
+            .iter()
+            .map(|(string, fret)| review_key(*string, *fret))
+            .collect();
+        let last = self.question.map(|q| review_key(q.string, q.fret));
+        let next = positions[reviews.pick(&keys, last.as_deref(), &mut self.rng)];
+
+        self.question = Some(Question {
+            string: next.0,
+            fret: next.1,
+            asked_at: now,
+        });
+    }
+
+    fn record(
+        &mut self,
+        tuning: &[Note],
+        reviews: &mut Reviews,
+        correct: bool,
+        feedback: String,
+        now: f64,
+    ) {
+        let Some(Question {
+            string,
+            fret,
+            asked_at,
+        }) = self.question
+        else {
+            return;
+        };
+
+        let seconds = (now - asked_at) as f32;
+        let stats = self.stats_mut(string, fret);
+        stats.attempts += 1;
+        stats.total_seconds += seconds;
+        if correct {
+            stats.correct += 1;
+        }
+        reviews.record(&review_key(string, fret), review::quality(correct, seconds));
+
+        self.feedback = Some((correct, format!("{} ({:.1}s)", feedback, seconds)));
+        self.ask(tuning, reviews, now);
+    }
+
+    /// A position on the fretboard was clicked.
+    pub(crate) fn answer_position(
+        &mut self,
+        tuning: &[Note],
+        reviews: &mut Reviews,
+        speller: &Speller,
+        string: usize,
+        fret: usize,
+        now: f64,
+    ) {
+        let Some(question) = &self.question else {
+            return;
+        };
+        if self.mode != QuizMode::FindNote {
+            return;
+        }
+
+        let target = note_for_fret(tuning[question.string], question.fret);
+        let clicked = note_for_fret(tuning[string], fret);
+        // Any fret with the same pitch on the right string counts, e.g. the
+        // open string and the 12th fret
+        let correct = string == question.string
+            && pitch_class(clicked.pitch()) == pitch_class(target.pitch());
+
+        let feedback = if correct {
+            tr("✔ Correct").to_owned()
+        } else {
+            tr_fill(
+                "✘ That was {} on the {} string, {} is at fret {}",
+                &[
+                    &speller.name(clicked, true),
+                    &string_name(tuning, string),
+                    &speller.name(target, true),
+                    &question.fret.to_string(),
+                ],
+            )
+        };
+        self.record(tuning, reviews, correct, feedback, now);
+    }
+
+    fn answer_name(
+        &mut self,
+        tuning: &[Note],
+        reviews: &mut Reviews,
+        speller: &Speller,
+        class: usize,
+        now: f64,
+    ) {
+        let Some(question) = &self.question else {
+            return;
+        };
+
+        let target = note_for_fret(tuning[question.string], question.fret);
+        let correct = pitch_class(target.pitch()) == class;
+        let feedback = if correct {
+            tr("✔ Correct").to_owned()
+        } else {
+            tr_with("✘ That was {}", &speller.name(target, true))
+        };
+        self.record(tuning, reviews, correct, feedback, now);
+    }
+
+    pub(crate) fn show(
+        &mut self,
+        ctx: &egui::Context,
+        tuning: &[Note],
+        reviews: &mut Reviews,
+        speller: &Speller,
+    ) {
+        let mut open = self.open;
+        egui::Window::new(tr("Fretboard quiz"))
+            .id(egui::Id::new("fretboard_quiz"))
+            .open(&mut open)
+            .show(ctx, |ui| self.ui(ui, tuning, reviews, speller));
+        self.open = open;
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, tuning: &[Note], reviews: &mut Reviews, speller: &Speller) {
+        let now = ui.input(|i| i.time);
+
+        ui.add_enabled_ui(self.question.is_none(), |ui| {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.mode, QuizMode::FindNote, tr("Find the note"));
+                ui.radio_value(&mut self.mode, QuizMode::NameNote, tr("Name the note"));
+            });
+            ui.horizontal(|ui| {
+                ui.label(tr("Up to fret"));
+                ui.add(egui::DragValue::new(&mut self.highest_fret).clamp_range(1..=MAX_FRET - 1));
+                ui.checkbox(&mut self.naturals_only, tr("Natural notes only"));
+            });
+        });
+
+        ui.horizontal(|ui| {
+            if self.question.is_none() {
+                if ui.button(tr("▶ Start")).clicked() {
+                    self.rng.seed(now);
+                    self.feedback = None;
+                    self.ask(tuning, reviews, now);
+                }
+            } else if ui.button(tr("⏹ Stop")).clicked() {
+                self.question = None;
+            }
+        });
+
+        if let Some(question) = self.question {
+            let target = note_for_fret(tuning[question.string], question.fret);
+            let prompt = match self.mode {
+                QuizMode::FindNote => tr_fill(
+                    "Find {} on the {} string",
+                    &[
+                        &speller.name(target, true),
+                        &string_name(tuning, question.string),
+                    ],
+                ),
+                QuizMode::NameNote => tr("Name the highlighted note").to_owned(),
+            };
+            ui.heading(egui::RichText::new(prompt).size(24.0));
+
+            if self.mode == QuizMode::NameNote {
+                ui.horizontal_wrapped(|ui| {
+                    for class in 0..12 {
+                        let name = speller.name(note_for_fret(klib::core::note::C, class), true);
+                        if ui.button(name).clicked() {
+                            self.answer_name(tuning, reviews, speller, class, now);
+                        }
+                    }
+                });
+            }
+        }
+
+        feedback_label(ui, &self.feedback);
+
+        ui.separator();
+        self.stats_ui(ui, tuning);
+    }
+
+    fn stats_ui(&mut self, ui: &mut egui::Ui, tuning: &[Note]) {
+        let (attempts, correct) = self
+            .stats
+            .iter()
+            .flatten()
+            .fold((0, 0), |(a, c), s| (a + s.attempts, c + s.correct));
+        if attempts == 0 {
+            ui.label(tr("No answers yet"));
+            return;
+        }
+
+        ui.label(tr_fill(
+            "{} of {} correct ({}%)",
+            &[
+                &correct.to_string(),
+                &attempts.to_string(),
+                &format!("{:.0}", 100.0 * correct as f32 / attempts as f32),
+            ],
+        ));
+
+        egui::Grid::new("quiz_stats")
+            .spacing([2.0, 2.0])
+            .show(ui, |ui| {
+                ui.label("");
+                for fret in 0..=self.highest_fret {
+                    ui.label(egui::RichText::new(fret.to_string()).small());
+                }
+                ui.end_row();
+
+                for string in 0..tuning.len() {
+                    ui.label(egui::RichText::new(string_name(tuning, string)).small());
+                    for fret in 0..=self.highest_fret {
+                        let stats = self
+                            .stats
+                            .get(string)
+                            .and_then(|s| s.get(fret))
+                            .cloned()
+                            .unwrap_or_default();
+                        let color = match stats.accuracy() {
+                            Some(accuracy) => egui::Color32::from_rgb(
+                                (255.0 * (1.0 - accuracy)) as u8,
+                                (200.0 * accuracy) as u8,
+                                60,
+                            ),
+                            None => ui.visuals().widgets.inactive.bg_fill,
+                        };
+                        let (rect, response) =
+                            ui.allocate_exact_size([16.0, 16.0].into(), egui::Sense::hover());
+                        ui.painter().rect_filled(rect, 2.0, color);
+                        if let (Some(accuracy), Some(seconds)) =
+                            (stats.accuracy(), stats.average_seconds())
+                        {
+                            response.on_hover_text(tr_fill(
+                                "{}% of {} correct, {}s on average",
+                                &[
+                                    &format!("{:.0}", accuracy * 100.0),
+                                    &stats.attempts.to_string(),
+                                    &format!("{:.1}", seconds),
+                                ],
+                            ));
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+
+        if ui.button(tr("Reset statistics")).clicked() {
+            self.stats.clear();
+        }
+    }
+}
@@ -0,0 +1,34 @@
+/// A small xorshift generator. The quizzes don't need anything better, and it
+/// keeps a rand dependency out of the wasm build.
+pub(crate) struct Rng(u64);
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self(0x9e37_79b9_7f4a_7c15)
+    }
+}
+
+impl Rng {
+    /// Seed from something that varies between runs, e.g. the UI time.
+    pub(crate) fn seed(&mut self, seed: f64) {
+        self.0 ^= (seed * 1000.0) as u64;
+        if self.0 == 0 {
+            *self = Self::default();
+        }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `0..n`, or 0 if `n` is 0.
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        if n == 0 {
+            return 0;
+        }
+        (self.next_u64() % n as u64) as usize
+    }
+}
@@ -0,0 +1,450 @@
+//! Spaced repetition for the practice modes, using a variant of SM-2.
+//!
+//! Every practice item (a fretboard position, an interval, a chord shape...)
+//! gets a key like `note:0:5`, and the modes ask the scheduler which of their
+//! candidates to present next. Items that are due come first, then ones that
+//! haven't been seen, then whatever is due soonest.
+
+use crate::app::feedback_label;
+use crate::locale::{tr, tr_fill, tr_with};
+use crate::random::Rng;
+use std::collections::BTreeMap;
+
+// Wrong answers come back after ten minutes, so within the same session
+const RELEARN_DAYS: f64 = 10.0 / (24.0 * 60.0);
+const MIN_EASINESS: f32 = 1.3;
+// Bumped when the export format changes in a way older builds can't read
+const EXPORT_VERSION: u32 = 1;
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub(crate) struct ReviewItem {
+    easiness: f32,
+    repetitions: u32,
+    interval_days: f64,
+    // Days since the Unix epoch
+    due: f64,
+    last_reviewed: f64,
+    attempts: u32,
+    correct: u32,
+}
+
+impl Default for ReviewItem {
+    fn default() -> Self {
+        Self {
+            easiness: 2.5,
+            repetitions: 0,
+            interval_days: 0.0,
+            due: 0.0,
+            last_reviewed: 0.0,
+            attempts: 0,
+            correct: 0,
+        }
+    }
+}
+
+impl ReviewItem {
+    /// Update the schedule after an answer graded from 0 (blackout) to 5
+    /// (perfect), like SM-2.
+    fn review(&mut self, quality: u8, today: f64) {
+        let quality = quality.min(5);
+        let q = (5 - quality) as f32;
+        self.easiness = (self.easiness + 0.1 - q * (0.08 + q * 0.02)).max(MIN_EASINESS);
+
+        self.attempts += 1;
+        if quality >= 3 {
+            self.correct += 1;
+            self.interval_days = match self.repetitions {
+                0 => 1.0,
+                1 => 6.0,
+                _ => self.interval_days * self.easiness as f64,
+            };
+            self.repetitions += 1;
+        } else {
+            self.repetitions = 0;
+            self.interval_days = RELEARN_DAYS;
+        }
+
+        self.due = today + self.interval_days;
+        self.last_reviewed = today;
+    }
+
+    /// What's wrong with an item from a file, if anything. NaN or infinite
+    /// numbers would carry on into every later review.
+    fn problem(&self) -> Option<&'static str> {
+        if !self.due.is_finite() || !self.last_reviewed.is_finite() {
+            Some("a bad date")
+        } else if !self.interval_days.is_finite() || self.interval_days < 0.0 {
+            Some("a bad interval")
+        } else if !self.easiness.is_finite() {
+            Some("a bad easiness")
+        } else if self.correct > self.attempts {
+            Some("more right answers than attempts")
+        } else {
+            None
+        }
+    }
+}
+
+/// Grade an answer for the scheduler from whether it was right and how long
+/// it took.
+pub(crate) fn quality(correct: bool, seconds: f32) -> u8 {
+    match (correct, seconds) {
+        (false, _) => 1,
+        (true, s) if s < 3.0 => 5,
+        (true, s) if s < 8.0 => 4,
+        (true, _) => 3,
+    }
+}
+
+/// The current time in days since the Unix epoch.
+pub(crate) fn today() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        js_sys::Date::now() / 86_400_000.0
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs_f64() / 86_400.0)
+            .unwrap_or(0.0)
+    }
+}
+
+/// What gets exported, kept separate so the format doesn't change when the
+/// window does.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct Export {
+    version: u32,
+    items: BTreeMap<String, ReviewItem>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+#[serde(default)]
+pub(crate) struct Reviews {
+    pub(crate) open: bool,
+    items: BTreeMap<String, ReviewItem>,
+
+    #[serde(skip)]
+    import_text: String,
+    #[serde(skip)]
+    message: Option<(bool, String)>,
+}
+
+impl Reviews {
+    pub(crate) fn record(&mut self, key: &str, quality: u8) {
+        self.record_at(key, quality, today());
+    }
+
+    fn record_at(&mut self, key: &str, quality: u8, today: f64) {
+        self.items
+            .entry(key.to_owned())
+            .or_default()
+            .review(quality, today);
+    }
+
+    /// Index of the candidate to present next: the most overdue one, or one
+    /// that hasn't been seen yet, or failing that the one due soonest.
+    /// `avoid` is skipped if there's anything else, so the same question
+    /// isn't asked twice in a row.
+    pub(crate) fn pick(&self, keys: &[String], avoid: Option<&str>, rng: &mut Rng) -> usize {
+        self.pick_at(keys, avoid, rng, today())
+    }
+
+    fn pick_at(&self, keys: &[String], avoid: Option<&str>, rng: &mut Rng, today: f64) -> usize {
+        let candidates: Vec<usize> = (0..keys.len())
+            .filter(|i| keys.len() == 1 || Some(keys[*i].as_str()) != avoid)
+            .collect();
+
+        let due = |i: &usize| self.items.get(&keys[*i]).map(|item| item.due);
+        let sooner = |a: &&usize, b: &&usize| {
+            let never = f64::INFINITY;
+            due(a).unwrap_or(never).total_cmp(&due(b).unwrap_or(never))
+        };
+        if let Some(i) = candidates
+            .iter()
+            .filter(|i| due(i).is_some_and(|d| d <= today))
+            .min_by(sooner)
+        {
+            return *i;
+        }
+
+        let unseen: Vec<usize> = candidates
+            .iter()
+            .copied()
+            .filter(|i| due(i).is_none())
+            .collect();
+        if !unseen.is_empty() {
+            return unseen[rng.below(unseen.len())];
+        }
+
+        candidates.iter().min_by(sooner).copied().unwrap_or(0)
+    }
+
+    fn export(&self) -> Result<String, String> {
+        let export = Export {
+            version: EXPORT_VERSION,
+            items: self.items.clone(),
+        };
+        ron::ser::to_string_pretty(&export, Default::default()).map_err(|e| e.to_string())
+    }
+
+    /// Merge exported progress in, keeping whichever copy of each item was
+    /// reviewed most recently. Returns the number of items taken.
+    pub(crate) fn import(&mut self, text: &str) -> Result<usize, String> {
+        let export: Export = ron::from_str(text)
+            .map_err(|e| tr_with("Invalid progress file: {}", &e.to_string()))?;
+        if export.version != EXPORT_VERSION {
+            return Err(tr_fill(
+                "Progress files from version {} of the format can't be read, only {}",
+                &[&export.version.to_string(), &EXPORT_VERSION.to_string()],
+            ));
+        }
+        let problem = export
+            .items
+            .iter()
+            .find_map(|(key, item)| Some((key, item.problem()?)));
+        if let Some((key, problem)) = problem {
+            return Err(tr_fill(
+                "Invalid progress file: {} has {}",
+                &[key, tr(problem)],
+            ));
+        }
+
+        let mut taken = 0;
+        for (key, item) in export.items {
+            let newer = match self.items.get(&key) {
+                Some(ours) => item.last_reviewed > ours.last_reviewed,
+                None => true,
+            };
+            if newer {
+                self.items.insert(key, item);
+                taken += 1;
+            }
+        }
+        Ok(taken)
+    }
+
+    /// Import a dropped progress file, reporting how it went in the window.
+    pub(crate) fn import_file(&mut self, name: &str, bytes: &[u8]) {
+        self.open = true;
+        let result = String::from_utf8(bytes.to_vec())
+            .map_err(|e| e.to_string())
+            .and_then(|text| self.import(&text));
+        self.message = Some(match result {
+            Ok(taken) => (
+                true,
+                tr_fill("Imported {} items from {}", &[&taken.to_string(), name]),
+            ),
+            Err(e) => {
+                log::error!("error importing {}: {}", name, e);
+                (false, e)
+            }
+        });
+    }
+
+    pub(crate) fn show(&mut self, ctx: &egui::Context) {
+        let mut open = self.open;
+        egui::Window::new(tr("Practice progress"))
+            .id(egui::Id::new("practice_progress"))
+            .open(&mut open)
+            .show(ctx, |ui| self.ui(ui));
+        self.open = open;
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let today = today();
+
+        if self.items.is_empty() {
+            ui.label(tr("Nothing practised yet"));
+        } else {
+            // Group by the part of the key before the first colon
+            let mut categories: BTreeMap<&str, (usize, usize, usize)> = BTreeMap::new();
+            for (key, item) in &self.items {
+                let category = key.split(':').next().unwrap_or_default();
+                let (total, due, learned) = categories.entry(category).or_default();
+                *total += 1;
+                if item.due <= today {
+                    *due += 1;
+                }
+                if item.repetitions >= 2 {
+                    *learned += 1;
+                }
+            }
+
+            egui::Grid::new("review_categories")
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong(tr("Practice"));
+                    ui.strong(tr("Seen"));
+                    ui.strong(tr("Due"));
+                    ui.strong(tr("Learned"));
+                    ui.end_row();
+                    for (category, (total, due, learned)) in categories {
+                        ui.label(tr(category_name(category)));
+                        ui.label(total.to_string());
+                        ui.label(due.to_string());
+                        ui.label(learned.to_string());
+                        ui.end_row();
+                    }
+                });
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui
+                .button(tr("📋 Copy progress"))
+                .on_hover_text(tr(
+                    "Copy your progress to paste into the app on another device",
+                ))
+                .clicked()
+            {
+                match self.export() {
+                    Ok(text) => {
+                        ui.output_mut(|o| o.copied_text = text);
+                        self.message = Some((true, tr("Copied to the clipboard").to_owned()));
+                    }
+                    Err(e) => {
+                        log::error!("error exporting progress: {}", e);
+                        self.message = Some((false, e));
+                    }
+                }
+            }
+            if ui.button(tr("Reset")).clicked() {
+                self.items.clear();
+                self.message = None;
+            }
+        });
+
+        ui.label(tr(
+            "Paste progress from another device, or drop an exported file here:",
+        ));
+        ui.add(
+            egui::TextEdit::multiline(&mut self.import_text)
+                .desired_rows(3)
+                .code_editor(),
+        );
+        if ui
+            .add_enabled(
+                !self.import_text.is_empty(),
+                egui::Button::new(tr("Import")),
+            )
+            .clicked()
+        {
+            self.message = Some(match self.import(&self.import_text.clone()) {
+                Ok(taken) => {
+                    self.import_text.clear();
+                    (true, tr_with("Imported {} items", &taken.to_string()))
+                }
+                Err(e) => (false, e),
+            });
+        }
+
+        feedback_label(ui, &self.message);
+    }
+}
+
+fn category_name(category: &str) -> &str {
+    match category {
+        "note" => "Note locations",
+        "interval" => "Intervals",
+        "chord" => "Chord qualities",
+        "inversion" => "Inversions",
+        "shape" => "Chord shapes",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule() {
+        let mut item = ReviewItem::default();
+        item.review(5, 100.0);
+        assert_eq!(item.due, 101.0);
+        item.review(4, 101.0);
+        assert_eq!(item.due, 107.0);
+        item.review(4, 107.0);
+        assert!(item.due > 120.0);
+
+        // A lapse starts over and comes back soon
+        item.review(1, 130.0);
+        assert_eq!(item.repetitions, 0);
+        assert!(item.due < 130.1);
+        assert!(item.easiness >= MIN_EASINESS);
+    }
+
+    #[test]
+    fn test_pick_due_first() {
+        let keys: Vec<String> = ["a", "b", "c"].map(String::from).to_vec();
+        let mut reviews = Reviews::default();
+        let mut rng = Rng::default();
+        for key in &keys {
+            reviews.record_at(key, 5, 10.0);
+        }
+        // The one that was failed comes back first
+        reviews.record_at("b", 1, 10.5);
+        assert_eq!(reviews.pick_at(&keys, None, &mut rng, 10.6), 1);
+        // Unless we just asked it
+        assert_ne!(reviews.pick_at(&keys, Some("b"), &mut rng, 10.6), 1);
+
+        // Due items beat unseen ones
+        let more: Vec<String> = ["a", "b", "c", "d"].map(String::from).to_vec();
+        assert_eq!(reviews.pick_at(&more, None, &mut rng, 10.6), 1);
+        assert_eq!(reviews.pick_at(&more, Some("b"), &mut rng, 10.6), 3);
+    }
+
+    #[test]
+    fn test_export_import_merges() {
+        let mut ours = Reviews::default();
+        ours.record_at("note:0:5", 5, 10.0);
+        ours.record_at("note:1:3", 5, 20.0);
+
+        let mut theirs = Reviews::default();
+        theirs.record_at("note:0:5", 1, 15.0);
+        theirs.record_at("note:1:3", 1, 12.0);
+        theirs.record_at("interval:Octave", 4, 12.0);
+
+        let taken = ours.import(&theirs.export().unwrap()).unwrap();
+        assert_eq!(taken, 2);
+        assert_eq!(ours.items["note:0:5"], theirs.items["note:0:5"]);
+        assert_ne!(ours.items["note:1:3"], theirs.items["note:1:3"]);
+        assert!(ours.items.contains_key("interval:Octave"));
+
+        assert!(ours.import("nonsense").is_err());
+        let export = theirs.export().unwrap();
+        assert!(ours
+            .import(&export.replace("version: 1", "version: 2"))
+            .is_err());
+        let broken = |change: fn(&mut ReviewItem)| {
+            let mut broken = Reviews {
+                items: theirs.items.clone(),
+                ..Default::default()
+            };
+            change(broken.items.get_mut("interval:Octave").unwrap());
+            broken.export().unwrap()
+        };
+        let before = ours.items.clone();
+        let nan = broken(|item| item.due = f64::NAN);
+        assert!(ours.import(&nan).unwrap_err().contains("bad date"));
+        let infinite = broken(|item| item.interval_days = f64::INFINITY);
+        assert!(ours.import(&infinite).unwrap_err().contains("bad interval"));
+        let negative = broken(|item| item.interval_days = -1.0);
+        assert!(ours.import(&negative).unwrap_err().contains("bad interval"));
+        let easiness = broken(|item| item.easiness = f32::NAN);
+        assert!(ours.import(&easiness).unwrap_err().contains("bad easiness"));
+        let correct = broken(|item| item.correct = item.attempts + 1);
+        assert!(ours.import(&correct).unwrap_err().contains("attempts"));
+        // Nothing from a bad file is taken
+        assert_eq!(ours.items, before);
+
+        // And one already in the schedule doesn't stop picking
+        ours.items.get_mut("note:0:5").unwrap().due = f64::NAN;
+        let keys = ["note:0:5".to_owned(), "note:1:3".to_owned()];
+        let picked = ours.pick_at(&keys, None, &mut Rng::default(), 0.0);
+        assert!(picked < keys.len());
+    }
+}
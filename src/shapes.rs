@@ -0,0 +1,383 @@
+use crate::app::{feedback_label, note_for_fret, string_name, MAX_FRET};
+use crate::locale::{tr, tr_fill, tr_with};
+use crate::quiz::{Mark, QuizCell};
+use crate::random::Rng;
+use crate::review::{self, Reviews};
+use crate::theory::{pitch_class, PITCH_NAMES};
+use crate::voicing::{check_voicing, Voicing, VoicingCheck};
+use klib::core::base::Parsable;
+use klib::core::chord::Chord;
+use klib::core::note::Note;
+use klib::core::pitch::HasPitch;
+
+/// Chord qualities that can be asked for, as (suffix, description).
+const QUALITIES: [(&str, &str); 9] = [
+    ("", "Major"),
+    ("m", "Minor"),
+    ("7", "Dominant seventh"),
+    ("maj7", "Major seventh"),
+    ("m7", "Minor seventh"),
+    ("m7(♭5)", "Half diminished"),
+    ("dim7", "Diminished seventh"),
+    ("sus4", "Suspended fourth"),
+    ("6", "Major sixth"),
+];
+
+struct Question {
+    chord: String,
+    asked_at: f64,
+    // Only the first check of each question goes into the review schedule
+    recorded: bool,
+    solved: bool,
+}
+
+/// Quiz where the user plays a named chord anywhere in a region of the
+/// fretboard, in any voicing.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub(crate) struct ShapeQuiz {
+    pub(crate) open: bool,
+    qualities: Vec<String>,
+    // Pitch classes of the roots to ask for
+    roots: Vec<usize>,
+    lowest_fret: usize,
+    highest_fret: usize,
+    attempts: u32,
+    correct: u32,
+
+    #[serde(skip)]
+    question: Option<Question>,
+    #[serde(skip)]
+    check: Option<VoicingCheck>,
+    #[serde(skip)]
+    feedback: Option<(bool, String)>,
+    #[serde(skip)]
+    rng: Rng,
+}
+
+impl Default for ShapeQuiz {
+    fn default() -> Self {
+        Self {
+            open: false,
+            qualities: ["", "m", "7"].map(String::from).to_vec(),
+            // The natural notes
+            roots: vec![0, 2, 4, 5, 7, 9, 11],
+            lowest_fret: 0,
+            highest_fret: 5,
+            attempts: 0,
+            correct: 0,
+            question: None,
+            check: None,
+            feedback: None,
+            rng: Rng::default(),
+        }
+    }
+}
+
+impl ShapeQuiz {
+    /// Whether a chord has been asked for and not played yet.
+    fn waiting(&self) -> bool {
+        self.question.as_ref().is_some_and(|q| !q.solved)
+    }
+
+    fn in_region(&self, fret: usize) -> bool {
+        (self.lowest_fret..=self.highest_fret).contains(&fret)
+    }
+
+    /// How to draw a fretboard position, if the quiz has taken over the
+    /// fretboard.
+    pub(crate) fn cell(
+        &self,
+        selection: &Voicing,
+        tuning: &[Note],
+        string: usize,
+        fret: usize,
+    ) -> Option<QuizCell> {
+        self.question.as_ref().filter(|_| self.open)?;
+
+        let selected = selection.fret(string) == Some(fret);
+        let enabled = self.in_region(fret);
+        let mark = self.check.as_ref().and_then(|check| {
+            let pitch = note_for_fret(tuning[string], fret).pitch();
+            if selected && check.wrong.contains(&string) {
+                Some(Mark::Wrong)
+            } else if selected {
+                Some(Mark::Correct)
+            } else if enabled && check.missing.contains(&pitch) {
+                Some(Mark::Missing)
+            } else {
+                None
+            }
+        });
+
+        Some(QuizCell {
+            enabled,
+            selected,
+            // Names are only given away once the shape has been checked
+            show_name: mark.is_some(),
+            mark,
+        })
+    }
+
+    /// A position on the fretboard was clicked.
+    pub(crate) fn select(&mut self, selection: &mut Voicing, string: usize, fret: usize) {
+        if !self.waiting() {
+            return;
+        }
+        selection.toggle(string, fret);
+        self.check = None;
+    }
+
+    fn pool(&self) -> Vec<String> {
+        let mut pool = Vec::new();
+        for root in &self.roots {
+            for quality in &self.qualities {
+                let chord = format!("{}{}", PITCH_NAMES[*root], quality);
+                if Chord::parse(&chord).is_ok() {
+                    pool.push(chord);
+                }
+            }
+        }
+        pool
+    }
+
+    fn ask(&mut self, selection: &mut Voicing, reviews: &Reviews, now: f64) {
+        let pool = self.pool();
+        if pool.is_empty() {
+            self.question = None;
+            self.feedback = Some((false, tr("Pick at least one root and quality").to_owned()));
+            return;
+        }
+
+        // Chords that are due for review come first
+        let keys: Vec<String> = pool.iter().map(|c| review_key(c)).collect();
+        let last = self.question.as_ref().map(|q| review_key(&q.chord));
+        let chord = pool[reviews.pick(&keys, last.as_deref(), &mut self.rng)].clone();
+
+        selection.clear();
+        self.check = None;
+        self.question = Some(Question {
+            chord,
+            asked_at: now,
+            recorded: false,
+            solved: false,
+        });
+    }
+
+    fn check(&mut self, selection: &Voicing, tuning: &[Note], reviews: &mut Reviews, now: f64) {
+        let Some(question) = &mut self.question else {
+            return;
+        };
+
+        let check = match check_voicing(&question.chord, selection, tuning) {
+            Ok(check) => check,
+            Err(e) => {
+                log::error!("error checking {}: {}", question.chord, e);
+                return;
+            }
+        };
+
+        let correct = check.is_correct();
+        if !question.recorded {
+            question.recorded = true;
+            let seconds = (now - question.asked_at) as f32;
+            reviews.record(
+                &review_key(&question.chord),
+                review::quality(correct, seconds),
+            );
+            self.attempts += 1;
+            if correct {
+                self.correct += 1;
+            }
+        }
+        question.solved = correct;
+
+        let feedback = if correct {
+            tr_with("✔ That's {}", &question.chord)
+        } else {
+            let mut problems = Vec::new();
+            if !check.wrong.is_empty() {
+                let strings: Vec<String> = check
+                    .wrong
+                    .iter()
+                    .map(|s| string_name(tuning, *s))
+                    .collect();
+                let problem = if strings.len() > 1 {
+                    "wrong notes on the {} strings"
+                } else {
+                    "wrong notes on the {} string"
+                };
+                problems.push(tr_with(problem, &strings.join(", ")));
+            }
+            if !check.missing.is_empty() {
+                let missing: Vec<&str> = check
+                    .missing
+                    .iter()
+                    .map(|p| PITCH_NAMES[pitch_class(*p)])
+                    .collect();
+                problems.push(tr_with("missing {}", &missing.join(", ")));
+            }
+            tr_with("✘ Not quite: {}", &problems.join("; "))
+        };
+        self.feedback = Some((correct, feedback));
+        self.check = Some(check);
+    }
+
+    /// Move on without getting it right, which counts as a miss.
+    fn skip(&mut self, selection: &mut Voicing, reviews: &mut Reviews, now: f64) {
+        if let Some(question) = &self.question {
+            if !question.recorded {
+                reviews.record(&review_key(&question.chord), 0);
+                self.attempts += 1;
+            }
+        }
+        self.feedback = None;
+        self.ask(selection, reviews, now);
+    }
+
+    pub(crate) fn show(
+        &mut self,
+        ctx: &egui::Context,
+        tuning: &[Note],
+        selection: &mut Voicing,
+        reviews: &mut Reviews,
+    ) {
+        let mut open = self.open;
+        egui::Window::new(tr("Chord shape quiz"))
+            .id(egui::Id::new("chord_shape_quiz"))
+            .open(&mut open)
+            .show(ctx, |ui| self.ui(ui, tuning, selection, reviews));
+        self.open = open;
+    }
+
+    fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        tuning: &[Note],
+        selection: &mut Voicing,
+        reviews: &mut Reviews,
+    ) {
+        let now = ui.input(|i| i.time);
+
+        ui.add_enabled_ui(self.question.is_none(), |ui| self.pool_ui(ui));
+
+        ui.horizontal(|ui| {
+            if self.question.is_none() {
+                if ui.button(tr("▶ Start")).clicked() {
+                    self.rng.seed(now);
+                    self.feedback = None;
+                    self.ask(selection, reviews, now);
+                }
+            } else if ui.button(tr("⏹ Stop")).clicked() {
+                self.question = None;
+                self.check = None;
+                selection.clear();
+            }
+        });
+
+        if let Some(question) = &self.question {
+            ui.heading(egui::RichText::new(tr_with("Play {}", &question.chord)).size(24.0));
+            ui.label(tr_fill(
+                "Click the notes on the fretboard, between frets {} and {}",
+                &[
+                    &self.lowest_fret.to_string(),
+                    &self.highest_fret.to_string(),
+                ],
+            ));
+
+            let solved = question.solved;
+            ui.horizontal(|ui| {
+                if solved {
+                    if ui.button(tr("Next ▶")).clicked() {
+                        self.feedback = None;
+                        self.ask(selection, reviews, now);
+                    }
+                } else {
+                    if ui
+                        .add_enabled(!selection.is_empty(), egui::Button::new(tr("✔ Check")))
+                        .clicked()
+                    {
+                        self.check(selection, tuning, reviews, now);
+                    }
+                    if ui.button(tr("Clear")).clicked() {
+                        selection.clear();
+                        self.check = None;
+                    }
+                    if ui.button(tr("Skip")).clicked() {
+                        self.skip(selection, reviews, now);
+                    }
+                }
+            });
+        }
+
+        feedback_label(ui, &self.feedback);
+
+        if self.attempts > 0 {
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label(tr_fill(
+                    "{} of {} right first time ({}%)",
+                    &[
+                        &self.correct.to_string(),
+                        &self.attempts.to_string(),
+                        &format!("{:.0}", 100.0 * self.correct as f32 / self.attempts as f32),
+                    ],
+                ));
+                if ui.small_button(tr("Reset")).clicked() {
+                    self.attempts = 0;
+                    self.correct = 0;
+                }
+            });
+        }
+    }
+
+    fn pool_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(tr("Qualities"));
+        ui.horizontal_wrapped(|ui| {
+            for (suffix, description) in QUALITIES {
+                let mut enabled = self.qualities.iter().any(|q| q == suffix);
+                let label = if suffix.is_empty() {
+                    tr(description).to_owned()
+                } else {
+                    format!("{} ({})", tr(description), suffix)
+                };
+                if ui.checkbox(&mut enabled, label).changed() {
+                    if enabled {
+                        self.qualities.push(suffix.to_owned());
+                    } else {
+                        self.qualities.retain(|q| q != suffix);
+                    }
+                }
+            }
+        });
+
+        ui.label(tr("Roots"));
+        ui.horizontal_wrapped(|ui| {
+            for (class, name) in PITCH_NAMES.iter().enumerate() {
+                let enabled = self.roots.contains(&class);
+                if ui.selectable_label(enabled, *name).clicked() {
+                    if enabled {
+                        self.roots.retain(|r| *r != class);
+                    } else {
+                        self.roots.push(class);
+                    }
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label(tr("Between frets"));
+            ui.add(egui::DragValue::new(&mut self.lowest_fret).clamp_range(0..=MAX_FRET - 1));
+            ui.label(tr("and"));
+            ui.add(
+                egui::DragValue::new(&mut self.highest_fret)
+                    .clamp_range(self.lowest_fret..=MAX_FRET - 1),
+            );
+        });
+        self.highest_fret = self.highest_fret.max(self.lowest_fret);
+    }
+}
+
+fn review_key(chord: &str) -> String {
+    format!("shape:{}", chord)
+}
@@ -0,0 +1,337 @@
+//! Rendering part of the UI offscreen to an image, without a GPU.
+//!
+//! The UI is laid out and tessellated by a separate egui context, the same
+//! way it is on screen, and the triangles are then filled in on the CPU.
+
+use crate::app::feedback_label;
+use crate::files::{file_name, save_feedback};
+use crate::locale::tr;
+use crate::png;
+use egui::epaint::{ClippedPrimitive, Primitive, Vertex};
+use egui::{Color32, ColorImage, ImageData, TextureId};
+use std::collections::HashMap;
+
+// Space around the content, in points
+const MARGIN: f32 = 16.0;
+
+/// A rendered image, in premultiplied colour.
+pub(crate) struct Image {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pixels: Vec<Color32>,
+}
+
+impl Image {
+    pub(crate) fn to_png(&self) -> Vec<u8> {
+        let rgba: Vec<u8> = self
+            .pixels
+            .iter()
+            .flat_map(|p| p.to_srgba_unmultiplied())
+            .collect();
+        png::encode_rgba(self.width as u32, self.height as u32, &rgba)
+    }
+}
+
+/// Draw a UI into an image `width` pixels wide, as tall as the UI needs.
+pub(crate) fn render(
+    fonts: egui::FontDefinitions,
+    visuals: egui::Visuals,
+    width: u32,
+    mut draw: impl FnMut(&mut egui::Ui),
+) -> Image {
+    let ctx = egui::Context::default();
+    ctx.set_fonts(fonts);
+    ctx.set_visuals(visuals);
+
+    let mut textures = Textures::default();
+    let mut content = egui::Vec2::ZERO;
+    let mut frame = |screen: egui::Vec2, pixels_per_point: f32, content: &mut egui::Vec2| {
+        let mut input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(egui::Pos2::ZERO, screen)),
+            ..Default::default()
+        };
+        input
+            .viewports
+            .entry(input.viewport_id)
+            .or_default()
+            .native_pixels_per_point = Some(pixels_per_point);
+
+        let output = ctx.run(input, |ctx| {
+            let frame = egui::Frame::central_panel(&ctx.style()).inner_margin(MARGIN);
+            egui::CentralPanel::default().frame(frame).show(ctx, |ui| {
+                // The panel itself fills the screen, so measure inside it
+                *content = ui.vertical(|ui| draw(ui)).response.rect.size();
+            });
+        });
+        textures.update(output.textures_delta);
+        ctx.tessellate(output.shapes, output.pixels_per_point)
+    };
+
+    // Lay it out on a big canvas to find out how big it is (grids need a
+    // frame to measure their columns), then again at the size and scale that
+    // give the width asked for
+    for _ in 0..3 {
+        frame(egui::vec2(4096.0, 4096.0), 1.0, &mut content);
+    }
+    let screen = content + egui::Vec2::splat(2.0 * MARGIN);
+    let pixels_per_point = width.max(1) as f32 / screen.x;
+    frame(screen, pixels_per_point, &mut content);
+    let primitives = frame(screen, pixels_per_point, &mut content);
+
+    let mut image = Image {
+        width: width.max(1) as usize,
+        height: ((screen.y * pixels_per_point).round() as usize).max(1),
+        pixels: Vec::new(),
+    };
+    image.pixels = vec![Color32::TRANSPARENT; image.width * image.height];
+    for ClippedPrimitive {
+        clip_rect,
+        primitive,
+    } in primitives
+    {
+        let Primitive::Mesh(mesh) = primitive else {
+            continue;
+        };
+        let Some(texture) = textures.images.get(&mesh.texture_id) else {
+            continue;
+        };
+        let clip = [
+            (clip_rect.min.x * pixels_per_point).max(0.0),
+            (clip_rect.min.y * pixels_per_point).max(0.0),
+            (clip_rect.max.x * pixels_per_point).min(image.width as f32),
+            (clip_rect.max.y * pixels_per_point).min(image.height as f32),
+        ];
+        for triangle in mesh.indices.chunks_exact(3) {
+            let vertices = [0, 1, 2].map(|i| &mesh.vertices[triangle[i] as usize]);
+            fill_triangle(&mut image, texture, vertices, pixels_per_point, clip);
+        }
+    }
+    image
+}
+
+/// The textures egui has uploaded so far.
+#[derive(Default)]
+struct Textures {
+    images: HashMap<TextureId, ColorImage>,
+}
+
+impl Textures {
+    fn update(&mut self, delta: egui::TexturesDelta) {
+        for (id, delta) in delta.set {
+            let image = match delta.image {
+                ImageData::Color(image) => (*image).clone(),
+                ImageData::Font(font) => ColorImage {
+                    size: font.size,
+                    pixels: font.srgba_pixels(None).collect(),
+                },
+            };
+            match (delta.pos, self.images.get_mut(&id)) {
+                // A patch of a texture that's already there, like new glyphs
+                (Some([x, y]), Some(texture)) => {
+                    let [width, height] = image.size;
+                    for row in 0..height {
+                        let start = (y + row) * texture.size[0] + x;
+                        texture.pixels[start..start + width]
+                            .copy_from_slice(&image.pixels[row * width..(row + 1) * width]);
+                    }
+                }
+                _ => {
+                    self.images.insert(id, image);
+                }
+            }
+        }
+        for id in delta.free {
+            self.images.remove(&id);
+        }
+    }
+}
+
+fn edge(a: egui::Pos2, b: egui::Pos2, p: egui::Pos2) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+/// Fill the pixels whose centres are inside a triangle, blending the vertex
+/// colours times the texture over what's already there.
+fn fill_triangle(
+    image: &mut Image,
+    texture: &ColorImage,
+    vertices: [&Vertex; 3],
+    pixels_per_point: f32,
+    clip: [f32; 4],
+) {
+    let [a, b, c] = vertices.map(|v| (v.pos.to_vec2() * pixels_per_point).to_pos2());
+    let area = edge(a, b, c);
+    if area.abs() < 1e-6 {
+        return;
+    }
+
+    let x0 = a.x.min(b.x).min(c.x).max(clip[0]).floor() as usize;
+    let y0 = a.y.min(b.y).min(c.y).max(clip[1]).floor() as usize;
+    let x1 = a.x.max(b.x).max(c.x).min(clip[2]).ceil() as usize;
+    let y1 = a.y.max(b.y).max(c.y).min(clip[3]).ceil() as usize;
+
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let p = egui::pos2(x as f32 + 0.5, y as f32 + 0.5);
+            let weights = [edge(b, c, p) / area, edge(c, a, p) / area];
+            let weights = [weights[0], weights[1], 1.0 - weights[0] - weights[1]];
+            if weights.iter().any(|w| *w < 0.0) {
+                continue;
+            }
+
+            let uv = vertices
+                .iter()
+                .zip(weights)
+                .fold(egui::Vec2::ZERO, |uv, (v, w)| uv + v.uv.to_vec2() * w);
+            let texel = sample(texture, uv);
+            let mut source = [0.0; 4];
+            for (v, w) in vertices.iter().zip(weights) {
+                for (channel, value) in source.iter_mut().zip(v.color.to_array()) {
+                    *channel += value as f32 * w;
+                }
+            }
+            for (channel, value) in source.iter_mut().zip(texel.to_array()) {
+                *channel *= value as f32 / 255.0;
+            }
+
+            // Both are premultiplied, so "over" is just this
+            let pixel = &mut image.pixels[y * image.width + x];
+            let keep = 1.0 - source[3] / 255.0;
+            let blended: Vec<u8> = source
+                .iter()
+                .zip(pixel.to_array())
+                .map(|(s, d)| (s + d as f32 * keep).round().clamp(0.0, 255.0) as u8)
+                .collect();
+            *pixel =
+                Color32::from_rgba_premultiplied(blended[0], blended[1], blended[2], blended[3]);
+        }
+    }
+}
+
+/// The nearest texel; glyphs are rasterised at the output scale, so they
+/// line up with the pixels anyway.
+fn sample(texture: &ColorImage, uv: egui::Vec2) -> Color32 {
+    let [width, height] = texture.size;
+    let x = ((uv.x * width as f32) as usize).min(width - 1);
+    let y = ((uv.y * height as f32) as usize).min(height - 1);
+    texture.pixels[y * width + x]
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq)]
+pub(crate) enum Theme {
+    /// Whatever the app is using
+    App,
+    Light,
+    Dark,
+}
+
+impl Theme {
+    pub(crate) fn visuals(&self, ctx: &egui::Context) -> egui::Visuals {
+        match self {
+            Theme::App => ctx.style().visuals.clone(),
+            Theme::Light => egui::Visuals::light(),
+            Theme::Dark => egui::Visuals::dark(),
+        }
+    }
+}
+
+/// Settings for exporting the fretboard as a picture.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub(crate) struct ImageExport {
+    pub(crate) open: bool,
+    pub(crate) width: u32,
+    pub(crate) theme: Theme,
+    pub(crate) horizontal: bool,
+    pub(crate) show_chord: bool,
+
+    #[serde(skip)]
+    message: Option<(bool, String)>,
+}
+
+impl Default for ImageExport {
+    fn default() -> Self {
+        Self {
+            open: false,
+            width: 1600,
+            theme: Theme::App,
+            horizontal: true,
+            show_chord: true,
+            message: None,
+        }
+    }
+}
+
+impl ImageExport {
+    /// Returns true if an export was asked for.
+    pub(crate) fn show(&mut self, ctx: &egui::Context) -> bool {
+        let mut open = self.open;
+        let mut export = false;
+        egui::Window::new(tr("Export image"))
+            .id(egui::Id::new("export_image"))
+            .open(&mut open)
+            .show(ctx, |ui| export = self.ui(ui));
+        self.open = open;
+        export
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        egui::Grid::new("image_export").show(ui, |ui| {
+            ui.label(tr("Width"));
+            ui.add(
+                egui::DragValue::new(&mut self.width)
+                    .clamp_range(200..=8000)
+                    .suffix(" px"),
+            );
+            ui.end_row();
+
+            ui.label(tr("Theme"));
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.theme, Theme::App, tr("Same as the app"));
+                ui.radio_value(&mut self.theme, Theme::Light, tr("Light"));
+                ui.radio_value(&mut self.theme, Theme::Dark, tr("Dark"));
+            });
+            ui.end_row();
+
+            ui.label(tr("Layout"));
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.horizontal, true, tr("Horizontal"));
+                ui.radio_value(&mut self.horizontal, false, tr("Vertical"));
+            });
+            ui.end_row();
+        });
+        ui.checkbox(&mut self.show_chord, tr("Put the chord name at the top"));
+
+        let export = ui.button(tr("💾 Export PNG")).clicked();
+
+        feedback_label(ui, &self.message);
+        export
+    }
+
+    pub(crate) fn save(&mut self, chord: &str, image: &Image) {
+        let name = file_name(if chord.is_empty() { "fretboard" } else { chord }, "png");
+        self.message = Some(save_feedback(&name, "image/png", &image.to_png()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let visuals = egui::Visuals::light();
+        let background = visuals.panel_fill;
+        let image = render(Default::default(), visuals, 300, |ui| {
+            ui.heading("Cmaj7");
+            ui.label("C E G B");
+        });
+        assert_eq!(image.width, 300);
+        assert!(image.height > 20 && image.height < 300);
+
+        // The corner is background, and the text is drawn in something darker
+        assert_eq!(image.pixels[0], background);
+        let darkest = image.pixels.iter().map(|p| p.r()).min().unwrap();
+        assert!(darkest < 128);
+    }
+}
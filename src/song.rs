@@ -0,0 +1,645 @@
+//! Songs read from song sheets, and the window that steps through their
+//! chords on the fretboard.
+
+use crate::app::feedback_label;
+use crate::files::{file_name, save_feedback};
+use crate::locale::{tr, tr_fill, tr_with};
+use crate::progression::Progression;
+use crate::symbol::fix_chord_name;
+use crate::voicing::Voicing;
+use klib::core::base::Parsable;
+use klib::core::chord::Chord;
+use klib::core::note::Note;
+
+// Chords on each line of a song made from a progression
+const CHORDS_PER_LINE: usize = 4;
+
+/// A stretch of lyrics, starting with the chord played over it, if any.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default, PartialEq)]
+pub(crate) struct Segment {
+    /// The chord as written in the sheet
+    pub(crate) chord: Option<String>,
+    pub(crate) text: String,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub(crate) enum Line {
+    /// Lyrics with chords, empty for a blank line
+    Lyrics(Vec<Segment>),
+    /// A comment meant to be shown, like "Repeat twice"
+    Comment(String),
+    /// Anything else from the sheet, like the start of a chorus, kept so it
+    /// can be written back out
+    Directive { name: String, value: String },
+}
+
+/// A voicing the song gives for one of its chords.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct Define {
+    pub(crate) name: String,
+    pub(crate) voicing: Voicing,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub(crate) struct Song {
+    pub(crate) title: String,
+    pub(crate) artist: String,
+    pub(crate) key: Option<String>,
+    pub(crate) capo: u32,
+    pub(crate) defines: Vec<Define>,
+    pub(crate) lines: Vec<Line>,
+}
+
+/// Something in a sheet that couldn't be read, with the line it's on
+/// (counting from 1).
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Problem {
+    pub(crate) line: usize,
+    pub(crate) message: String,
+}
+
+/// The label for a directive that starts a section, like "Chorus" for
+/// `{start_of_chorus}`.
+fn section_label(name: &str, value: &str) -> Option<String> {
+    let section = name.strip_prefix("start_of_")?;
+    if !value.is_empty() {
+        return Some(value.to_owned());
+    }
+    let mut chars = section.chars();
+    chars
+        .next()
+        .map(|c| c.to_uppercase().chain(chars).collect())
+}
+
+/// A labelled part of a song, with its chords.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Section {
+    pub(crate) label: String,
+    pub(crate) chords: Vec<String>,
+    /// Where its first chord is in the song's chords
+    pub(crate) start: usize,
+}
+
+/// Whether something in chord brackets isn't meant to be a chord, like
+/// "N.C." for no chord or a `*` annotation.
+pub(crate) fn is_annotation(written: &str) -> bool {
+    let written = written.trim();
+    written.is_empty()
+        || written.starts_with('*')
+        || matches!(written, "N.C." | "N.C" | "NC" | "n.c.")
+}
+
+/// The name to show a chord from a sheet as, or `None` if it isn't a chord.
+pub(crate) fn chord_name(written: &str) -> Option<String> {
+    if is_annotation(written) {
+        return None;
+    }
+    let name = fix_chord_name(written.trim());
+    Chord::parse(&name).is_ok().then_some(name)
+}
+
+impl Song {
+    /// Every chord played in the song, in order, by its normalised name.
+    pub(crate) fn chords(&self) -> Vec<String> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Lyrics(segments) => Some(segments),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|segment| segment.chord.as_deref().and_then(chord_name))
+            .collect()
+    }
+
+    /// A song with just the chords of a progression, a few to a line with
+    /// bar lines between them.
+    pub(crate) fn from_progression(progression: &Progression) -> Self {
+        let lines = progression
+            .chords
+            .chunks(CHORDS_PER_LINE)
+            .map(|chords| {
+                let mut segments = vec![Segment {
+                    chord: None,
+                    text: "| ".to_owned(),
+                }];
+                segments.extend(chords.iter().map(|chord| Segment {
+                    chord: Some(chord.name.clone()),
+                    text: "   |".repeat(chord.bars as usize) + " ",
+                }));
+                Line::Lyrics(segments)
+            })
+            .collect();
+        Self {
+            lines,
+            ..Default::default()
+        }
+    }
+
+    /// The chords as written in the sheet, each once, in the order they're
+    /// first played. Things that aren't chords are left out.
+    pub(crate) fn written_chords(&self) -> Vec<&str> {
+        let mut written: Vec<&str> = Vec::new();
+        for line in &self.lines {
+            let Line::Lyrics(segments) = line else {
+                continue;
+            };
+            for chord in segments.iter().filter_map(|s| s.chord.as_deref()) {
+                if chord_name(chord).is_some() && !written.contains(&chord) {
+                    written.push(chord);
+                }
+            }
+        }
+        written
+    }
+
+    /// The song's sections and the chords in each, in order. Chords outside
+    /// of any section are left out.
+    pub(crate) fn sections(&self) -> Vec<Section> {
+        let mut sections: Vec<Section> = Vec::new();
+        let mut in_section = false;
+        let mut count = 0;
+        for line in &self.lines {
+            match line {
+                Line::Directive { name, value } => {
+                    if let Some(label) = section_label(name, value) {
+                        sections.push(Section {
+                            label,
+                            chords: Vec::new(),
+                            start: count,
+                        });
+                        in_section = true;
+                    } else if name.starts_with("end_of_") {
+                        in_section = false;
+                    }
+                }
+                Line::Lyrics(segments) => {
+                    for segment in segments {
+                        let Some(chord) = segment.chord.as_deref().and_then(chord_name) else {
+                            continue;
+                        };
+                        count += 1;
+                        if let Some(section) = sections.last_mut().filter(|_| in_section) {
+                            section.chords.push(chord);
+                        }
+                    }
+                }
+                Line::Comment(_) => {}
+            }
+        }
+        sections
+    }
+
+    /// The chords in each bar, using the bar lines written in the sheet. An
+    /// empty bar holds the chord before it. Sheets without bar lines get a
+    /// bar for each chord.
+    pub(crate) fn bars(&self) -> Vec<Vec<String>> {
+        let segments: Vec<&Segment> = self
+            .lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Lyrics(segments) => Some(segments),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        if !segments.iter().any(|s| s.text.contains('|')) {
+            return self.chords().into_iter().map(|c| vec![c]).collect();
+        }
+
+        let mut bars = Vec::new();
+        let mut bar = Vec::new();
+        for line in &self.lines {
+            let Line::Lyrics(segments) = line else {
+                continue;
+            };
+            for (i, segment) in segments.iter().enumerate() {
+                if let Some(chord) = segment.chord.as_deref().and_then(chord_name) {
+                    bar.push(chord);
+                }
+                let mut text = segment.text.as_str();
+                // A bar line at the start of a line just opens the first bar
+                if i == 0 && segment.chord.is_none() {
+                    text = text.trim_start().strip_prefix('|').unwrap_or(text);
+                }
+                for _ in text.matches('|') {
+                    bars.push(std::mem::take(&mut bar));
+                }
+            }
+        }
+        if !bar.is_empty() {
+            bars.push(bar);
+        }
+        bars
+    }
+
+    /// Use a voicing for a chord, replacing any the song already had.
+    pub(crate) fn set_define(&mut self, chord: &str, voicing: Voicing) {
+        // Keep the name the way the sheet writes it, so other tools match it
+        // up with the chords
+        let name = self
+            .written_chords()
+            .into_iter()
+            .find(|written| chord_name(written).as_deref() == Some(chord))
+            .unwrap_or(chord)
+            .to_owned();
+        self.defines
+            .retain(|d| d.name != name && fix_chord_name(&d.name) != chord);
+        self.defines.push(Define { name, voicing });
+    }
+
+    /// The voicing the song defines for a chord, if it has one.
+    pub(crate) fn define(&self, chord: &str) -> Option<&Voicing> {
+        self.defines
+            .iter()
+            .find(|d| d.name == chord || fix_chord_name(&d.name) == chord)
+            .map(|d| &d.voicing)
+    }
+}
+
+/// The "Song" window, showing a song sheet and stepping through its chords.
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+#[serde(default)]
+pub(crate) struct SongView {
+    pub(crate) open: bool,
+    song: Option<Song>,
+    source: String,
+    // Index into the song's chords
+    position: usize,
+
+    progression_text: String,
+
+    #[serde(skip)]
+    paste_text: String,
+    #[serde(skip)]
+    problems: Vec<Problem>,
+    #[serde(skip)]
+    error: Option<String>,
+    #[serde(skip)]
+    message: Option<(bool, String)>,
+}
+
+impl SongView {
+    /// Load a dropped song sheet or MusicXML file.
+    pub(crate) fn load_file(&mut self, name: &str, bytes: &[u8]) {
+        self.open = true;
+        if name.to_lowercase().ends_with(".mxl") {
+            self.error = Some(format!(
+                "{} is compressed, export it as uncompressed MusicXML (.musicxml)",
+                name
+            ));
+            return;
+        }
+
+        let text = match String::from_utf8(bytes.to_vec()) {
+            Ok(text) => text,
+            Err(e) => {
+                log::error!("error reading {}: {}", name, e);
+                self.error = Some(format!("Could not read {}: {}", name, e));
+                return;
+            }
+        };
+        let lower = name.to_lowercase();
+        if lower.ends_with(".musicxml") || lower.ends_with(".xml") {
+            match crate::musicxml::import(&text) {
+                Ok((song, problems)) => {
+                    self.set_song(name, song);
+                    self.problems = problems;
+                }
+                Err(e) => {
+                    log::error!("error reading {}: {}", name, e);
+                    self.error = Some(format!("Could not read {}: {}", name, e));
+                }
+            }
+        } else {
+            self.load(name, &text);
+        }
+    }
+
+    /// Load ChordPro, or plain text with the chords above the lyrics.
+    fn load(&mut self, name: &str, text: &str) {
+        if crate::lyrics::looks_like_chordpro(text) {
+            let (song, problems) = crate::chordpro::parse(text);
+            self.set_song(name, song);
+            self.problems = problems;
+            return;
+        }
+        let (mut song, problems) = crate::lyrics::parse(text);
+        song.title = name
+            .rsplit_once('.')
+            .map_or(name, |(stem, _)| stem)
+            .to_owned();
+        self.set_song(name, song);
+        self.problems = problems;
+        self.message = Some((
+            true,
+            tr("Read as chords over lyrics, export it to get ChordPro").to_owned(),
+        ));
+    }
+
+    pub(crate) fn song(&self) -> Option<&Song> {
+        self.song.as_ref()
+    }
+
+    /// Show a song from the library.
+    pub(crate) fn open_song(&mut self, song: Song) {
+        self.open = true;
+        let name = song.title.clone();
+        self.set_song(&name, song);
+    }
+
+    fn set_song(&mut self, name: &str, song: Song) {
+        self.song = Some(song);
+        self.source = name.to_owned();
+        self.problems.clear();
+        self.position = 0;
+        self.error = None;
+        self.message = None;
+    }
+
+    /// Show the song window. Returns the chord to show on the fretboard, with
+    /// the song's voicing for it if it has one, when the position changes.
+    pub(crate) fn show(
+        &mut self,
+        ctx: &egui::Context,
+        selection: &Voicing,
+        tuning: &[Note],
+    ) -> Option<(String, Option<Voicing>)> {
+        let mut open = self.open;
+        let mut chosen = None;
+        egui::Window::new(tr("Song"))
+            .id(egui::Id::new("song"))
+            .open(&mut open)
+            .default_width(500.0)
+            .show(ctx, |ui| {
+                chosen = self.ui(ui);
+                self.export_ui(ui, selection, tuning);
+            });
+        self.open = open;
+
+        let chord = chosen?;
+        let voicing = self.song.as_ref().and_then(|s| s.define(&chord)).cloned();
+        Some((chord, voicing))
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) -> Option<String> {
+        if self.song.is_none() {
+            ui.label(tr(
+                "Drop a ChordPro (.cho), plain text (.txt) or MusicXML (.musicxml) song here, \
+                 or paste one below.",
+            ));
+        }
+        egui::CollapsingHeader::new(tr("Paste a song"))
+            .default_open(self.song.is_none())
+            .show(ui, |ui| {
+                ui.add(
+                    egui::TextEdit::multiline(&mut self.paste_text)
+                        .desired_rows(6)
+                        .code_editor(),
+                );
+                if ui
+                    .add_enabled(
+                        !self.paste_text.trim().is_empty(),
+                        egui::Button::new(tr("Load")),
+                    )
+                    .clicked()
+                {
+                    let text = std::mem::take(&mut self.paste_text);
+                    self.load(tr("Pasted song"), &text);
+                }
+
+                ui.label(tr("Or start from a progression:"));
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.progression_text)
+                        .on_hover_text(tr("Chords with optional bar counts, e.g. Am7:2 D7:2 G:4"));
+                    if ui.button(tr("Load")).clicked() {
+                        match Progression::parse(&self.progression_text) {
+                            Ok(progression) => {
+                                self.set_song("progression", Song::from_progression(&progression))
+                            }
+                            Err(e) => self.error = Some(e),
+                        }
+                    }
+                });
+            });
+
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        for problem in &self.problems {
+            ui.colored_label(
+                ui.visuals().error_fg_color,
+                tr_fill(
+                    "Line {}: {}",
+                    &[&problem.line.to_string(), &problem.message],
+                ),
+            );
+        }
+
+        let song = self.song.as_ref()?;
+        let chords = song.chords();
+        let mut position = self.position.min(chords.len().saturating_sub(1));
+
+        ui.separator();
+        let title = if song.title.is_empty() {
+            &self.source
+        } else {
+            &song.title
+        };
+        ui.heading(title);
+        let mut details = Vec::new();
+        if !song.artist.is_empty() {
+            details.push(song.artist.clone());
+        }
+        if let Some(key) = &song.key {
+            details.push(tr_with("Key of {}", key));
+        }
+        if song.capo > 0 {
+            details.push(tr_with("Capo {}", &song.capo.to_string()));
+        }
+        if !details.is_empty() {
+            ui.label(details.join(" · "));
+        }
+        if song.capo > 0 {
+            ui.label(
+                egui::RichText::new(tr("Chords are the shapes played with the capo on")).weak(),
+            );
+        }
+
+        if chords.is_empty() {
+            ui.label(tr("This song has no chords"));
+            return None;
+        }
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(position > 0, egui::Button::new(tr("◀ Previous")))
+                .clicked()
+            {
+                position -= 1;
+            }
+            ui.label(format!("{} / {}", position + 1, chords.len()));
+            if ui
+                .add_enabled(position + 1 < chords.len(), egui::Button::new(tr("Next ▶")))
+                .clicked()
+            {
+                position += 1;
+            }
+        });
+
+        // The different chords in the song, to jump to where each is first
+        // played
+        ui.horizontal_wrapped(|ui| {
+            let mut seen: Vec<&String> = Vec::new();
+            for (i, chord) in chords.iter().enumerate() {
+                if seen.contains(&chord) {
+                    continue;
+                }
+                seen.push(chord);
+                if ui
+                    .selectable_label(chords[position] == *chord, chord)
+                    .clicked()
+                {
+                    position = i;
+                }
+            }
+        });
+
+        // Each section's progression, to jump to its start
+        let sections = song.sections();
+        if !sections.is_empty() {
+            egui::CollapsingHeader::new(tr("Sections")).show(ui, |ui| {
+                for section in sections.iter().filter(|s| !s.chords.is_empty()) {
+                    let text = format!("{}: {}", section.label, section.chords.join(" "));
+                    if ui
+                        .selectable_label(position == section.start, text)
+                        .clicked()
+                    {
+                        position = section.start;
+                    }
+                }
+            });
+        }
+
+        ui.separator();
+        egui::ScrollArea::vertical()
+            .max_height(400.0)
+            .show(ui, |ui| sheet_ui(ui, song, &mut position));
+
+        let changed = position != self.position;
+        self.position = position;
+        changed.then(|| chords[position].clone())
+    }
+
+    fn export_ui(&mut self, ui: &mut egui::Ui, selection: &Voicing, tuning: &[Note]) {
+        let Some(song) = &mut self.song else {
+            return;
+        };
+        ui.separator();
+
+        let chords = song.chords();
+        if let Some(chord) = chords.get(self.position.min(chords.len().saturating_sub(1))) {
+            let chosen = song.define(chord) == Some(selection);
+            if ui
+                .add_enabled(
+                    !selection.is_empty() && !chosen,
+                    egui::Button::new(tr_with("Use the selected voicing for {}", chord)),
+                )
+                .on_hover_text(tr("Exported with the song so other apps show this shape"))
+                .clicked()
+            {
+                song.set_define(chord, selection.clone());
+            }
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button(tr("💾 Export ChordPro")).clicked() {
+                let text = crate::chordpro::write(song, tuning);
+                let title = if song.title.is_empty() {
+                    "song"
+                } else {
+                    &song.title
+                };
+                let name = file_name(title, "cho");
+                self.message = Some(save_feedback(&name, "text/plain", text.as_bytes()));
+            }
+            if ui
+                .button(tr("💾 Export MusicXML"))
+                .on_hover_text(tr(
+                    "Chord symbols with fretboard diagrams, for notation software",
+                ))
+                .clicked()
+            {
+                let xml = crate::musicxml::export(song, tuning);
+                let title = if song.title.is_empty() {
+                    "song"
+                } else {
+                    &song.title
+                };
+                let name = file_name(title, "musicxml");
+                self.message = Some(save_feedback(
+                    &name,
+                    "application/vnd.recordare.musicxml+xml",
+                    xml.as_bytes(),
+                ));
+            }
+            if ui.button(tr("📋 Copy ChordPro")).clicked() {
+                let text = crate::chordpro::write(song, tuning);
+                ui.output_mut(|o| o.copied_text = text);
+                self.message = Some((true, tr("Copied to the clipboard").to_owned()));
+            }
+        });
+
+        feedback_label(ui, &self.message);
+    }
+}
+
+/// The song sheet with each chord above the lyrics it's played over.
+/// Clicking a chord moves to it.
+fn sheet_ui(ui: &mut egui::Ui, song: &Song, position: &mut usize) {
+    let mut index = 0;
+    for line in &song.lines {
+        match line {
+            Line::Lyrics(segments) if segments.is_empty() => {
+                ui.add_space(8.0);
+            }
+            Line::Lyrics(segments) => {
+                ui.horizontal_wrapped(|ui| {
+                    ui.spacing_mut().item_spacing.x = 0.0;
+                    for segment in segments {
+                        ui.vertical(|ui| {
+                            match segment.chord.as_deref().map(|c| (c, chord_name(c))) {
+                                Some((_, Some(name))) => {
+                                    let current = index == *position;
+                                    let label = egui::RichText::new(&name).strong();
+                                    if ui.selectable_label(current, label).clicked() {
+                                        *position = index;
+                                    }
+                                    index += 1;
+                                }
+                                // Shown as written, but not something to
+                                // step to
+                                Some((written, None)) => {
+                                    ui.label(egui::RichText::new(written).weak());
+                                }
+                                None => {
+                                    ui.label(" ");
+                                }
+                            }
+                            ui.label(&segment.text);
+                        });
+                    }
+                });
+            }
+            Line::Comment(text) => {
+                ui.label(egui::RichText::new(text).italics().weak());
+            }
+            Line::Directive { name, value } => {
+                if let Some(label) = section_label(name, value) {
+                    ui.label(egui::RichText::new(label).strong());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Spelling notes with sharps or flats. A chord's own notes are spelled the
+//! way theory spells them in that chord, so B♭ is B♭ D F rather than A♯ D F,
+//! and other notes follow the key the chord's root would be in.
+
+use crate::naming::Naming;
+use crate::symbol::normalize;
+use crate::theory::{midi_number, pitch_class};
+use klib::core::base::{HasName, HasStaticName, Parsable};
+use klib::core::chord::{Chord, HasChord, HasRoot};
+use klib::core::interval::Interval;
+use klib::core::named_pitch::HasNamedPitch;
+use klib::core::note::{Note, ToUniversal};
+use klib::core::pitch::HasPitch;
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum Accidentals {
+    /// From the chord being shown
+    #[default]
+    Auto,
+    Sharps,
+    Flats,
+}
+
+/// The note with the same pitch spelled with a flat, or no accidental.
+fn flat(note: Note) -> Note {
+    // Notes made from ids are never sharp or doubled
+    note.to_universal()
+}
+
+fn sharp(note: Note) -> Note {
+    let note = flat(note);
+    if note.named_pitch().static_name().contains('♭') {
+        note + Interval::AugmentedSeventh - Interval::PerfectOctave
+    } else {
+        note
+    }
+}
+
+/// Whether a key on this root has flats in its key signature, like F major
+/// or D minor.
+fn flat_key(root: &str, minor: bool) -> bool {
+    let flat_naturals: &[&str] = if minor { &["D", "G", "C", "F"] } else { &["F"] };
+    root.contains('♭') || flat_naturals.contains(&root)
+}
+
+/// Spells notes for the chord being shown.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Speller {
+    accidentals: Accidentals,
+    naming: Naming,
+    /// For notes outside the chord when spelling automatically
+    flats: bool,
+    /// The chord's notes, as it spells them
+    chord: Vec<Note>,
+}
+
+impl Speller {
+    /// A speller for the chord with this name, or just the preferences if
+    /// it isn't a chord.
+    pub(crate) fn new(accidentals: Accidentals, naming: &Naming, chord: &str) -> Self {
+        let Ok(parsed) = Chord::parse(chord) else {
+            return Self {
+                accidentals,
+                naming: naming.clone(),
+                ..Default::default()
+            };
+        };
+        let root = parsed.root().static_name();
+        // Read the way kord writes the root, so F# is F♯
+        let normalized = normalize(chord).unwrap_or_else(|_| chord.to_owned());
+        let quality = normalized
+            .strip_prefix(root)
+            .unwrap_or_default()
+            .split('/')
+            .next()
+            .unwrap_or_default();
+        let minor =
+            quality.starts_with('m') && !quality.starts_with("maj") || quality.starts_with("dim");
+        Self {
+            accidentals,
+            naming: naming.clone(),
+            flats: flat_key(root, minor),
+            chord: parsed.chord(),
+        }
+    }
+
+    /// The note spelled as it should be shown.
+    pub(crate) fn spell(&self, note: Note) -> Note {
+        match self.accidentals {
+            Accidentals::Sharps => return sharp(note),
+            Accidentals::Flats => return flat(note),
+            Accidentals::Auto => {}
+        }
+        let class = pitch_class(note.pitch());
+        let Some(mut spelled) = self
+            .chord
+            .iter()
+            .copied()
+            .find(|tone| pitch_class(tone.pitch()) == class)
+        else {
+            return if self.flats { flat(note) } else { sharp(note) };
+        };
+
+        // Into the same octave as the note
+        let midi = midi_number(note);
+        for _ in 0..10 {
+            match midi_number(spelled).cmp(&midi) {
+                std::cmp::Ordering::Less => spelled = spelled + Interval::PerfectOctave,
+                std::cmp::Ordering::Greater => spelled = spelled - Interval::PerfectOctave,
+                std::cmp::Ordering::Equal => break,
+            }
+        }
+        spelled
+    }
+
+    /// The note's name in the naming system.
+    pub(crate) fn name(&self, note: Note, hide_octaves: bool) -> String {
+        self.naming.note(&note_name(self.spell(note), hide_octaves))
+    }
+}
+
+/// A note's name as it's spelled, with the octave as a subscript unless it's
+/// hidden.
+pub(crate) fn note_name(note: Note, hide_octaves: bool) -> String {
+    if hide_octaves {
+        return note.named_pitch().static_name().to_owned();
+    }
+
+    // Turn octave (well all numbers) into subscripts
+    let mut s = String::new();
+    for c in note.name().chars() {
+        s.push(match c {
+            '0' => '₀',
+            '1' => '₁',
+            '2' => '₂',
+            '3' => '₃',
+            '4' => '₄',
+            '5' => '₅',
+            '6' => '₆',
+            '7' => '₇',
+            '8' => '₈',
+            '9' => '₉',
+            _ => c,
+        });
+    }
+    s
+}
+
+/// Format a note with sharps, for when there's no chord to spell it for.
+pub(crate) fn sharp_name(note: Note, hide_octaves: bool) -> String {
+    note_name(sharp(note), hide_octaves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theory::note_from_midi;
+
+    #[test]
+    fn test_spelling() {
+        let names = |accidentals, chord: &str, midis: &[i32]| -> Vec<String> {
+            let speller = Speller::new(accidentals, &Naming::default(), chord);
+            midis
+                .iter()
+                .map(|midi| speller.name(note_from_midi(*midi), true))
+                .collect()
+        };
+        // B♭ D F, and E♭ G♭ B♭ D♭
+        assert_eq!(
+            names(Accidentals::Auto, "B♭", &[70, 62, 65]),
+            ["B♭", "D", "F"]
+        );
+        assert_eq!(
+            names(Accidentals::Auto, "E♭m7", &[63, 66, 70, 73]),
+            ["E♭", "G♭", "B♭", "D♭"]
+        );
+        // The third of G♯ is B♯, not C
+        assert_eq!(names(Accidentals::Auto, "G♯", &[68, 72]), ["G♯", "B♯"]);
+        // Notes outside the chord follow its key
+        assert_eq!(names(Accidentals::Auto, "F", &[61]), ["D♭"]);
+        assert_eq!(names(Accidentals::Auto, "E", &[70]), ["A♯"]);
+        assert_eq!(names(Accidentals::Auto, "Dm", &[70]), ["B♭"]);
+
+        assert_eq!(names(Accidentals::Sharps, "B♭", &[70]), ["A♯"]);
+        assert_eq!(names(Accidentals::Flats, "E", &[68]), ["A♭"]);
+        // Roots typed with # or b, which kord writes longer, read the same
+        for (typed, written) in [("C#m", "C♯m"), ("Dbm7", "D♭m7"), ("F#/C#", "F♯/C♯")] {
+            let midis = [61, 62, 64, 66, 68, 70];
+            assert_eq!(
+                names(Accidentals::Auto, typed, &midis),
+                names(Accidentals::Auto, written, &midis)
+            );
+        }
+
+        // Octaves stay with the pitch, so B♯3 sounds as C4
+        let speller = Speller::new(Accidentals::Auto, &Naming::default(), "G♯");
+        let c4 = speller.spell(note_from_midi(60));
+        assert_eq!(c4.named_pitch().static_name(), "B♯");
+        assert_eq!(midi_number(c4), 60);
+    }
+}
@@ -0,0 +1,393 @@
+//! The chord or voicing written on a staff, to connect the fretboard to
+//! notation.
+
+use crate::locale::tr;
+use crate::spelling::Speller;
+use crate::theory::octave_number;
+use crate::voicing::Voicing;
+use klib::core::base::{HasStaticName, Parsable};
+use klib::core::chord::{Chord, HasChord};
+use klib::core::named_pitch::HasNamedPitch;
+use klib::core::note::Note;
+use klib::core::octave::HasOctave;
+
+const LINE_GAP: f32 = 8.0;
+const NOTE_WIDTH: f32 = 12.0;
+// The music font's symbols are sized for a staff as tall as the font size,
+// with the bottom line just below their baseline and a note on a line
+// centred a little above it
+const MUSIC_SIZE: f32 = 4.0 * LINE_GAP;
+const BOTTOM_LINE_BELOW_BASELINE: f32 = 0.1;
+const NOTE_ABOVE_BASELINE: f32 = 0.13;
+const ACCIDENTAL_WIDTH: f32 = 11.0;
+const WIDTH: f32 = 180.0;
+// Where notes go, leaving room for the clef and accidentals
+const NOTE_X: f32 = 110.0;
+// Between the treble and bass staves, in steps
+const GRAND_GAP: i32 = 10;
+
+// Positions on the staff are counted in steps of a line or space, from C0,
+// so middle C is 28
+const LETTERS: [char; 7] = ['C', 'D', 'E', 'F', 'G', 'A', 'B'];
+const MIDDLE_C: i32 = 28;
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum Clef {
+    /// Treble clef an octave down, which is how guitar music is written
+    #[default]
+    Guitar,
+    /// Treble and bass staves, like piano music
+    Grand,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Stave {
+    Treble,
+    Bass,
+}
+
+impl Stave {
+    /// The step of the bottom line, E4 or G2.
+    fn bottom(self) -> i32 {
+        match self {
+            Stave::Treble => 30,
+            Stave::Bass => 18,
+        }
+    }
+
+    fn top(self) -> i32 {
+        self.bottom() + 8
+    }
+}
+
+/// A note written on the staff.
+#[derive(Clone, Debug, PartialEq)]
+struct Written {
+    step: i32,
+    stave: Stave,
+    accidental: String,
+    /// Moved to the right of the stem side, for a note a step from the one
+    /// below it
+    shifted: bool,
+}
+
+impl Written {
+    /// Ledger lines it needs, as steps.
+    fn ledger_lines(&self) -> Vec<i32> {
+        let (bottom, top) = (self.stave.bottom(), self.stave.top());
+        let below = (self.step..bottom).filter(|step| (bottom - step) % 2 == 0);
+        let above = (top + 1..=self.step).filter(|step| (step - top) % 2 == 0);
+        below.chain(above).collect()
+    }
+}
+
+/// Where the notes go, lowest first. Guitar is written an octave above how
+/// it sounds.
+fn write(notes: &[Note], speller: &Speller, clef: Clef) -> Vec<Written> {
+    let mut written: Vec<Written> = notes
+        .iter()
+        .map(|note| {
+            let spelled = speller.spell(*note);
+            let name = spelled.named_pitch().static_name();
+            let mut chars = name.chars();
+            let letter = chars.next().unwrap_or('C');
+            let letter = LETTERS.iter().position(|l| *l == letter).unwrap_or(0) as i32;
+            let mut step = octave_number(spelled.octave()) * 7 + letter;
+            let stave = match clef {
+                Clef::Guitar => {
+                    step += 7;
+                    Stave::Treble
+                }
+                Clef::Grand if step >= MIDDLE_C => Stave::Treble,
+                Clef::Grand => Stave::Bass,
+            };
+            Written {
+                step,
+                stave,
+                accidental: chars.as_str().to_owned(),
+                shifted: false,
+            }
+        })
+        .collect();
+    // Equal notes next to each other, so they can be dropped
+    written.sort_by(|a, b| (a.step, &a.accidental).cmp(&(b.step, &b.accidental)));
+    written.dedup_by_key(|w| (w.step, w.accidental.clone()));
+
+    // A plain note on the same line or space as an altered one, like G with
+    // G♯, needs a natural to tell them apart
+    let altered: Vec<i32> = written
+        .iter()
+        .filter(|w| !w.accidental.is_empty())
+        .map(|w| w.step)
+        .collect();
+    for w in &mut written {
+        if w.accidental.is_empty() && altered.contains(&w.step) {
+            w.accidental = "♮".to_owned();
+        }
+    }
+
+    // Seconds can't share a column, so every other note in a cluster moves
+    for i in 1..written.len() {
+        let (below, note) = (&written[i - 1], &written[i]);
+        let second = note.step - below.step <= 1 && note.stave == below.stave;
+        written[i].shifted = second && !below.shifted;
+    }
+    written
+}
+
+/// Columns for the accidentals, going left, so ones within a sixth of each
+/// other don't overlap.
+fn accidental_columns(written: &[Written]) -> Vec<usize> {
+    let mut columns: Vec<usize> = Vec::new();
+    let mut placed: Vec<(i32, usize)> = Vec::new();
+    // From the top down, the way they're usually stacked
+    for note in written.iter().rev() {
+        if note.accidental.is_empty() {
+            columns.push(0);
+            continue;
+        }
+        let column = (0..)
+            .find(|column| {
+                !placed
+                    .iter()
+                    .any(|(step, c)| c == column && (step - note.step).abs() < 6)
+            })
+            .unwrap_or(0);
+        placed.push((note.step, column));
+        columns.push(column);
+    }
+    columns.reverse();
+    columns
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+#[serde(default)]
+pub(crate) struct StaffView {
+    pub(crate) open: bool,
+    clef: Clef,
+}
+
+impl StaffView {
+    pub(crate) fn show(
+        &mut self,
+        ctx: &egui::Context,
+        chord: &str,
+        selection: &Voicing,
+        tuning: &[Note],
+        speller: &Speller,
+    ) {
+        let mut open = self.open;
+        // The title is translated, so the window keeps its place by id
+        egui::Window::new(tr("Staff notation"))
+            .id(egui::Id::new("staff"))
+            .open(&mut open)
+            .show(ctx, |ui| self.ui(ui, chord, selection, tuning, speller));
+        self.open = open;
+    }
+
+    fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        chord: &str,
+        selection: &Voicing,
+        tuning: &[Note],
+        speller: &Speller,
+    ) {
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.clef, Clef::Guitar, tr("Guitar"))
+                .on_hover_text(tr("Treble clef, written an octave higher than it sounds"));
+            ui.radio_value(&mut self.clef, Clef::Grand, tr("Piano"))
+                .on_hover_text(tr("Treble and bass clefs, at pitch"));
+        });
+
+        let voicing: Vec<Note> = selection
+            .notes(tuning)
+            .into_iter()
+            .map(|(_, note)| note)
+            .collect();
+        let notes = if !voicing.is_empty() {
+            ui.label(tr("The voicing picked on the fretboard"));
+            voicing
+        } else if let Ok(parsed) = Chord::parse(chord) {
+            ui.label(chord);
+            parsed.chord()
+        } else {
+            ui.label(tr("Type a chord or pick a voicing to see it here"));
+            Vec::new()
+        };
+        let written = write(&notes, speller, self.clef);
+        paint(ui, &written, self.clef);
+    }
+}
+
+fn paint(ui: &mut egui::Ui, written: &[Written], clef: Clef) {
+    let staves: &[Stave] = match clef {
+        Clef::Guitar => &[Stave::Treble],
+        Clef::Grand => &[Stave::Treble, Stave::Bass],
+    };
+    // Each step is half a line gap, and the bass stave is drawn as if it
+    // carried on below the treble one
+    let row = |step: i32, stave: Stave| match stave {
+        Stave::Treble => step,
+        Stave::Bass => step - Stave::Bass.top() + Stave::Treble.bottom() - GRAND_GAP,
+    };
+    let rows = written.iter().map(|w| row(w.step, w.stave));
+    let lowest = staves.iter().map(|s| row(s.bottom(), *s));
+    let highest = rows.clone().chain([Stave::Treble.top()]).max().unwrap_or(0) + 6;
+    let lowest = rows.chain(lowest).min().unwrap_or(0) - 6;
+
+    let (rect, _) = ui.allocate_exact_size(
+        egui::vec2(WIDTH, (highest - lowest) as f32 * LINE_GAP / 2.0),
+        egui::Sense::hover(),
+    );
+    let y = |row: i32| rect.top() + (highest - row) as f32 * LINE_GAP / 2.0;
+    let color = ui.visuals().strong_text_color();
+    let stroke = egui::Stroke::new(1.0, color);
+    let painter = ui.painter();
+
+    for stave in staves {
+        for line in 0..5 {
+            let y = y(row(stave.bottom() + line * 2, *stave));
+            painter.hline(rect.left()..=rect.right(), y, stroke);
+        }
+        let glyph = match (stave, clef) {
+            (Stave::Treble, Clef::Guitar) => "𝄠",
+            (Stave::Treble, Clef::Grand) => "𝄞",
+            (Stave::Bass, _) => "𝄢",
+        };
+        let bottom = y(row(stave.bottom(), *stave)) - MUSIC_SIZE * BOTTOM_LINE_BELOW_BASELINE;
+        let at = egui::pos2(rect.left() + 4.0, bottom);
+        paint_music(painter, at, egui::Align::Min, glyph, color);
+    }
+    if clef == Clef::Grand {
+        let top = y(row(Stave::Treble.top(), Stave::Treble));
+        let bottom = y(row(Stave::Bass.bottom(), Stave::Bass));
+        painter.vline(rect.left(), top..=bottom, stroke);
+    }
+
+    let columns = accidental_columns(written);
+    for (note, column) in written.iter().zip(columns) {
+        let x = NOTE_X + if note.shifted { NOTE_WIDTH } else { 0.0 };
+        let center = egui::pos2(rect.left() + x, y(row(note.step, note.stave)));
+
+        for line in note.ledger_lines() {
+            let y = y(row(line, note.stave));
+            let reach = NOTE_WIDTH * 0.8;
+            painter.hline(center.x - reach..=center.x + reach, y, stroke);
+        }
+
+        // A whole note: an open oval, tilted a little
+        let points: Vec<egui::Pos2> = (0..24)
+            .map(|i| {
+                let angle = i as f32 / 24.0 * std::f32::consts::TAU;
+                let (x, y) = (angle.cos() * NOTE_WIDTH / 2.0, angle.sin() * LINE_GAP / 2.0);
+                let tilt: f32 = -0.35;
+                center
+                    + egui::vec2(
+                        x * tilt.cos() - y * tilt.sin(),
+                        x * tilt.sin() + y * tilt.cos(),
+                    )
+            })
+            .collect();
+        painter.add(egui::Shape::closed_line(
+            points,
+            egui::Stroke::new(1.6, color),
+        ));
+
+        if !note.accidental.is_empty() {
+            let right = rect.left() + NOTE_X - NOTE_WIDTH * 0.9 - column as f32 * ACCIDENTAL_WIDTH;
+            let baseline = center.y + MUSIC_SIZE * NOTE_ABOVE_BASELINE;
+            let at = egui::pos2(right, baseline);
+            paint_music(painter, at, egui::Align::Max, &note.accidental, color);
+        }
+    }
+}
+
+/// Draw music symbols with their baseline at a point, starting or ending
+/// there.
+fn paint_music(
+    painter: &egui::Painter,
+    at: egui::Pos2,
+    align: egui::Align,
+    text: &str,
+    color: egui::Color32,
+) {
+    let galley = painter.layout_no_wrap(
+        text.to_owned(),
+        egui::FontId::proportional(MUSIC_SIZE),
+        color,
+    );
+    let baseline = galley
+        .rows
+        .first()
+        .and_then(|row| row.glyphs.first())
+        .map_or(0.0, |glyph| glyph.pos.y);
+    let x = match align {
+        egui::Align::Min => at.x,
+        egui::Align::Center => at.x - galley.size().x / 2.0,
+        egui::Align::Max => at.x - galley.size().x,
+    };
+    painter.galley(egui::pos2(x, at.y - baseline), galley);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{note_for_fret, standard_tuning};
+    use crate::naming::Naming;
+    use crate::spelling::Accidentals;
+
+    #[test]
+    fn test_write() {
+        let tuning = standard_tuning();
+        let speller = Speller::new(Accidentals::Auto, &Naming::default(), "");
+        // Guitar's open low E is written E3, three ledger lines down
+        let low_e = write(&[tuning[5]], &speller, Clef::Guitar);
+        assert_eq!(low_e[0].step, 23);
+        assert_eq!(low_e[0].ledger_lines(), [24, 26, 28]);
+        // And the open high E in the top space
+        let high_e = write(&[tuning[0]], &speller, Clef::Guitar);
+        assert_eq!(high_e[0].step, 37);
+        assert!(high_e[0].ledger_lines().is_empty());
+
+        // At pitch, middle C goes on the treble stave with one ledger line
+        let c4 = note_for_fret(tuning[1], 1);
+        let grand = write(&[c4, tuning[5]], &speller, Clef::Grand);
+        assert_eq!(grand[0].stave, Stave::Bass);
+        assert_eq!(grand[1].stave, Stave::Treble);
+        assert_eq!(grand[1].ledger_lines(), [28]);
+
+        // B♭ and C a second apart, with the C moved over
+        let speller = Speller::new(Accidentals::Auto, &Naming::default(), "C7");
+        let seventh = write(
+            &[note_for_fret(tuning[2], 3), note_for_fret(tuning[2], 5)],
+            &speller,
+            Clef::Guitar,
+        );
+        assert_eq!(seventh[0].accidental, "♭");
+        assert!(!seventh[0].shifted && seventh[1].shifted);
+
+        // G and G♯ share a line, so the G gets a natural
+        let speller = Speller::new(Accidentals::Sharps, &Naming::default(), "");
+        let g = write(
+            &[note_for_fret(tuning[2], 0), note_for_fret(tuning[2], 1)],
+            &speller,
+            Clef::Guitar,
+        );
+        assert_eq!(g[0].step, g[1].step);
+        let accidentals: Vec<&str> = g.iter().map(|w| w.accidental.as_str()).collect();
+        assert_eq!(accidentals, ["♮", "♯"]);
+        // Even with the G on two strings, around the G♯
+        let g = write(
+            &[
+                note_for_fret(tuning[3], 5),
+                note_for_fret(tuning[2], 1),
+                note_for_fret(tuning[2], 0),
+            ],
+            &speller,
+            Clef::Guitar,
+        );
+        let accidentals: Vec<&str> = g.iter().map(|w| w.accidental.as_str()).collect();
+        assert_eq!(accidentals, ["♮", "♯"]);
+    }
+}
@@ -0,0 +1,476 @@
+//! Reading chord symbols the many ways people write them, like `C-7`,
+//! `CΔ9`, `Cø`, `C7b9` or `c#m7/g#`, and writing them the way the chord
+//! parser expects.
+//!
+//! A symbol is a root, a quality, an extension, then any number of
+//! suspensions, added notes, alterations and omissions, and finally a bass
+//! note after a slash. Parentheses and commas between the parts are
+//! ignored.
+
+use std::fmt;
+
+use crate::locale::{fill, tr_fill};
+
+/// Where a symbol couldn't be read, as a range of characters, and why.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SymbolError {
+    /// Index of the first character that's wrong
+    pub(crate) start: usize,
+    /// Index after the last one, the same as `start` if something's missing
+    /// at the end
+    pub(crate) end: usize,
+    /// The message in English, the key for translating it, with a `{}` for
+    /// each of `args`
+    text: &'static str,
+    args: Vec<String>,
+}
+
+impl SymbolError {
+    /// Why the symbol couldn't be read, in the language being shown.
+    pub(crate) fn message(&self) -> String {
+        tr_fill(self.text, &self.args())
+    }
+
+    fn args(&self) -> Vec<&str> {
+        self.args.iter().map(String::as_str).collect()
+    }
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = fill(self.text, &self.args());
+        write!(f, "{} (at character {})", message, self.start + 1)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Quality {
+    Major,
+    Minor,
+    Diminished,
+    HalfDiminished,
+    Augmented,
+}
+
+#[derive(Default)]
+struct Symbol {
+    root: String,
+    quality: Option<Quality>,
+    /// 5, 6, 7, 9, 11 or 13
+    extension: Option<u32>,
+    /// Whether the seventh in the extension is major, like `maj9`
+    major_seventh: bool,
+    sus: Option<u32>,
+    adds: Vec<u32>,
+    /// Like `♭9`
+    alterations: Vec<(char, u32)>,
+    omits: Vec<u32>,
+    bass: Option<String>,
+}
+
+impl Symbol {
+    fn write(&self) -> String {
+        let mut name = self.root.clone();
+        let mut extension = self.extension;
+        let mut alterations = self.alterations.clone();
+        match self.quality {
+            None | Some(Quality::Major) => {}
+            Some(Quality::Minor) => name.push('m'),
+            Some(Quality::Diminished) if extension == Some(7) && !self.major_seventh => {
+                name.push_str("dim7");
+                extension = None;
+            }
+            Some(Quality::Diminished) => name.push_str("dim"),
+            Some(Quality::HalfDiminished) => {
+                name.push('m');
+                extension = extension.or(Some(7));
+                if !alterations.contains(&('♭', 5)) {
+                    alterations.push(('♭', 5));
+                }
+            }
+            Some(Quality::Augmented) => name.push('+'),
+        }
+
+        match extension {
+            Some(n) if self.major_seventh && self.quality == Some(Quality::Minor) => {
+                name.push_str(&format!("(maj{})", n))
+            }
+            Some(n) if self.major_seventh => name.push_str(&format!("maj{}", n)),
+            Some(n) => name.push_str(&n.to_string()),
+            None => {}
+        }
+        if let Some(n) = self.sus {
+            name.push_str(&format!("sus{}", n));
+        }
+        for n in &self.adds {
+            name.push_str(&format!("add{}", n));
+        }
+        alterations.sort_by_key(|(_, n)| *n);
+        for (accidental, n) in alterations {
+            name.push_str(&format!("({}{})", accidental, n));
+        }
+        for n in &self.omits {
+            name.push_str(&format!("(no{})", n));
+        }
+        if let Some(bass) = &self.bass {
+            name.push('/');
+            name.push_str(bass);
+        }
+        name
+    }
+}
+
+struct Reader {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Reader {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.chars.len()
+    }
+
+    /// Take one of the words if it's next, trying them in order.
+    fn word(&mut self, words: &[&str], ignore_case: bool) -> bool {
+        for word in words {
+            let len = word.chars().count();
+            let Some(next) = self.chars.get(self.pos..self.pos + len) else {
+                continue;
+            };
+            let next: String = next.iter().collect();
+            let matches = if ignore_case {
+                next.to_lowercase() == word.to_lowercase()
+            } else {
+                next == *word
+            };
+            if matches {
+                self.pos += len;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn error<T>(
+        &self,
+        start: usize,
+        text: &'static str,
+        args: &[&dyn fmt::Display],
+    ) -> Result<T, SymbolError> {
+        Err(SymbolError {
+            start,
+            end: (start + 1).min(self.chars.len()).max(start),
+            text,
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        })
+    }
+
+    /// A note name like `C`, `f#` or `B♭`, written with a capital letter
+    /// and ♯ or ♭.
+    fn note(&mut self, bass: bool) -> Result<String, SymbolError> {
+        let start = self.pos;
+        let letter = match (self.peek(), bass) {
+            (Some(c @ ('A'..='G' | 'a'..='g')), _) => c.to_ascii_uppercase(),
+            (Some(c), false) => {
+                return self.error(start, "expected a root note from A to G, not {}", &[&c])
+            }
+            (Some(c), true) => {
+                return self.error(start, "expected a bass note from A to G, not {}", &[&c])
+            }
+            (None, false) => return self.error(start, "expected a root note from A to G", &[]),
+            (None, true) => return self.error(start, "expected a bass note from A to G", &[]),
+        };
+        self.pos += 1;
+        let mut note = letter.to_string();
+        match self.peek() {
+            Some('#' | '♯') => note.push('♯'),
+            Some('b' | '♭') => note.push('♭'),
+            _ => return Ok(note),
+        }
+        self.pos += 1;
+        if let Some('#' | '♯' | 'b' | '♭') = self.peek() {
+            return self.error(self.pos, "double sharps and flats aren't supported", &[]);
+        }
+        Ok(note)
+    }
+
+    /// A number of one or two digits.
+    fn number(&mut self) -> Option<(usize, u32)> {
+        let start = self.pos;
+        let digits: String = self.chars[self.pos..]
+            .iter()
+            .take(2)
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        // 6 and 9 run together, as in C69, so only take both for 11 and 13
+        let digits = match digits.as_str() {
+            "11" | "13" => digits,
+            _ => digits.chars().take(1).collect(),
+        };
+        let n = digits.parse().ok()?;
+        self.pos += digits.len();
+        Some((start, n))
+    }
+
+    /// The number after a word like `add` or `sus`, which must be one of
+    /// `allowed`.
+    fn degree(&mut self, what: &str, allowed: &[u32]) -> Result<u32, SymbolError> {
+        let list: Vec<String> = allowed.iter().map(|n| n.to_string()).collect();
+        match self.number() {
+            Some((_, n)) if allowed.contains(&n) => Ok(n),
+            Some((start, n)) => self.error(
+                start,
+                "{} can't be {}, only {}",
+                &[&what, &n, &list.join(", ")],
+            ),
+            None => self.error(
+                self.pos,
+                "{} needs a number: {}",
+                &[&what, &list.join(", ")],
+            ),
+        }
+    }
+}
+
+/// Read a chord symbol and write it the way the chord parser expects, or
+/// say what's wrong with it.
+pub(crate) fn normalize(text: &str) -> Result<String, SymbolError> {
+    // Positions count from the start of what was typed, spaces and all
+    let leading = text.chars().take_while(|c| c.is_whitespace()).count();
+    let mut reader = Reader {
+        chars: text.trim_end().chars().collect(),
+        pos: leading,
+    };
+    if reader.at_end() {
+        return reader.error(reader.pos, "type a chord, like Am7", &[]);
+    }
+
+    let mut symbol = Symbol {
+        root: reader.note(false)?,
+        ..Default::default()
+    };
+
+    loop {
+        // Parts can be grouped and separated however people like
+        while let Some('(' | ')' | ',' | ' ') = reader.peek() {
+            reader.pos += 1;
+        }
+        // C6/9 is a 6 with an added 9, not a slash chord
+        if symbol.extension == Some(6) && reader.word(&["/9"], false) {
+            symbol.adds.push(9);
+            continue;
+        }
+        if reader.at_end() || reader.peek() == Some('/') {
+            break;
+        }
+        let start = reader.pos;
+
+        if symbol.quality.is_none() {
+            let omit = reader.word(&["omit"], true);
+            reader.pos = start;
+            let quality = if reader.word(&["major", "maj"], true)
+                || reader.word(&["M", "Δ", "∆"], false)
+            {
+                let delta = matches!(reader.chars[start], 'Δ' | '∆');
+                let before = reader.pos;
+                match reader.number() {
+                    Some((_, n @ (7 | 9 | 11 | 13))) => {
+                        symbol.major_seventh = true;
+                        symbol.extension = Some(n);
+                    }
+                    // Read as an extension of the major chord, like CM6
+                    Some(_) => reader.pos = before,
+                    // Δ on its own means a major seventh
+                    None if delta => {
+                        symbol.major_seventh = true;
+                        symbol.extension = Some(7);
+                    }
+                    None => {}
+                }
+                Some(Quality::Major)
+            } else if reader.word(&["minor", "min"], true) || reader.word(&["mi", "m", "-"], false)
+            {
+                Some(Quality::Minor)
+            } else if reader.word(&["dim", "°"], true) || !omit && reader.word(&["o"], true) {
+                Some(Quality::Diminished)
+            } else if reader.word(&["ø", "Ø"], false) {
+                Some(Quality::HalfDiminished)
+            } else if reader.word(&["aug", "+"], true) {
+                // C+5 is just another way to write C+
+                let before = reader.pos;
+                if reader.number() != Some((before, 5)) {
+                    reader.pos = before;
+                }
+                Some(Quality::Augmented)
+            } else {
+                None
+            };
+            // The first part is the quality, or it's a major chord
+            symbol.quality = Some(quality.unwrap_or(Quality::Major));
+            if quality.is_some() {
+                continue;
+            }
+        }
+
+        if symbol.extension.is_none()
+            && (reader.word(&["maj"], true) || reader.word(&["M", "Δ", "∆"], false))
+        {
+            // A major seventh on another quality, like m(maj7)
+            symbol.major_seventh = true;
+            symbol.extension = Some(match reader.number() {
+                Some((_, n @ (7 | 9 | 11 | 13))) => n,
+                Some((at, n)) => {
+                    return reader.error(at, "a major seventh chord can't have a {}", &[&n])
+                }
+                None => 7,
+            });
+        } else if reader.word(&["sus"], true) {
+            if symbol.sus.is_some() {
+                return reader.error(start, "the chord is already suspended", &[]);
+            }
+            symbol.sus = Some(match reader.peek() {
+                Some(c) if c.is_ascii_digit() => reader.degree("sus", &[2, 4])?,
+                _ => 4,
+            });
+        } else if reader.word(&["add"], true) {
+            let n = reader.degree("add", &[2, 4, 6, 9, 11, 13])?;
+            symbol.adds.push(n);
+        } else if reader.word(&["omit", "no"], true) {
+            let n = reader.degree("omit", &[3, 5])?;
+            symbol.omits.push(n);
+        } else if let Some(accidental @ ('#' | '♯' | 'b' | '♭' | '+' | '-')) = reader.peek() {
+            reader.pos += 1;
+            let n = match reader.number() {
+                Some((_, n @ (5 | 9 | 11 | 13))) => n,
+                Some((at, n)) => {
+                    return reader.error(
+                        at,
+                        "only the 5, 9, 11 and 13 can be altered, not the {}",
+                        &[&n],
+                    )
+                }
+                None => {
+                    return reader.error(
+                        start,
+                        "{} needs a number after it, like {}9",
+                        &[&accidental, &accidental],
+                    )
+                }
+            };
+            let accidental = if matches!(accidental, '#' | '♯' | '+') {
+                '♯'
+            } else {
+                '♭'
+            };
+            if symbol.alterations.contains(&(accidental, n)) {
+                return reader.error(start, "{}{} is there twice", &[&accidental, &n]);
+            }
+            symbol.alterations.push((accidental, n));
+        } else if let Some((at, n)) = reader.number() {
+            match (symbol.extension, n) {
+                (None, 5 | 6 | 7 | 9 | 11 | 13) => symbol.extension = Some(n),
+                // C2 and C4 are suspended
+                (None, 2 | 4) if symbol.sus.is_none() => symbol.sus = Some(n),
+                // C69 has a 6 and an added 9
+                (Some(6), 9) => symbol.adds.push(9),
+                (None, _) => {
+                    return reader.error(
+                        at,
+                        "there's no {} chord, extensions are 6, 7, 9, 11 and 13",
+                        &[&n],
+                    )
+                }
+                (Some(extension), _) => {
+                    return reader.error(
+                        at,
+                        "it already has a {}, did you mean add{}?",
+                        &[&extension, &n],
+                    )
+                }
+            }
+        } else {
+            let c = reader.chars[start];
+            return reader.error(start, "didn't expect {} here", &[&c]);
+        }
+    }
+
+    if reader.peek() == Some('/') {
+        reader.pos += 1;
+        symbol.bass = Some(reader.note(true)?);
+    }
+    if let Some(c) = reader.peek() {
+        return reader.error(reader.pos, "didn't expect {} after the bass note", &[&c]);
+    }
+    Ok(symbol.write())
+}
+
+/// Normalize a chord name so the chord parser can recognize it, leaving it
+/// as typed if it can't be read.
+pub(crate) fn fix_chord_name(chord: &str) -> String {
+    normalize(chord).unwrap_or_else(|_| chord.trim().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        for (typed, name) in [
+            ("c", "C"),
+            ("Cb", "C♭"),
+            ("f#m7", "F♯m7"),
+            ("Bbmaj7", "B♭maj7"),
+            ("CMaj7", "Cmaj7"),
+            ("CM7", "Cmaj7"),
+            ("CΔ", "Cmaj7"),
+            ("CΔ9", "Cmaj9"),
+            ("CM", "C"),
+            ("C-7", "Cm7"),
+            ("Cmin", "Cm"),
+            ("Cmi7", "Cm7"),
+            ("CmM7", "Cm(maj7)"),
+            ("Cm(maj7)", "Cm(maj7)"),
+            ("Cø", "Cm7(♭5)"),
+            ("Cm7b5", "Cm7(♭5)"),
+            ("C°", "Cdim"),
+            ("Co7", "Cdim7"),
+            ("Caug", "C+"),
+            ("C+", "C+"),
+            ("C7#9", "C7(♯9)"),
+            ("C7(b9)", "C7(♭9)"),
+            ("Csus", "Csus4"),
+            ("C7sus4", "C7sus4"),
+            ("C2", "Csus2"),
+            ("Cadd9", "Cadd9"),
+            ("Cadd9b13", "Cadd9(♭13)"),
+            ("C69", "C6add9"),
+            ("C6/9", "C6add9"),
+            ("Am6/9/E", "Am6add9/E"),
+            ("Comit3", "C(no3)"),
+            (" am/g ", "Am/G"),
+            ("C#m7b5/g#", "C♯m7(♭5)/G♯"),
+        ] {
+            assert_eq!(normalize(typed).as_deref(), Ok(name), "{}", typed);
+        }
+
+        let error = |typed| normalize(typed).unwrap_err();
+        assert_eq!(error("Hm").start, 0);
+        assert_eq!(error("Csub").start, 1);
+        assert_eq!(error("Cadd").start, 4);
+        assert_eq!(error("Cadd").end, 4);
+        assert_eq!(error("C7b").start, 2);
+        assert_eq!(error("C7/").start, 3);
+        assert_eq!(error("C##").start, 2);
+        let sub = error("Cm7sub");
+        assert_eq!((sub.start, sub.end), (3, 4));
+        assert_eq!(sub.message(), "didn't expect s here");
+
+        // What the chord parser gets, whether or not it can be read
+        assert_eq!(fix_chord_name("Cm7sub"), "Cm7sub");
+    }
+}
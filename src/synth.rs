@@ -0,0 +1,323 @@
+//! Note playback with a few simple instrument models.
+//!
+//! Plucked strings use Karplus-Strong synthesis, the piano is a handful of
+//! decaying partials, and the sine is there for a plain reference tone.
+
+use crate::locale::{tr, tr_with};
+use std::f32::consts::PI;
+use std::time::Duration;
+
+const SAMPLE_RATE: u32 = 44100;
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Timbre {
+    Nylon,
+    Steel,
+    ElectricClean,
+    Piano,
+    Sine,
+}
+
+impl Timbre {
+    pub(crate) const ALL: [Timbre; 5] = [
+        Timbre::Nylon,
+        Timbre::Steel,
+        Timbre::ElectricClean,
+        Timbre::Piano,
+        Timbre::Sine,
+    ];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Timbre::Nylon => "Nylon guitar",
+            Timbre::Steel => "Steel guitar",
+            Timbre::ElectricClean => "Electric guitar (clean)",
+            Timbre::Piano => "Piano",
+            Timbre::Sine => "Sine",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+#[serde(default)]
+pub(crate) struct PlaybackSettings {
+    pub(crate) note_length_ms: u32,
+    pub(crate) attack_ms: u32,
+    pub(crate) release_ms: u32,
+    pub(crate) volume: f32,
+    pub(crate) timbre: Timbre,
+}
+
+impl Default for PlaybackSettings {
+    fn default() -> Self {
+        Self {
+            note_length_ms: 500,
+            attack_ms: 5,
+            release_ms: 100,
+            volume: 0.8,
+            timbre: Timbre::Sine,
+        }
+    }
+}
+
+impl PlaybackSettings {
+    pub(crate) fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(
+            egui::Slider::new(&mut self.note_length_ms, 50..=3000)
+                .logarithmic(true)
+                .suffix(" ms")
+                .text(tr("Note length")),
+        );
+        ui.add(
+            egui::Slider::new(&mut self.attack_ms, 0..=500)
+                .suffix(" ms")
+                .text(tr("Attack")),
+        );
+        ui.add(
+            egui::Slider::new(&mut self.release_ms, 0..=2000)
+                .suffix(" ms")
+                .text(tr("Release")),
+        );
+        ui.add(
+            egui::Slider::new(&mut self.volume, 0.0..=1.0)
+                .fixed_decimals(2)
+                .text(tr("Volume")),
+        );
+        ui.menu_button(tr_with("Timbre: {}", tr(self.timbre.name())), |ui| {
+            for timbre in Timbre::ALL {
+                ui.selectable_value(&mut self.timbre, timbre, tr(timbre.name()));
+            }
+        });
+    }
+}
+
+/// A single synthesised note, as a rodio source.
+struct Voice {
+    frequency: f32,
+    timbre: Timbre,
+    volume: f32,
+    sample: usize,
+    attack: usize,
+    sustain_end: usize,
+    end: usize,
+    // Karplus-Strong delay line and the fractional delay we read it at
+    string: Vec<f32>,
+    delay: f32,
+    decay: f32,
+}
+
+impl Voice {
+    fn new(frequency: f32, settings: &PlaybackSettings) -> Self {
+        let samples = |ms: u32| (ms as u64 * SAMPLE_RATE as u64 / 1000) as usize;
+        let attack = samples(settings.attack_ms);
+        let sustain_end = samples(settings.note_length_ms).max(attack);
+        let end = sustain_end + samples(settings.release_ms);
+
+        let mut voice = Self {
+            frequency,
+            timbre: settings.timbre,
+            volume: settings.volume,
+            sample: 0,
+            attack,
+            sustain_end,
+            end,
+            string: Vec::new(),
+            delay: 0.0,
+            decay: 1.0,
+        };
+        voice.pluck();
+        voice
+    }
+
+    /// Fill the delay line with noise for the plucked string timbres.
+    fn pluck(&mut self) {
+        let (brightness, decay) = match self.timbre {
+            Timbre::Nylon => (0.3, 0.996),
+            Timbre::Steel => (0.8, 0.998),
+            Timbre::ElectricClean => (1.0, 0.9995),
+            Timbre::Piano | Timbre::Sine => return,
+        };
+
+        // The averaging filter in the loop adds half a sample of delay
+        let period = SAMPLE_RATE as f32 / self.frequency;
+        self.delay = (period - 0.5).max(1.0);
+        self.decay = decay;
+
+        // Deterministic noise is fine here, and avoids pulling in a rand crate
+        let mut seed: u32 = 0x2545_f491;
+        let mut last = 0.0;
+        self.string = (0..self.delay.ceil() as usize + 2)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                let noise = seed as f32 / u32::MAX as f32 * 2.0 - 1.0;
+                // One pole low pass, darker strings start with less treble
+                last += brightness * (noise - last);
+                last
+            })
+            .collect();
+    }
+
+    fn envelope(&self) -> f32 {
+        let n = self.sample;
+        if n < self.attack {
+            n as f32 / self.attack as f32
+        } else if n < self.sustain_end {
+            1.0
+        } else {
+            let release = (self.end - self.sustain_end).max(1);
+            1.0 - (n - self.sustain_end) as f32 / release as f32
+        }
+    }
+
+    fn plucked(&mut self) -> f32 {
+        let len = self.string.len();
+        let string = &self.string;
+        let sample = self.sample;
+        let read = |offset: usize| string[(sample + len - offset % len) % len];
+
+        let whole = self.delay.floor() as usize;
+        let fraction = self.delay - whole as f32;
+        let delayed = read(whole) * (1.0 - fraction) + read(whole + 1) * fraction;
+        let previous = read(whole + 1) * (1.0 - fraction) + read(whole + 2) * fraction;
+        let out = self.decay * 0.5 * (delayed + previous);
+        // A pickup hears the string minus a slightly delayed copy of itself
+        let pickup = 0.7 * out - 0.3 * read(len / 5);
+
+        self.string[sample % len] = out;
+        if self.timbre == Timbre::ElectricClean {
+            pickup
+        } else {
+            out
+        }
+    }
+
+    fn piano(&self) -> f32 {
+        let t = self.sample as f32 / SAMPLE_RATE as f32;
+        (1..=8)
+            .map(|n| {
+                let n = n as f32;
+                // Slightly stretched partials that die away faster as they go up
+                let partial = self.frequency * n * (1.0 + 0.0004 * n * n);
+                (2.0 * PI * partial * t).sin() * (-t * (1.5 + n)).exp() / n
+            })
+            .sum::<f32>()
+            * 0.6
+    }
+}
+
+impl Iterator for Voice {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.sample >= self.end {
+            return None;
+        }
+
+        let raw = match self.timbre {
+            Timbre::Nylon | Timbre::Steel | Timbre::ElectricClean => self.plucked(),
+            Timbre::Piano => self.piano(),
+            Timbre::Sine => {
+                let t = self.sample as f32 / SAMPLE_RATE as f32;
+                (2.0 * PI * self.frequency * t).sin() * 0.5
+            }
+        };
+        let value = raw * self.envelope() * self.volume;
+        self.sample += 1;
+        Some(value)
+    }
+}
+
+impl rodio::Source for Voice {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f64(
+            self.end as f64 / SAMPLE_RATE as f64,
+        ))
+    }
+}
+
+/// Plays notes on a single output stream, which is opened the first time
+/// something is played (browsers only allow audio after user interaction).
+#[derive(Default)]
+pub(crate) struct Player {
+    output: Option<(rodio::OutputStream, rodio::OutputStreamHandle)>,
+}
+
+impl Player {
+    pub(crate) fn play(&mut self, frequency: f32, settings: &PlaybackSettings) {
+        self.play_after(Duration::ZERO, frequency, settings);
+    }
+
+    /// Play a note after a delay, e.g. to strum or arpeggiate a chord.
+    pub(crate) fn play_after(
+        &mut self,
+        delay: Duration,
+        frequency: f32,
+        settings: &PlaybackSettings,
+    ) {
+        use rodio::Source;
+
+        if self.output.is_none() {
+            match rodio::OutputStream::try_default() {
+                Ok(output) => self.output = Some(output),
+                Err(e) => {
+                    log::error!("error opening audio output: {}", e);
+                    return;
+                }
+            }
+        }
+
+        if let Some((_, handle)) = &self.output {
+            let voice = Voice::new(frequency, settings).delay(delay);
+            if let Err(e) = handle.play_raw(voice) {
+                log::error!("error playing note: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_voice_length_and_level() {
+        let settings = PlaybackSettings {
+            note_length_ms: 100,
+            release_ms: 50,
+            ..Default::default()
+        };
+        for timbre in Timbre::ALL {
+            let samples: Vec<f32> = Voice::new(
+                220.0,
+                &PlaybackSettings {
+                    timbre,
+                    ..settings.clone()
+                },
+            )
+            .collect();
+            assert_eq!(samples.len(), SAMPLE_RATE as usize * 150 / 1000);
+            assert!(samples.iter().all(|s| s.abs() <= 1.0), "{:?} clips", timbre);
+            assert!(
+                samples.iter().any(|s| s.abs() > 0.01),
+                "{:?} is silent",
+                timbre
+            );
+            // The release should fade all the way out
+            assert!(samples.last().unwrap().abs() < 0.01);
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! Plain text guitar tab, for sharing voicings and progressions in chat and
+//! forums.
+
+use crate::app::feedback_label;
+use crate::files::{file_name, save_feedback};
+use crate::locale::{tr, tr_with};
+use crate::progression::Progression;
+use crate::spelling::Speller;
+use crate::voicing::{find_voicing, Voicing};
+use klib::core::note::Note;
+
+// Chords per line, so tab doesn't wrap in a chat window
+const CHORDS_PER_LINE: usize = 8;
+
+/// A chord in the tab and how many bars it's held for.
+pub(crate) struct TabChord {
+    pub(crate) name: String,
+    pub(crate) voicing: Voicing,
+    pub(crate) bars: u32,
+}
+
+/// Names for the start of each line, padded to the same width. When strings
+/// share a name the higher ones are lower case, like `e` for the high E.
+fn string_labels(tuning: &[Note], speller: &Speller) -> Vec<String> {
+    let names: Vec<String> = tuning
+        .iter()
+        .map(|note| speller.name(*note, true))
+        .collect();
+    let labels: Vec<String> = names
+        .iter()
+        .enumerate()
+        .map(|(string, name)| {
+            // The last string in tuning order is the lowest
+            if names[string + 1..].contains(name) {
+                name.to_lowercase()
+            } else {
+                name.clone()
+            }
+        })
+        .collect();
+    let width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    labels.iter().map(|l| pad(l, width, ' ')).collect()
+}
+
+fn pad(text: &str, width: usize, fill: char) -> String {
+    let mut padded = text.to_owned();
+    for _ in text.chars().count()..width {
+        padded.push(fill);
+    }
+    padded
+}
+
+/// Write chords as tab, with the chord names above, one line per string in
+/// tuning order.
+pub(crate) fn tab(chords: &[TabChord], tuning: &[Note], speller: &Speller, ascii: bool) -> String {
+    let labels = string_labels(tuning, speller);
+    let label_width = labels.first().map_or(0, |l| l.chars().count());
+    let ascii_name = |name: &str| {
+        if ascii {
+            name.replace('♯', "#").replace('♭', "b")
+        } else {
+            name.to_owned()
+        }
+    };
+
+    let mut systems = Vec::new();
+    for line in chords.chunks(CHORDS_PER_LINE) {
+        let mut names = pad("", label_width + 1, ' ');
+        let mut strings: Vec<String> = labels
+            .iter()
+            .map(|l| format!("{}|", ascii_name(l)))
+            .collect();
+        for chord in line {
+            let name = ascii_name(&chord.name);
+            let frets: Vec<String> = (0..tuning.len())
+                .map(|string| match chord.voicing.fret(string) {
+                    Some(fret) => fret.to_string(),
+                    None => "x".to_owned(),
+                })
+                .collect();
+            let width = frets
+                .iter()
+                .map(String::len)
+                .chain([name.chars().count()])
+                .max()
+                .unwrap_or(1);
+            // Held chords get more room, a few dashes per extra bar
+            let hold = 4 * chord.bars.saturating_sub(1) as usize;
+
+            names.push(' ');
+            names.push_str(&pad(&name, width + hold + 2, ' '));
+            for (line, fret) in strings.iter_mut().zip(&frets) {
+                line.push('-');
+                line.push_str(&pad(fret, width + hold, '-'));
+                line.push_str("-|");
+            }
+        }
+        let mut system = vec![names.trim_end().to_owned()];
+        system.extend(strings);
+        systems.push(system.join("\n"));
+    }
+    systems.join("\n\n") + "\n"
+}
+
+/// Tab for a progression, with a voicing found for each chord.
+pub(crate) fn progression_tab(
+    text: &str,
+    tuning: &[Note],
+    speller: &Speller,
+    ascii: bool,
+) -> Result<String, String> {
+    let progression = Progression::parse(text)?;
+    let mut chords = Vec::new();
+    for chord in progression.chords {
+        let voicing = find_voicing(&chord.name, tuning)
+            .ok_or_else(|| tr_with("Couldn't find a voicing of {}", &chord.name))?;
+        chords.push(TabChord {
+            name: chord.name,
+            voicing,
+            bars: chord.bars,
+        });
+    }
+    Ok(tab(&chords, tuning, speller, ascii))
+}
+
+/// What the tab was written from, to know when to write it again.
+#[derive(Clone, PartialEq)]
+struct TabSource {
+    from_progression: bool,
+    progression_text: String,
+    ascii: bool,
+    chord: String,
+    selection: Voicing,
+    tuning: Vec<Note>,
+    speller: Speller,
+}
+
+/// The "Tab" window, which shows tab for the selected voicing or a
+/// progression, ready to copy.
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+#[serde(default)]
+pub(crate) struct TabExport {
+    pub(crate) open: bool,
+    from_progression: bool,
+    progression_text: String,
+    ascii: bool,
+
+    // Finding voicings for a progression is too slow to do every frame
+    #[serde(skip)]
+    written: Option<(TabSource, Result<String, String>)>,
+    #[serde(skip)]
+    message: Option<(bool, String)>,
+}
+
+impl TabExport {
+    pub(crate) fn show(
+        &mut self,
+        ctx: &egui::Context,
+        chord: &str,
+        selection: &Voicing,
+        tuning: &[Note],
+        speller: &Speller,
+    ) {
+        let mut open = self.open;
+        egui::Window::new(tr("Tab"))
+            .id(egui::Id::new("tab"))
+            .open(&mut open)
+            .show(ctx, |ui| self.ui(ui, chord, selection, tuning, speller));
+        self.open = open;
+    }
+
+    fn text(
+        &self,
+        chord: &str,
+        selection: &Voicing,
+        tuning: &[Note],
+        speller: &Speller,
+    ) -> Result<String, String> {
+        if self.from_progression {
+            if self.progression_text.trim().is_empty() {
+                return Err(tr("Type in a progression").to_owned());
+            }
+            return progression_tab(&self.progression_text, tuning, speller, self.ascii);
+        }
+        if selection.is_empty() {
+            return Err(tr("Click notes on the fretboard to build a voicing").to_owned());
+        }
+        let chord = TabChord {
+            name: chord.to_owned(),
+            voicing: selection.clone(),
+            bars: 1,
+        };
+        Ok(tab(&[chord], tuning, speller, self.ascii))
+    }
+
+    fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        chord: &str,
+        selection: &Voicing,
+        tuning: &[Note],
+        speller: &Speller,
+    ) {
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.from_progression, false, tr("Selected voicing"));
+            ui.radio_value(&mut self.from_progression, true, tr("Progression"));
+        });
+        if self.from_progression {
+            ui.text_edit_singleline(&mut self.progression_text)
+                .on_hover_text(tr("Chords with optional bar counts, e.g. Am7:2 D7:2 G:4"));
+        }
+        ui.checkbox(&mut self.ascii, tr("ASCII only"))
+            .on_hover_text(tr(
+                "Write # and b instead of ♯ and ♭, for places that mangle them",
+            ));
+
+        let source = TabSource {
+            from_progression: self.from_progression,
+            progression_text: self.progression_text.clone(),
+            ascii: self.ascii,
+            chord: chord.to_owned(),
+            selection: selection.clone(),
+            tuning: tuning.to_vec(),
+            speller: speller.clone(),
+        };
+        let text = match &self.written {
+            Some((written_from, text)) if *written_from == source => text.clone(),
+            _ => {
+                let text = self.text(chord, selection, tuning, speller);
+                self.written = Some((source, text.clone()));
+                text
+            }
+        };
+        match &text {
+            Ok(text) => {
+                egui::ScrollArea::horizontal().show(ui, |ui| {
+                    ui.label(egui::RichText::new(text).monospace());
+                });
+            }
+            Err(e) => {
+                ui.colored_label(ui.visuals().error_fg_color, e);
+            }
+        }
+
+        ui.add_enabled_ui(text.is_ok(), |ui| {
+            ui.horizontal(|ui| {
+                if ui.button(tr("📋 Copy")).clicked() {
+                    if let Ok(text) = &text {
+                        ui.output_mut(|o| o.copied_text = text.clone());
+                        self.message = Some((true, tr("Copied to the clipboard").to_owned()));
+                    }
+                }
+                if ui.button(tr("💾 Save")).clicked() {
+                    if let Ok(text) = &text {
+                        self.save(chord, text);
+                    }
+                }
+            });
+        });
+
+        feedback_label(ui, &self.message);
+    }
+
+    fn save(&mut self, chord: &str, text: &str) {
+        let name = if self.from_progression {
+            "progression.txt".to_owned()
+        } else {
+            file_name(chord, "txt")
+        };
+        self.message = Some(save_feedback(&name, "text/plain", text.as_bytes()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::standard_tuning;
+
+    #[test]
+    fn test_tab() {
+        let tuning = standard_tuning();
+        let speller = Speller::default();
+        let text = progression_tab("C Am F#:2", &tuning, &speller, false).unwrap();
+        assert_eq!(
+            text,
+            "   C   Am   F♯
+e|-0-|-0--|-2------|
+B|-1-|-1--|-2------|
+G|-0-|-2--|-3------|
+D|-2-|-2--|-4------|
+A|-3-|-0--|-4------|
+E|-x-|-x--|-2------|
+"
+        );
+
+        let ascii = progression_tab("F#", &tuning, &speller, true).unwrap();
+        assert!(ascii.starts_with("   F#\n"));
+    }
+}
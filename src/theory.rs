@@ -0,0 +1,106 @@
+//! Small helpers for turning kord notes into numbers we can do arithmetic on.
+
+use klib::core::base::HasStaticName;
+use klib::core::named_pitch::HasNamedPitch;
+use klib::core::note::Note;
+use klib::core::octave::{HasOctave, Octave};
+use klib::core::pitch::{HasPitch, Pitch};
+
+/// Pitches in order of their pitch class (C = 0).
+pub(crate) const PITCHES: [Pitch; 12] = [
+    Pitch::C,
+    Pitch::DFlat,
+    Pitch::D,
+    Pitch::EFlat,
+    Pitch::E,
+    Pitch::F,
+    Pitch::GFlat,
+    Pitch::G,
+    Pitch::AFlat,
+    Pitch::A,
+    Pitch::BFlat,
+    Pitch::B,
+];
+
+/// Names for each pitch class, spelled the way chord names usually are.
+pub(crate) const PITCH_NAMES: [&str; 12] = [
+    "C", "C♯", "D", "E♭", "E", "F", "F♯", "G", "A♭", "A", "B♭", "B",
+];
+
+const OCTAVES: [Octave; 11] = [
+    Octave::Zero,
+    Octave::One,
+    Octave::Two,
+    Octave::Three,
+    Octave::Four,
+    Octave::Five,
+    Octave::Six,
+    Octave::Seven,
+    Octave::Eight,
+    Octave::Nine,
+    Octave::Ten,
+];
+
+pub(crate) fn pitch_class(pitch: Pitch) -> usize {
+    PITCHES.iter().position(|p| *p == pitch).unwrap_or(0)
+}
+
+pub(crate) fn octave_number(octave: Octave) -> i32 {
+    OCTAVES.iter().position(|o| *o == octave).unwrap_or(4) as i32
+}
+
+/// MIDI note number, where C4 (middle C) is 60. Uses the sounding pitch, so
+/// e.g. B♯3 and C4 are the same number.
+pub(crate) fn midi_number(note: Note) -> i32 {
+    // The octave belongs to the letter name, so B♯3 is a C in octave 3 and
+    // C♭4 is a B in octave 4; fix those up by looking at the letter.
+    let letter = note
+        .named_pitch()
+        .static_name()
+        .chars()
+        .next()
+        .unwrap_or('C');
+    let class = pitch_class(note.pitch()) as i32;
+    let octave = octave_number(note.octave());
+    let octave = match (letter, class) {
+        ('B', 0..=2) => octave + 1,
+        ('C', 9..=11) => octave - 1,
+        _ => octave,
+    };
+    (octave + 1) * 12 + class
+}
+
+/// The note for a MIDI note number, spelled the way kord spells notes it
+/// builds from ids (i.e. with flats).
+pub(crate) fn note_from_midi(midi: i32) -> Note {
+    use klib::core::named_pitch::NamedPitch;
+    use klib::core::note::HasNoteId;
+
+    let c0 = Note::new(NamedPitch::C, Octave::Zero);
+    Note::from_id(c0.id() << (midi - 12).max(0)).unwrap()
+}
+
+/// Frequency in Hz of a MIDI note number, in equal temperament with A4 at
+/// 440Hz.
+pub(crate) fn frequency(midi: i32) -> f32 {
+    440.0 * 2f32.powf((midi - 69) as f32 / 12.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use klib::core::named_pitch::NamedPitch;
+
+    #[test]
+    fn test_midi_number() {
+        assert_eq!(midi_number(Note::new(NamedPitch::C, Octave::Four)), 60);
+        assert_eq!(midi_number(Note::new(NamedPitch::E, Octave::Two)), 40);
+        assert_eq!(
+            midi_number(Note::new(NamedPitch::BSharp, Octave::Three)),
+            60
+        );
+        assert_eq!(midi_number(Note::new(NamedPitch::CFlat, Octave::Four)), 59);
+        assert_eq!(frequency(69), 440.0);
+        assert_eq!(midi_number(note_from_midi(61)), 61);
+    }
+}
@@ -0,0 +1,466 @@
+use crate::analysis::{Analyser, Analysis, Progress, Recording};
+use crate::locale::{tr, tr_fill, tr_with};
+
+/// Width of one second of audio on the timeline, in points, at zoom 1.
+const POINTS_PER_SECOND: f32 = 40.0;
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq)]
+enum Grouping {
+    Beats,
+    Bars,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct Segment {
+    start: f32,
+    end: f32,
+    chord: Option<String>,
+}
+
+/// Keeps the output stream alive while the recording plays.
+struct RecordingPlayback {
+    _stream: rodio::OutputStream,
+    sink: rodio::Sink,
+}
+
+impl RecordingPlayback {
+    fn start(recording: &Recording, from: f32) -> Result<Self, String> {
+        let (stream, handle) = rodio::OutputStream::try_default().map_err(|e| e.to_string())?;
+        let sink = rodio::Sink::try_new(&handle).map_err(|e| e.to_string())?;
+        let first = ((from * recording.sample_rate as f32) as usize).min(recording.samples.len());
+        sink.append(rodio::buffer::SamplesBuffer::new(
+            1,
+            recording.sample_rate,
+            &recording.samples[first..],
+        ));
+        Ok(Self {
+            _stream: stream,
+            sink,
+        })
+    }
+}
+
+/// A decoded recording and its analysis, or why it couldn't be read.
+type Loaded = Result<(Recording, Analysis), String>;
+
+/// What the analysis thread sends back.
+#[cfg(not(target_arch = "wasm32"))]
+enum Update {
+    Progress(Progress),
+    Done(Loaded),
+}
+
+/// A recording being decoded and analysed, on another thread natively, or a
+/// step each frame on the web where there aren't threads.
+struct Loading {
+    name: String,
+    progress: Progress,
+    #[cfg(not(target_arch = "wasm32"))]
+    updates: std::sync::mpsc::Receiver<Update>,
+    #[cfg(target_arch = "wasm32")]
+    analyser: Result<Analyser, String>,
+}
+
+impl Loading {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn start(name: &str, bytes: Vec<u8>) -> Self {
+        let (sender, updates) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let analyse = || {
+                let mut analyser = Analyser::new(bytes)?;
+                loop {
+                    if let Some(done) = analyser.step()? {
+                        return Ok(done);
+                    }
+                    // Stop if another recording was loaded instead
+                    if sender.send(Update::Progress(analyser.progress())).is_err() {
+                        return Err("stopped".to_owned());
+                    }
+                }
+            };
+            let _ = sender.send(Update::Done(analyse()));
+        });
+        Self {
+            name: name.to_owned(),
+            progress: Progress::Decoding,
+            updates,
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn start(name: &str, bytes: Vec<u8>) -> Self {
+        Self {
+            name: name.to_owned(),
+            progress: Progress::Decoding,
+            analyser: Analyser::new(bytes),
+        }
+    }
+
+    /// Catch up on the work, returning the result once it's finished.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn poll(&mut self) -> Option<Loaded> {
+        use std::sync::mpsc::TryRecvError;
+
+        loop {
+            match self.updates.try_recv() {
+                Ok(Update::Progress(progress)) => self.progress = progress,
+                Ok(Update::Done(loaded)) => return Some(loaded),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    return Some(Err(tr("the analysis stopped").to_owned()))
+                }
+            }
+        }
+    }
+
+    /// Do a step of the work, returning the result once it's finished.
+    #[cfg(target_arch = "wasm32")]
+    fn poll(&mut self) -> Option<Loaded> {
+        let analyser = match &mut self.analyser {
+            Ok(analyser) => analyser,
+            Err(e) => return Some(Err(e.clone())),
+        };
+        match analyser.step() {
+            Ok(None) => {
+                self.progress = analyser.progress();
+                None
+            }
+            Ok(Some(done)) => Some(Ok(done)),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// A recording split into beats or bars, each labelled with a chord.
+///
+/// Only the labels are persisted; the audio itself has to be loaded again to
+/// re-analyse or play it.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub(crate) struct Timeline {
+    pub(crate) open: bool,
+    file_name: String,
+    segments: Vec<Segment>,
+    auto_tempo: bool,
+    bpm: f32,
+    // Time of the first downbeat, in seconds
+    offset: f32,
+    grouping: Grouping,
+    beats_per_bar: u32,
+    zoom: f32,
+    position: f32,
+
+    #[serde(skip)]
+    recording: Option<Recording>,
+    #[serde(skip)]
+    analysis: Option<Analysis>,
+    #[serde(skip)]
+    loading: Option<Loading>,
+    #[serde(skip)]
+    playback: Option<RecordingPlayback>,
+    // UI time and position when playback was started
+    #[serde(skip)]
+    playing_since: Option<(f64, f32)>,
+    #[serde(skip)]
+    error: Option<String>,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            open: false,
+            file_name: String::new(),
+            segments: Vec::new(),
+            auto_tempo: true,
+            bpm: 120.0,
+            offset: 0.0,
+            grouping: Grouping::Bars,
+            beats_per_bar: 4,
+            zoom: 1.0,
+            position: 0.0,
+            recording: None,
+            analysis: None,
+            loading: None,
+            playback: None,
+            playing_since: None,
+            error: None,
+        }
+    }
+}
+
+impl Timeline {
+    /// Start loading a recording, which is shown once it's analysed.
+    pub(crate) fn load(&mut self, name: &str, bytes: Vec<u8>) {
+        self.stop();
+        self.error = None;
+        self.loading = Some(Loading::start(name, bytes));
+    }
+
+    /// Take the recording once it's loaded.
+    fn finish_loading(&mut self) {
+        let Some(loading) = &mut self.loading else {
+            return;
+        };
+        let Some(result) = loading.poll() else {
+            return;
+        };
+        let name = loading.name.clone();
+        self.loading = None;
+        match result {
+            Ok((recording, analysis)) => {
+                self.analysis = Some(analysis);
+                self.recording = Some(recording);
+                self.file_name = name;
+                self.position = 0.0;
+                self.analyse();
+            }
+            Err(e) => {
+                log::error!("error decoding {}: {}", name, e);
+                self.error = Some(tr_fill("Could not load {}: {}", &[&name, &e]));
+            }
+        }
+    }
+
+    fn analyse(&mut self) {
+        let Some(analysis) = &self.analysis else {
+            return;
+        };
+
+        if self.auto_tempo {
+            match analysis.tempo() {
+                Some((bpm, offset)) => {
+                    self.bpm = bpm.round();
+                    self.offset = offset;
+                }
+                None => log::warn!("could not estimate tempo, using {} BPM", self.bpm),
+            }
+        }
+
+        let beats = match self.grouping {
+            Grouping::Beats => 1,
+            Grouping::Bars => self.beats_per_bar,
+        };
+        self.segments = analysis
+            .segments(self.bpm, self.offset, beats)
+            .into_iter()
+            .map(|(start, end)| Segment {
+                start,
+                end,
+                chord: analysis.label(start, end),
+            })
+            .collect();
+    }
+
+    fn duration(&self) -> f32 {
+        match &self.recording {
+            Some(recording) => recording.duration(),
+            None => self.segments.last().map_or(0.0, |s| s.end),
+        }
+    }
+
+    fn segment_at(&self, position: f32) -> Option<usize> {
+        self.segments
+            .iter()
+            .position(|s| s.start <= position && position < s.end)
+    }
+
+    fn play(&mut self, now: f64) {
+        if let Some(recording) = &self.recording {
+            match RecordingPlayback::start(recording, self.position) {
+                Ok(playback) => self.playback = Some(playback),
+                Err(e) => log::error!("error playing recording: {}", e),
+            }
+        }
+        self.playing_since = Some((now, self.position));
+    }
+
+    fn stop(&mut self) {
+        if let Some(playback) = self.playback.take() {
+            playback.sink.stop();
+        }
+        self.playing_since = None;
+    }
+
+    /// Show the timeline window. Returns the chord to show on the fretboard
+    /// when the current segment changes.
+    pub(crate) fn show(&mut self, ctx: &egui::Context) -> Option<String> {
+        self.finish_loading();
+        if self.loading.is_some() {
+            ctx.request_repaint();
+        }
+
+        let mut open = self.open;
+        let mut chord = None;
+        egui::Window::new(tr("Recording timeline"))
+            .id(egui::Id::new("recording_timeline"))
+            .open(&mut open)
+            .default_width(600.0)
+            .show(ctx, |ui| chord = self.ui(ui));
+        self.open = open;
+
+        if !self.open {
+            self.stop();
+        }
+        chord
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) -> Option<String> {
+        let now = ui.input(|i| i.time);
+        let previous_segment = self.segment_at(self.position);
+
+        if self.file_name.is_empty() {
+            ui.label(tr(
+                "Drop a WAV, FLAC or Ogg Vorbis recording here to find its chords.",
+            ));
+        } else {
+            ui.label(tr_with("Recording: {}", &self.file_name));
+        }
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        if let Some(loading) = &self.loading {
+            match loading.progress {
+                Progress::Decoding => {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(tr_with("Reading {}", &loading.name));
+                    });
+                }
+                Progress::Analysing(done) => {
+                    let text = tr_with("Finding the chords in {}", &loading.name);
+                    ui.add(egui::ProgressBar::new(done).text(text));
+                }
+            }
+        }
+
+        ui.horizontal(|ui| {
+            let mut changed = ui
+                .checkbox(&mut self.auto_tempo, tr("Detect tempo"))
+                .on_hover_text(tr("Estimate the tempo and first beat from the recording"))
+                .changed();
+            ui.add_enabled_ui(!self.auto_tempo, |ui| {
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut self.bpm)
+                            .clamp_range(30.0..=300.0)
+                            .suffix(tr(" BPM")),
+                    )
+                    .changed();
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut self.offset)
+                            .clamp_range(0.0..=10.0)
+                            .speed(0.01)
+                            .prefix(tr("first beat "))
+                            .suffix("s"),
+                    )
+                    .changed();
+            });
+
+            ui.separator();
+            changed |= ui
+                .radio_value(&mut self.grouping, Grouping::Beats, tr("Beats"))
+                .changed();
+            changed |= ui
+                .radio_value(&mut self.grouping, Grouping::Bars, tr("Bars of"))
+                .changed();
+            changed |= ui
+                .add(egui::DragValue::new(&mut self.beats_per_bar).clamp_range(1..=12))
+                .changed();
+
+            if changed {
+                self.analyse();
+            }
+        });
+
+        let duration = self.duration();
+        if self.segments.is_empty() {
+            return None;
+        }
+
+        ui.horizontal(|ui| {
+            let playing = self.playing_since.is_some();
+            if ui
+                .button(if playing {
+                    tr("⏸ Pause")
+                } else {
+                    tr("▶ Play")
+                })
+                .clicked()
+            {
+                if playing {
+                    self.stop();
+                } else {
+                    self.play(now);
+                }
+            }
+
+            let scrubbed = ui
+                .add(
+                    egui::Slider::new(&mut self.position, 0.0..=duration)
+                        .suffix("s")
+                        .fixed_decimals(1),
+                )
+                .changed();
+            if scrubbed && self.playing_since.is_some() {
+                self.stop();
+                self.play(now);
+            }
+
+            ui.separator();
+            ui.add(
+                egui::Slider::new(&mut self.zoom, 0.25..=4.0)
+                    .logarithmic(true)
+                    .text(tr("zoom")),
+            );
+        });
+
+        if let Some((since, from)) = self.playing_since {
+            self.position = from + (now - since) as f32;
+            if self.position >= duration {
+                self.position = duration;
+                self.stop();
+            }
+            ui.ctx().request_repaint();
+        }
+
+        let current_segment = self.segment_at(self.position);
+        let mut jumped = false;
+        egui::ScrollArea::horizontal().show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.spacing_mut().item_spacing.x = 1.0;
+                for (i, segment) in self.segments.iter().enumerate() {
+                    let width = (segment.end - segment.start) * POINTS_PER_SECOND * self.zoom;
+                    let label = segment.chord.as_deref().unwrap_or("–");
+                    let selected = current_segment == Some(i);
+                    let response = ui
+                        .add_sized(
+                            [width.max(8.0), 40.0],
+                            egui::SelectableLabel::new(selected, label),
+                        )
+                        .on_hover_text(format!("{:.1}s – {:.1}s", segment.start, segment.end));
+
+                    if response.clicked() {
+                        self.position = segment.start;
+                        jumped = true;
+                    }
+                    if selected && previous_segment != current_segment {
+                        response.scroll_to_me(Some(egui::Align::Center));
+                    }
+                }
+            });
+        });
+
+        if jumped && self.playing_since.is_some() {
+            self.stop();
+            self.play(now);
+        }
+
+        // Clicking a segment moves the position, so look again
+        let current_segment = self.segment_at(self.position);
+        if current_segment != previous_segment {
+            return current_segment.and_then(|i| self.segments[i].chord.clone());
+        }
+        None
+    }
+}
@@ -0,0 +1,356 @@
+use crate::app::{note_for_fret, MAX_FRET};
+use crate::theory::{pitch_class, PITCHES};
+use klib::core::base::Parsable;
+use klib::core::chord::{Chord, HasChord, HasRoot};
+use klib::core::note::Note;
+use klib::core::pitch::{HasPitch, Pitch};
+
+/// A chord voicing on the fretboard: a fret for each string (in tuning
+/// order), or `None` if the string isn't played.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default, PartialEq)]
+pub(crate) struct Voicing {
+    pub(crate) frets: Vec<Option<usize>>,
+}
+
+impl Voicing {
+    pub(crate) fn fret(&self, string: usize) -> Option<usize> {
+        self.frets.get(string).copied().flatten()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.frets.iter().all(Option::is_none)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.frets.clear();
+    }
+
+    /// Select a fret on a string, replacing whatever was selected on that
+    /// string, or unselect it if it was already selected.
+    pub(crate) fn toggle(&mut self, string: usize, fret: usize) {
+        if self.frets.len() <= string {
+            self.frets.resize(string + 1, None);
+        }
+        self.frets[string] = match self.frets[string] {
+            Some(selected) if selected == fret => None,
+            _ => Some(fret),
+        };
+    }
+
+    /// The played notes with the strings they're on.
+    pub(crate) fn notes(&self, tuning: &[Note]) -> Vec<(usize, Note)> {
+        (0..tuning.len())
+            .filter_map(|string| {
+                self.fret(string)
+                    .map(|fret| (string, note_for_fret(tuning[string], fret)))
+            })
+            .collect()
+    }
+}
+
+/// Pitch classes of a chord's tones, the ones that may be left out, and the
+/// pitch class that belongs in the bass.
+struct ChordTones {
+    tones: Vec<usize>,
+    optional: Vec<usize>,
+    bass: usize,
+}
+
+fn chord_tones(chord: &str) -> Result<ChordTones, String> {
+    let chord = Chord::parse(chord).map_err(|e| e.to_string())?;
+    let notes = chord.chord();
+    let mut tones: Vec<usize> = Vec::new();
+    for note in &notes {
+        let class = pitch_class(note.pitch());
+        if !tones.contains(&class) {
+            tones.push(class);
+        }
+    }
+
+    // The fifth is usually left out of bigger chords on guitar
+    let fifth = (pitch_class(chord.root().pitch()) + 7) % 12;
+    let optional = if tones.len() >= 4 && tones.contains(&fifth) {
+        vec![fifth]
+    } else {
+        Vec::new()
+    };
+    // Slash chords list the bass note first
+    let bass = notes.first().map_or(0, |n| pitch_class(n.pitch()));
+
+    Ok(ChordTones {
+        tones,
+        optional,
+        bass,
+    })
+}
+
+/// Which finger frets each string (1 for the index finger), in tuning order.
+/// Fingers follow the frets up from the lowest fretted note, and if that
+/// needs more than four fingers the lowest fret is barred.
+pub(crate) fn fingers(voicing: &Voicing, tuning: &[Note]) -> Vec<Option<u8>> {
+    // Lowest string first within each fret, the way it's usually fingered
+    let mut fretted: Vec<(usize, usize)> = (0..tuning.len())
+        .rev()
+        .filter_map(|string| voicing.fret(string).map(|fret| (string, fret)))
+        .filter(|(_, fret)| *fret > 0)
+        .collect();
+    fretted.sort_by_key(|(_, fret)| *fret);
+
+    let mut fingers = vec![None; tuning.len()];
+    let Some(lowest) = fretted.first().map(|(_, fret)| *fret) else {
+        return fingers;
+    };
+    let at_lowest = fretted.iter().filter(|(_, f)| *f == lowest).count();
+    let barre = fretted.len() > 4 && at_lowest > 1;
+
+    let mut next = 1;
+    for (string, fret) in fretted {
+        if barre && fret == lowest {
+            fingers[string] = Some(1);
+            next = 2;
+            continue;
+        }
+        let finger = next.max(1 + fret - lowest);
+        fingers[string] = Some(finger.min(4) as u8);
+        next = finger + 1;
+    }
+    fingers
+}
+
+/// The number of fingers a voicing needs, counting a barre as one, or `None`
+/// if it can't be played because an open string is under the barre.
+fn fingers_needed(voicing: &Voicing) -> Option<usize> {
+    let fretted: Vec<usize> = voicing
+        .frets
+        .iter()
+        .flatten()
+        .copied()
+        .filter(|f| *f > 0)
+        .collect();
+    let Some(lowest) = fretted.iter().copied().min() else {
+        return Some(0);
+    };
+    if fretted.len() <= 4 {
+        return Some(fretted.len());
+    }
+
+    let barred: Vec<usize> = (0..voicing.frets.len())
+        .filter(|s| voicing.fret(*s) == Some(lowest))
+        .collect();
+    let (first, last) = (barred[0], barred[barred.len() - 1]);
+    if (first..=last).any(|s| voicing.fret(s) == Some(0)) {
+        return None;
+    }
+    Some(fretted.len() + 1 - barred.len())
+}
+
+// How far the hand can stretch, in frets
+const SPAN: usize = 3;
+
+/// Find a playable voicing of a chord: all its tones within a four fret
+/// span, the right note in the bass, as many strings as possible and as low
+/// on the neck as possible.
+pub(crate) fn find_voicing(chord: &str, tuning: &[Note]) -> Option<Voicing> {
+    let chord = chord_tones(chord).ok()?;
+
+    let mut best: Option<(f32, Voicing)> = None;
+    for position in 0..MAX_FRET - SPAN {
+        // Open strings only make sense near the nut
+        let lowest = if position == 0 { 0 } else { position };
+        let options: Vec<Vec<Option<usize>>> = tuning
+            .iter()
+            .map(|open| {
+                let mut options = vec![None];
+                options.extend(
+                    (lowest..=position + SPAN)
+                        .filter(|fret| {
+                            chord
+                                .tones
+                                .contains(&pitch_class(note_for_fret(*open, *fret).pitch()))
+                        })
+                        .map(Some),
+                );
+                options
+            })
+            .collect();
+
+        let mut frets = vec![None; tuning.len()];
+        search(&options, 0, &mut frets, &mut |frets| {
+            let voicing = Voicing {
+                frets: frets.to_vec(),
+            };
+            if let Some(score) = score(&voicing, tuning, &chord, position) {
+                if best.as_ref().map_or(true, |(best, _)| score > *best) {
+                    best = Some((score, voicing));
+                }
+            }
+        });
+    }
+    best.map(|(_, voicing)| voicing)
+}
+
+fn search(
+    options: &[Vec<Option<usize>>],
+    string: usize,
+    frets: &mut Vec<Option<usize>>,
+    visit: &mut dyn FnMut(&[Option<usize>]),
+) {
+    if string == options.len() {
+        visit(frets);
+        return;
+    }
+    for option in &options[string] {
+        frets[string] = *option;
+        search(options, string + 1, frets, visit);
+    }
+}
+
+/// How good a voicing is, or `None` if it isn't the chord or can't be
+/// played.
+fn score(voicing: &Voicing, tuning: &[Note], chord: &ChordTones, position: usize) -> Option<f32> {
+    let played = voicing.notes(tuning);
+    if played.len() < 3 || !fingers_needed(voicing).is_some_and(|f| f <= 4) {
+        return None;
+    }
+
+    let classes: Vec<usize> = played.iter().map(|(_, n)| pitch_class(n.pitch())).collect();
+    let complete = chord
+        .tones
+        .iter()
+        .all(|t| chord.optional.contains(t) || classes.contains(t));
+    // The last string in tuning order is the lowest
+    let bass = classes.last().copied();
+    if !complete || bass != Some(chord.bass) {
+        return None;
+    }
+
+    // Muted strings in the middle of a chord are hard to play cleanly
+    let first = played.first().map_or(0, |(s, _)| *s);
+    let last = played.last().map_or(0, |(s, _)| *s);
+    let inner_mutes = (first..=last)
+        .filter(|s| voicing.fret(*s).is_none())
+        .count();
+    let total_frets: usize = voicing.frets.iter().flatten().sum();
+
+    Some(
+        10.0 * played.len() as f32
+            - 15.0 * inner_mutes as f32
+            - 3.0 * position as f32
+            - 0.5 * total_frets as f32,
+    )
+}
+
+/// What's wrong with a voicing of a chord, if anything.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct VoicingCheck {
+    /// Strings playing notes that aren't in the chord
+    pub(crate) wrong: Vec<usize>,
+    /// Chord tones that aren't played anywhere
+    pub(crate) missing: Vec<Pitch>,
+}
+
+impl VoicingCheck {
+    pub(crate) fn is_correct(&self) -> bool {
+        self.wrong.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// Check a voicing against a chord, in any inversion. The fifth may be left
+/// out of chords with four or more notes, as it usually is on guitar.
+pub(crate) fn check_voicing(
+    chord: &str,
+    voicing: &Voicing,
+    tuning: &[Note],
+) -> Result<VoicingCheck, String> {
+    let ChordTones {
+        tones, optional, ..
+    } = chord_tones(chord)?;
+
+    let played = voicing.notes(tuning);
+    let played_classes: Vec<usize> = played.iter().map(|(_, n)| pitch_class(n.pitch())).collect();
+
+    let wrong = played
+        .iter()
+        .zip(&played_classes)
+        .filter(|(_, class)| !tones.contains(class))
+        .map(|((string, _), _)| *string)
+        .collect();
+    let mut missing = Vec::new();
+    for class in &tones {
+        if !optional.contains(class) && !played_classes.contains(class) {
+            missing.push(PITCHES[*class]);
+        }
+    }
+
+    Ok(VoicingCheck { wrong, missing })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::standard_tuning;
+
+    fn voicing(frets: [Option<usize>; 6]) -> Voicing {
+        // Written low E first like a chord chart, the tuning is high E first
+        let mut frets = frets.to_vec();
+        frets.reverse();
+        Voicing { frets }
+    }
+
+    #[test]
+    fn test_check_voicing() {
+        let tuning = standard_tuning();
+
+        // Open C, and the same with a G in the bass
+        let c = voicing([None, Some(3), Some(2), Some(0), Some(1), Some(0)]);
+        assert!(check_voicing("C", &c, &tuning).unwrap().is_correct());
+        let c_g = voicing([Some(3), Some(3), Some(2), Some(0), Some(1), Some(0)]);
+        assert!(check_voicing("C", &c_g, &tuning).unwrap().is_correct());
+
+        // Against Am the G is wrong and the A is missing
+        let check = check_voicing("Am", &c, &tuning).unwrap();
+        assert_eq!(check.wrong, [2]);
+        assert_eq!(check.missing, [Pitch::A]);
+        let check = check_voicing("Cm", &c, &tuning).unwrap();
+        assert_eq!(check.wrong, [0, 3]);
+        assert_eq!(check.missing, [Pitch::EFlat]);
+
+        // Shell voicing without a fifth
+        let g7 = voicing([Some(3), None, Some(3), Some(4), None, None]);
+        assert!(check_voicing("G7", &g7, &tuning).unwrap().is_correct());
+    }
+
+    #[test]
+    fn test_find_voicing() {
+        let tuning = standard_tuning();
+        let find = |chord| {
+            let mut frets = find_voicing(chord, &tuning).unwrap().frets;
+            frets.reverse();
+            frets
+        };
+        assert_eq!(
+            find("C"),
+            [None, Some(3), Some(2), Some(0), Some(1), Some(0)]
+        );
+        assert_eq!(
+            find("Am"),
+            [None, Some(0), Some(2), Some(2), Some(1), Some(0)]
+        );
+
+        let f = find("F");
+        assert_eq!(f, [Some(1), Some(3), Some(3), Some(2), Some(1), Some(1)]);
+        let mut fingers = fingers(&voicing(f.try_into().unwrap()), &tuning);
+        fingers.reverse();
+        assert_eq!(fingers, [1, 3, 4, 2, 1, 1].map(Some));
+    }
+
+    #[test]
+    fn test_toggle() {
+        let mut voicing = Voicing::default();
+        voicing.toggle(2, 5);
+        voicing.toggle(2, 7);
+        assert_eq!(voicing.frets, [None, None, Some(7)]);
+        voicing.toggle(2, 7);
+        assert!(voicing.is_empty());
+    }
+}